@@ -0,0 +1,233 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use vcf::annotate::{
+    AnnotationSource, Annotator, Column, Existing, Matching, Options, TableSource, VcfSource,
+};
+use vcf::edit::{Edits, Field};
+use vcf::tabix::{Index, IndexedReader};
+use vcf::vcf::{Reader, VCFError, VCF};
+use vcf::Header;
+
+use crate::io::{open_input, open_output};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum MatchBy {
+    /// Same position, REF and an ALT allele
+    Alleles,
+    /// Same position
+    Position,
+    /// Overlapping reference positions
+    Overlap,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ExistingValues {
+    /// Replace existing values where the source has one
+    Overwrite,
+    /// Only add values that are missing
+    Keep,
+    /// Replace existing values, removing those the source does not have
+    Clear,
+}
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Sorted VCF, BED or tab-separated file of annotations, optionally bgzipped; a bgzipped
+    /// file with a `.tbi` or `.csi` index next to it is only read around the input's records
+    #[arg(short, long, value_name = "FILE")]
+    annotations: Option<PathBuf>,
+    /// Comma-separated columns: for a VCF, ID and the INFO keys to copy; for a BED or
+    /// tab-separated file, every column, from CHROM, POS, FROM, TO, REF, ALT, ID, `-` to skip
+    /// a column and INFO keys
+    #[arg(short, long, value_name = "LIST")]
+    columns: Option<String>,
+    /// How records are matched to annotations [default: overlap for regions, otherwise
+    /// alleles]
+    #[arg(long = "match", value_enum)]
+    matching: Option<MatchBy>,
+    /// What to do with values records already have
+    #[arg(long, value_enum, default_value = "overwrite")]
+    existing: ExistingValues,
+    /// Comma-separated fields to remove before annotating, such as INFO/CSQ, FORMAT/PL or
+    /// FILTER/LowQual
+    #[arg(short = 'x', long, value_name = "LIST")]
+    remove: Option<String>,
+    /// Comma-separated fields to rename before annotating, such as INFO/AF=gnomAD_AF
+    #[arg(long, value_name = "LIST")]
+    rename: Option<String>,
+    /// File of header lines to add or replace, such as definitions of the copied keys; their
+    /// Number=A and Number=R definitions also match the values of a table to alleles
+    #[arg(long, value_name = "FILE")]
+    header_lines: Option<PathBuf>,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let mut edits = Edits::default();
+    for name in args.remove.iter().flat_map(|list| list.split(',')) {
+        edits.remove(Field::parse(name)?);
+    }
+    for rename in args.rename.iter().flat_map(|list| list.split(',')) {
+        let (field, name) = rename.split_once('=').ok_or_else(|| {
+            VCFError::InvalidExpression(format!("{:?} is not FIELD=NAME", rename))
+        })?;
+        edits.rename(Field::parse(field)?, name);
+    }
+
+    let mut reader = Reader::new(open_input(&args.input)?)?;
+    let mut header = reader.header().clone();
+    edits.apply_header(&mut header);
+    let header_lines = match &args.header_lines {
+        Some(path) => read_header_lines(path)?,
+        None => Vec::new(),
+    };
+    add_header_lines(&mut header, &header_lines)?;
+    let mut annotator = match &args.annotations {
+        Some(path) => Some(annotator(path, &args, &header)?),
+        None => None,
+    };
+    if let Some(annotator) = &annotator {
+        // The given header lines replace the source's definitions.
+        annotator.add_definitions(&mut header);
+        add_header_lines(&mut header, &header_lines)?;
+    }
+    let mut output = open_output(&args.output)?;
+    write!(output, "{}", header)?;
+    for record in reader.records() {
+        let mut record = record?;
+        edits.apply(&mut record);
+        if let Some(annotator) = &mut annotator {
+            annotator.annotate(&mut record)?;
+        }
+        writeln!(output, "{}", record)?;
+    }
+    output.finish()?;
+    Ok(())
+}
+
+fn annotator(
+    path: &Path,
+    args: &Args,
+    header: &VCF,
+) -> Result<Annotator<Box<dyn AnnotationSource>>, VCFError> {
+    let columns = args.columns.as_deref().ok_or_else(|| {
+        VCFError::InvalidExpression("--columns is needed with --annotations".to_string())
+    })?;
+    let columns = Column::parse_list(columns);
+    let name = path.to_string_lossy();
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    let indexed = indexed(path)?;
+    let (source, options): (Box<dyn AnnotationSource>, _) = if name.ends_with(".vcf") {
+        let mut options = options(args, Matching::Alleles);
+        for column in columns {
+            match column {
+                Column::Id => options.id = true,
+                Column::Info(key) => options.keys.push(key),
+                column => {
+                    return Err(VCFError::InvalidExpression(format!(
+                        "cannot copy {:?} from a VCF",
+                        column
+                    )))
+                }
+            }
+        }
+        let source: Box<dyn AnnotationSource> = match indexed {
+            Some(input) => Box::new(VcfSource::new(Reader::new(input)?).indexed()),
+            None => Box::new(VcfSource::new(Reader::new(open_input(path)?)?)),
+        };
+        (source, options)
+    } else {
+        let regions = !columns.contains(&Column::Pos);
+        let mut options = options(
+            args,
+            if regions {
+                Matching::Overlap
+            } else {
+                Matching::Alleles
+            },
+        );
+        options.id = columns.contains(&Column::Id);
+        for column in &columns {
+            if let Column::Info(key) = column {
+                options.keys.push(key.clone());
+            }
+        }
+        let bed = name.ends_with(".bed");
+        let source: Box<dyn AnnotationSource> = match indexed {
+            Some(input) => Box::new(table(input, columns, bed)?.indexed()),
+            None => Box::new(table(open_input(path)?, columns, bed)?),
+        };
+        (source, options)
+    };
+    Ok(Annotator::new(source, header, options))
+}
+
+/// The bgzipped file at `path`, read with the `.tbi` or `.csi` index next to it, if it has one.
+fn indexed(path: &Path) -> Result<Option<IndexedReader<BufReader<File>>>, VCFError> {
+    for extension in [".tbi", ".csi"] {
+        let mut index = path.as_os_str().to_owned();
+        index.push(extension);
+        if Path::new(&index).is_file() {
+            let index = Index::read(File::open(&index)?)?;
+            let input = BufReader::new(File::open(path)?);
+            return Ok(Some(IndexedReader::new(input, index)));
+        }
+    }
+    Ok(None)
+}
+
+fn table<R: BufRead>(
+    input: R,
+    columns: Vec<Column>,
+    bed: bool,
+) -> Result<TableSource<R>, VCFError> {
+    match bed {
+        true => TableSource::bed(input, columns),
+        false => TableSource::new(input, columns),
+    }
+}
+
+fn options(args: &Args, matching: Matching) -> Options {
+    Options {
+        keys: Vec::new(),
+        id: false,
+        matching: match args.matching {
+            Some(MatchBy::Alleles) => Matching::Alleles,
+            Some(MatchBy::Position) => Matching::Position,
+            Some(MatchBy::Overlap) => Matching::Overlap,
+            None => matching,
+        },
+        existing: match args.existing {
+            ExistingValues::Overwrite => Existing::Overwrite,
+            ExistingValues::Keep => Existing::Keep,
+            ExistingValues::Clear => Existing::Clear,
+        },
+    }
+}
+
+fn read_header_lines(path: &Path) -> Result<Vec<String>, VCFError> {
+    let mut lines = Vec::new();
+    for line in open_input(path)?.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+fn add_header_lines(header: &mut VCF, lines: &[String]) -> Result<(), VCFError> {
+    for line in lines {
+        let parsed = Header::parse(line)
+            .map_err(|_| VCFError::InvalidHeader(format!("cannot parse header line {}", line)))?;
+        header.set_header(&parsed);
+    }
+    Ok(())
+}
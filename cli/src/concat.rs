@@ -0,0 +1,48 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use vcf::concat::{concat, concat_blocks};
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{create, open_input, open_output};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Copy the compressed blocks of BGZF inputs without decompressing the records; the output
+    /// is BGZF-compressed
+    #[arg(short, long)]
+    naive: bool,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCFs with the same samples, in order
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    if args.naive {
+        let inputs = args
+            .inputs
+            .iter()
+            .map(|path| -> io::Result<Box<dyn Read>> {
+                if path == &PathBuf::from("-") {
+                    Ok(Box::new(io::stdin()))
+                } else {
+                    Ok(Box::new(File::open(path)?))
+                }
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        concat_blocks(inputs, create(&args.output)?)?.flush()?;
+        return Ok(());
+    }
+    let readers = args
+        .inputs
+        .iter()
+        .map(|path| Reader::new(open_input(path)?))
+        .collect::<Result<Vec<_>, VCFError>>()?;
+    let mut output = open_output(&args.output)?;
+    concat(readers, &mut output)?;
+    Ok(output.finish()?)
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use vcf::fill_tags::{read_groups, FillTags, Tag};
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{open_input, open_output};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Comma-separated tags to fill: AC, AN, AF, MAF, GTC (genotype counts), ExcHet, HWE
+    #[arg(
+        short,
+        long,
+        value_name = "LIST",
+        default_value = "AC,AN,AF,MAF,GTC,ExcHet,HWE"
+    )]
+    tags: String,
+    /// File of `sample<TAB>group` lines; tags are also filled for each group, with the group
+    /// name as a suffix
+    #[arg(short = 'g', long, value_name = "FILE")]
+    groups: Option<PathBuf>,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let tags = args
+        .tags
+        .split(',')
+        .map(|name| {
+            Tag::parse(name)
+                .ok_or_else(|| VCFError::InvalidExpression(format!("unknown tag {}", name)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let groups = match &args.groups {
+        Some(path) => read_groups(open_input(path)?)?,
+        None => HashMap::new(),
+    };
+    let mut reader = Reader::new(open_input(&args.input)?)?;
+    let fill = FillTags::new(reader.header(), &tags, &groups)?;
+    let mut header = reader.header().clone();
+    fill.add_definitions(&mut header);
+    let mut output = open_output(&args.output)?;
+    write!(output, "{}", header)?;
+    for record in reader.records() {
+        let mut record = record?;
+        fill.fill(&mut record);
+        writeln!(output, "{}", record)?;
+    }
+    output.finish()?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use vcf::filter::{
+    add_filter_definition, add_sample_filter_definition, soft_filter, soft_filter_samples, Filter,
+};
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{open_input, open_output};
+
+#[derive(clap::Args)]
+#[command(group(clap::ArgGroup::new("expression").required(true).args(["include", "exclude"])))]
+pub struct Args {
+    /// Keep the records for which the expression holds
+    #[arg(short, long)]
+    include: Option<String>,
+    /// Remove the records for which the expression holds
+    #[arg(short, long)]
+    exclude: Option<String>,
+    /// Keep all records, adding this ID to the FILTER column of those that fail
+    #[arg(short, long, value_name = "NAME")]
+    soft_filter: Option<String>,
+    /// Set the soft filter in the FT field of the samples that fail, leaving FILTER unchanged
+    #[arg(long, requires = "soft_filter")]
+    per_sample: bool,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let mut reader = Reader::new(open_input(&args.input)?)?;
+    let (expression, keep) = match (&args.include, &args.exclude) {
+        (Some(expression), _) => (expression, true),
+        (_, Some(expression)) => (expression, false),
+        _ => unreachable!("clap requires one of --include and --exclude"),
+    };
+    let filter = Filter::parse(expression, &reader.header().definitions())?;
+
+    let mut header = reader.header().clone();
+    if let Some(id) = &args.soft_filter {
+        let description = match keep {
+            true => format!("Set if not true: {}", expression),
+            false => format!("Set if true: {}", expression),
+        };
+        add_filter_definition(&mut header, id, &description)?;
+        if args.per_sample {
+            add_sample_filter_definition(&mut header);
+        }
+    }
+
+    let mut output = open_output(&args.output)?;
+    write!(output, "{}", header)?;
+    for record in reader.records() {
+        let mut record = record?;
+        match &args.soft_filter {
+            Some(id) if args.per_sample => {
+                let failed: Vec<bool> = filter
+                    .matches_samples(&record)
+                    .into_iter()
+                    .map(|matches| matches != keep)
+                    .collect();
+                soft_filter_samples(&mut record, id, &failed);
+            }
+            Some(id) => {
+                let failed = filter.matches(&record) != keep;
+                soft_filter(&mut record, id, failed);
+            }
+            None if filter.matches(&record) != keep => continue,
+            None => {}
+        }
+        writeln!(output, "{}", record)?;
+    }
+    output.finish()?;
+    Ok(())
+}
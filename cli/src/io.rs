@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+use vcf::bgzf;
+
+/// Open a file for reading, or standard input for `-`, decompressing gzip and BGZF input.
+pub fn open_input(path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    open_input_with_threads(path, 1)
+}
+
+/// Open a file like [`open_input`], decompressing BGZF input on `threads` threads when there is
+/// more than one.
+pub fn open_input_with_threads(path: &Path, threads: usize) -> io::Result<Box<dyn BufRead + Send>> {
+    let mut input: Box<dyn BufRead + Send> = if path == Path::new("-") {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    let start = input.fill_buf()?;
+    if bgzf::is_bgzf(start) && threads > 1 {
+        Ok(Box::new(bgzf::ParallelReader::new(input, threads)))
+    } else if bgzf::is_bgzf(start) {
+        Ok(Box::new(bgzf::Reader::new(input)))
+    } else if bgzf::is_gzip(start) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(input))))
+    } else {
+        Ok(input)
+    }
+}
+
+/// Create a file for writing, or standard output for `-`.
+pub fn create(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    if path == Path::new("-") {
+        Ok(Box::new(BufWriter::new(io::stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// Create a file for writing, or standard output for `-`, compressed with BGZF when its name
+/// ends in `.gz` or `.bgz`. [`Output::finish`] must be called once everything is written.
+pub fn open_output(path: &Path) -> io::Result<Output> {
+    let output = create(path)?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gz" | "bgz") => Ok(Output::Bgzf(bgzf::Writer::new(output))),
+        _ => Ok(Output::Plain(output)),
+    }
+}
+
+/// An output file, compressed or not.
+pub enum Output {
+    Plain(Box<dyn Write + Send>),
+    Bgzf(bgzf::Writer<Box<dyn Write + Send>>),
+}
+
+impl Output {
+    /// Write out everything still buffered and, for BGZF, the last block and the end-of-file
+    /// marker, reporting any error.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut output) => output.flush(),
+            Output::Bgzf(writer) => writer.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(output) => output.write(buf),
+            Output::Bgzf(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(output) => output.flush(),
+            Output::Bgzf(writer) => writer.flush(),
+        }
+    }
+}
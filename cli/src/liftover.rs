@@ -0,0 +1,79 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use vcf::fasta::IndexedFasta;
+use vcf::filter::soft_filter;
+use vcf::liftover::{add_reason_definitions, Chain, Lift, Liftover};
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{open_input, open_output};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// UCSC chain file from the assembly of the input to the new one, optionally gzipped
+    #[arg(short, long, value_name = "FILE")]
+    chain: PathBuf,
+    /// FASTA of the new assembly, to check REF and swap it with a matching ALT allele
+    #[arg(short = 'f', long)]
+    fasta_ref: Option<PathBuf>,
+    /// VCF to write records that could not be lifted over, with the reason in FILTER
+    #[arg(long, value_name = "FILE")]
+    reject: Option<PathBuf>,
+    /// Output file; records are not sorted
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let chain = Chain::parse(open_input(&args.chain)?)?;
+    let mut liftover = match &args.fasta_ref {
+        Some(path) => Liftover::with_reference(chain, IndexedFasta::open(path)?),
+        None => Liftover::new(chain),
+    };
+    let mut reader = Reader::new(open_input(&args.input)?)?;
+    let mut reject = match &args.reject {
+        Some(path) => {
+            let mut header = reader.header().clone();
+            add_reason_definitions(&mut header);
+            let mut output = open_output(path)?;
+            write!(output, "{}", header)?;
+            Some(output)
+        }
+        None => None,
+    };
+    let mut header = reader.header().clone();
+    liftover.update_header(&mut header);
+    let mut output = open_output(&args.output)?;
+    write!(output, "{}", header)?;
+    let (mut total, mut swapped, mut rejected) = (0, 0, 0);
+    for record in reader.records() {
+        let mut record = record?;
+        total += 1;
+        match liftover.lift(&mut record)? {
+            Lift::Mapped => writeln!(output, "{}", record)?,
+            Lift::Swapped => {
+                swapped += 1;
+                writeln!(output, "{}", record)?;
+            }
+            Lift::Rejected(reason) => {
+                rejected += 1;
+                if let Some(reject) = &mut reject {
+                    soft_filter(&mut record, reason.id(), true);
+                    writeln!(reject, "{}", record)?;
+                }
+            }
+        }
+    }
+    output.finish()?;
+    if let Some(reject) = reject {
+        reject.finish()?;
+    }
+    eprintln!(
+        "Lines total/swapped/rejected: {}/{}/{}",
+        total, swapped, rejected
+    );
+    Ok(())
+}
@@ -1,3 +1,64 @@
-fn main() {
-    todo!()
+use clap::{Parser, Subcommand};
+use vcf::vcf::VCFError;
+
+mod annotate;
+mod concat;
+mod fill_tags;
+mod filter;
+mod io;
+mod liftover;
+mod merge;
+mod norm;
+mod query;
+mod sort;
+mod stats;
+mod view;
+
+#[derive(Parser)]
+#[command(name = "vcf", about = "Tools for working with VCF files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Copy IDs and INFO values from a file of annotations, and remove or rename fields
+    Annotate(annotate::Args),
+    /// Left-align and trim variants against a reference sequence
+    Norm(norm::Args),
+    /// Concatenate VCFs with the same samples that cover consecutive regions
+    Concat(concat::Args),
+    /// Select records with a filter expression
+    Filter(filter::Args),
+    /// Compute allele frequencies, genotype counts and HWE statistics from genotypes
+    FillTags(fill_tags::Args),
+    /// Move records to another assembly with a UCSC chain file
+    Liftover(liftover::Args),
+    /// Sort records by contig and position
+    Sort(sort::Args),
+    /// Merge sorted VCFs with different samples into one multi-sample VCF
+    Merge(merge::Args),
+    /// Extract fields from records as text, one line per record
+    Query(query::Args),
+    /// Summarize variant types, genotypes and quality of the records
+    Stats(stats::Args),
+    /// Convert records to VCF, JSON or newline-delimited JSON
+    View(view::Args),
+}
+
+fn main() -> Result<(), VCFError> {
+    match Cli::parse().command {
+        Command::Annotate(args) => annotate::run(args),
+        Command::Norm(args) => norm::run(args),
+        Command::Concat(args) => concat::run(args),
+        Command::Filter(args) => filter::run(args),
+        Command::FillTags(args) => fill_tags::run(args),
+        Command::Liftover(args) => liftover::run(args),
+        Command::Sort(args) => sort::run(args),
+        Command::Merge(args) => merge::run(args),
+        Command::Query(args) => query::run(args),
+        Command::Stats(args) => stats::run(args),
+        Command::View(args) => view::run(args),
+    }
 }
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use vcf::merge::{merge, Multiallelic};
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{open_input, open_output};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Which records may be merged into multiallelic records: none, snps, indels, both or all
+    #[arg(short = 'm', long, default_value = "both", value_parser = parse_multiallelic)]
+    merge: Multiallelic,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Sorted input VCFs with distinct samples
+    #[arg(required = true, num_args = 2..)]
+    inputs: Vec<PathBuf>,
+}
+
+fn parse_multiallelic(value: &str) -> Result<Multiallelic, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected none, snps, indels, both or all, not {}", value))
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let readers = args
+        .inputs
+        .iter()
+        .map(|path| Reader::new(open_input(path)?))
+        .collect::<Result<Vec<_>, VCFError>>()?;
+    let mut output = open_output(&args.output)?;
+    merge(readers, args.merge, &mut output)?;
+    Ok(output.finish()?)
+}
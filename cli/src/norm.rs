@@ -0,0 +1,40 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use vcf::fasta::IndexedFasta;
+use vcf::normalize::normalize;
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{open_input, open_output};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Reference FASTA; an accompanying `.fai` index is used when present
+    #[arg(short = 'f', long)]
+    fasta_ref: PathBuf,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let mut fasta = IndexedFasta::open(&args.fasta_ref)?;
+    let mut reader = Reader::new(open_input(&args.input)?)?;
+    let mut output = open_output(&args.output)?;
+    write!(output, "{}", reader.header())?;
+    let (mut total, mut changed) = (0, 0);
+    for record in reader.records() {
+        let mut record = record?;
+        total += 1;
+        if normalize(&mut record, &mut fasta)? {
+            changed += 1;
+        }
+        writeln!(output, "{}", record)?;
+    }
+    output.finish()?;
+    eprintln!("Lines total/modified: {}/{}", total, changed);
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+use vcf::query::query;
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{open_input, open_output};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// What to write for each record, e.g. '%CHROM\t%POS\t%INFO/AF[\t%SAMPLE=%GT]\n'
+    #[arg(short, long)]
+    format: String,
+    /// Start with a line naming the fields
+    #[arg(short = 'H', long)]
+    print_header: bool,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let reader = Reader::new(open_input(&args.input)?)?;
+    let mut output = open_output(&args.output)?;
+    query(reader, &args.format, args.print_header, &mut output)?;
+    Ok(output.finish()?)
+}
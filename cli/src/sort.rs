@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use vcf::sort::{sort, DEFAULT_MAX_MEMORY};
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{open_input, open_output};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Memory to use before writing sorted runs to temporary files, e.g. 500M or 2G
+    #[arg(short, long, value_parser = parse_memory, default_value_t = DEFAULT_MAX_MEMORY)]
+    max_mem: usize,
+    /// Directory for temporary files
+    #[arg(short = 'T', long)]
+    temp_dir: Option<PathBuf>,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+/// Parse a number of bytes with an optional k, M or G suffix.
+fn parse_memory(value: &str) -> Result<usize, String> {
+    let (number, unit) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&value[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    number
+        .parse::<usize>()
+        .map(|number| number * unit)
+        .map_err(|_| format!("expected a size such as 768M, not {}", value))
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let reader = Reader::new(open_input(&args.input)?)?;
+    let mut output = open_output(&args.output)?;
+    sort(reader, &mut output, args.max_mem, args.temp_dir.as_deref())?;
+    Ok(output.finish()?)
+}
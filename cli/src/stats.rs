@@ -0,0 +1,46 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use vcf::stats::Stats;
+use vcf::vcf::{Reader, VCFError};
+
+use crate::io::{create, open_input};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum OutputFormat {
+    /// Tab-separated lines, each starting with the name of its section
+    Tsv,
+    /// A single JSON object
+    Json,
+}
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Format to write the statistics in
+    #[arg(short = 'O', long, value_enum, default_value = "tsv")]
+    output_format: OutputFormat,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let mut reader = Reader::new(open_input(&args.input)?)?;
+    let mut stats = Stats::new(reader.header());
+    for record in reader.records() {
+        stats.add(&record?);
+    }
+    let mut output = create(&args.output)?;
+    match args.output_format {
+        OutputFormat::Tsv => stats.write_tsv(&mut output)?,
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut output, &stats).map_err(io::Error::from)?;
+            writeln!(output)?;
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
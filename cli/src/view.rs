@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use vcf::arrow::{write_parquet, DEFAULT_ROW_GROUP_SIZE};
+use vcf::parallel::ParallelReader;
+use vcf::samples::{
+    add_allele_count_definitions, read_sample_list, update_allele_counts, SampleSelection,
+};
+use vcf::vcf::{Reader, VCFError};
+use vcf::{Record, TypedRecord};
+
+use crate::io::{create, open_input, open_input_with_threads, open_output};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum OutputFormat {
+    /// VCF text
+    Vcf,
+    /// A single JSON object with the header and an array of records
+    Json,
+    /// One JSON object per record and line, without the header
+    Ndjson,
+    /// Parquet, with a column per INFO field and a struct column per sample
+    Parquet,
+}
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Format to write records in
+    #[arg(short = 'O', long, value_enum, default_value = "vcf")]
+    output_format: OutputFormat,
+    /// Rows in each Parquet row group
+    #[arg(
+        long,
+        default_value_t = DEFAULT_ROW_GROUP_SIZE,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    row_group_size: usize,
+    /// Comma-separated samples to keep, in this order, or to remove if the list starts with ^
+    #[arg(short, long, value_name = "LIST", conflicts_with = "samples_file")]
+    samples: Option<String>,
+    /// File of samples to keep, one per line, or to remove if the name starts with ^
+    #[arg(short = 'S', long, value_name = "FILE")]
+    samples_file: Option<PathBuf>,
+    /// Recompute INFO/AC and INFO/AN from the genotypes of the samples kept
+    #[arg(long)]
+    update_counts: bool,
+    /// Threads to decompress and parse the input with
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+    /// Output file
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Input VCF
+    #[arg(default_value = "-")]
+    input: PathBuf,
+}
+
+fn selection(args: &Args) -> Result<Option<SampleSelection>, VCFError> {
+    if let Some(list) = &args.samples {
+        return Ok(Some(SampleSelection::parse_list(list)));
+    }
+    let Some(path) = &args.samples_file else {
+        return Ok(None);
+    };
+    let (path, exclude) = match path.to_str().and_then(|path| path.strip_prefix('^')) {
+        Some(path) => (PathBuf::from(path), true),
+        None => (path.clone(), false),
+    };
+    let names = read_sample_list(open_input(&path)?)?;
+    Ok(Some(match exclude {
+        true => SampleSelection::Exclude(names),
+        false => SampleSelection::Include(names),
+    }))
+}
+
+pub fn run(args: Args) -> Result<(), VCFError> {
+    let threads = usize::from(args.threads);
+    let mut reader = Reader::new(open_input_with_threads(&args.input, threads)?)?;
+    if let Some(selection) = selection(&args)? {
+        reader.select_samples(&selection)?;
+    }
+    let mut header = reader.header().clone();
+    if args.update_counts {
+        add_allele_count_definitions(&mut header);
+    }
+    let update_counts = args.update_counts;
+    let records: Box<dyn Iterator<Item = Result<Record, VCFError>>> = match threads {
+        1 => Box::new(reader.records()),
+        _ => Box::new(ParallelReader::new(reader, threads)),
+    };
+    let records = records.map(|record| {
+        let mut record: Record = record?;
+        if update_counts {
+            update_allele_counts(&mut record);
+        }
+        Ok(record)
+    });
+    if let OutputFormat::Parquet = args.output_format {
+        return write_parquet(&header, records, create(&args.output)?, args.row_group_size);
+    }
+    let definitions = header.definitions();
+    let mut output = open_output(&args.output)?;
+
+    match args.output_format {
+        OutputFormat::Vcf => {
+            write!(output, "{}", header)?;
+            for record in records {
+                writeln!(output, "{}", record?)?;
+            }
+        }
+        OutputFormat::Json => {
+            write!(output, "{{\"header\":")?;
+            serde_json::to_writer(&mut output, &header).map_err(io::Error::from)?;
+            write!(output, ",\"records\":[")?;
+            for (i, record) in records.enumerate() {
+                let typed = TypedRecord::new(&record?, &definitions, &header.samples);
+                writeln!(output, "{}", if i == 0 { "" } else { "," })?;
+                serde_json::to_writer(&mut output, &typed).map_err(io::Error::from)?;
+            }
+            writeln!(output, "]}}")?;
+        }
+        OutputFormat::Ndjson => {
+            for record in records {
+                let typed = TypedRecord::new(&record?, &definitions, &header.samples);
+                serde_json::to_writer(&mut output, &typed).map_err(io::Error::from)?;
+                writeln!(output)?;
+            }
+        }
+        OutputFormat::Parquet => unreachable!("Parquet is written above"),
+    }
+    output.finish()?;
+    Ok(())
+}
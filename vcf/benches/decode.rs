@@ -0,0 +1,146 @@
+//! Compares eager decoding with [`Record`] against lazy decoding with [`LazyRecord`].
+//!
+//! Run with `cargo bench -p vcf --bench decode`.
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use vcf::vcf::Reader;
+
+const RECORDS: usize = 2_000;
+const SAMPLES: usize = 200;
+
+/// A VCF with a few INFO fields and `SAMPLES` samples with three FORMAT fields each.
+fn source() -> Vec<u8> {
+    let mut source = String::from(
+        "##fileformat=VCFv4.3\n\
+         ##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\n\
+         ##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">\n\
+         ##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+         ##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype quality\">\n\
+         ##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Allele depths\">\n\
+         #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT",
+    );
+    for sample in 0..SAMPLES {
+        source.push_str(&format!("\tS{}", sample));
+    }
+    source.push('\n');
+    for record in 0..RECORDS {
+        source.push_str(&format!(
+            "1\t{}\t.\tA\tG\t50\tPASS\tAF=0.{};DP={}\tGT:GQ:AD",
+            record * 10 + 1,
+            record % 10,
+            record % 100
+        ));
+        for sample in 0..SAMPLES {
+            source.push_str(&format!(
+                "\t0/1:{}:{},{}",
+                sample % 99,
+                sample % 7,
+                sample % 5
+            ));
+        }
+        source.push('\n');
+    }
+    source.into_bytes()
+}
+
+fn decode(c: &mut Criterion) {
+    let source = source();
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+
+    group.bench_function("eager/position", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&source[..]).unwrap();
+            let mut sum = 0;
+            while let Some(record) = reader.read_record().unwrap() {
+                sum += record.pos;
+            }
+            black_box(sum)
+        })
+    });
+    group.bench_function("lazy/position", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&source[..]).unwrap();
+            let mut sum = 0;
+            while let Some(record) = reader.read_lazy().unwrap() {
+                sum += record.pos();
+            }
+            black_box(sum)
+        })
+    });
+
+    group.bench_function("eager/info", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&source[..]).unwrap();
+            let mut count = 0;
+            while let Some(record) = reader.read_record().unwrap() {
+                count += record.info("DP").flatten().map_or(0, str::len);
+            }
+            black_box(count)
+        })
+    });
+    group.bench_function("lazy/info", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&source[..]).unwrap();
+            let mut count = 0;
+            while let Some(record) = reader.read_lazy().unwrap() {
+                count += record.info("DP").flatten().map_or(0, str::len);
+            }
+            black_box(count)
+        })
+    });
+
+    group.bench_function("eager/one_sample", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&source[..]).unwrap();
+            let mut count = 0;
+            while let Some(record) = reader.read_record().unwrap() {
+                count += record.sample_value(SAMPLES / 2, "GQ").map_or(0, str::len);
+            }
+            black_box(count)
+        })
+    });
+    group.bench_function("lazy/one_sample", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&source[..]).unwrap();
+            let mut count = 0;
+            while let Some(record) = reader.read_lazy().unwrap() {
+                count += record.sample_value(SAMPLES / 2, "GQ").map_or(0, str::len);
+            }
+            black_box(count)
+        })
+    });
+
+    group.bench_function("eager/all_samples", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&source[..]).unwrap();
+            let mut count = 0;
+            while let Some(record) = reader.read_record().unwrap() {
+                count += (0..SAMPLES)
+                    .filter_map(|sample| record.sample_value(sample, "GT"))
+                    .count();
+            }
+            black_box(count)
+        })
+    });
+    group.bench_function("lazy/all_samples", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(&source[..]).unwrap();
+            let mut count = 0;
+            while let Some(record) = reader.read_lazy().unwrap() {
+                let gt = record.format().position(|key| key == "GT").unwrap();
+                count += record
+                    .samples()
+                    .iter()
+                    .filter_map(|sample| sample.split(':').nth(gt))
+                    .count();
+            }
+            black_box(count)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
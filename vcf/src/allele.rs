@@ -0,0 +1,453 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::parse::ParseError;
+use crate::vcf::VCFError;
+
+/// Symbolic allele types that may be used without an `##ALT` definition.
+const RESERVED_SYMBOLIC: [&str; 6] = ["*", "DEL", "INS", "DUP", "INV", "CNV"];
+
+/// A single allele from the ALT column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Allele {
+    /// A sequence of bases, e.g. `A` or `ACGT`.
+    Bases(String),
+    /// A symbolic allele, without the angle brackets, e.g. `DUP:TANDEM`, `*` or `NON_REF`.
+    Symbolic(String),
+    /// A breakend, in either the mated (`G]17:198982]`) or single (`.A`) notation.
+    Breakend(Breakend),
+    /// `*`: the allele is missing because of an overlapping deletion.
+    OverlappingDeletion,
+    /// `.`: no alternate allele.
+    Missing,
+}
+
+/// One side of a novel adjacency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakend {
+    /// The bases written in the allele: the reference base plus any inserted sequence.
+    pub sequence: String,
+    /// Whether the adjacency follows `sequence` (`t[p[`, `t]p]`, `t.`) rather than precedes it
+    /// (`]p]t`, `[p[t`, `.t`).
+    pub joined_after: bool,
+    /// The mate breakend, or `None` for a single breakend.
+    pub mate: Option<Mate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mate {
+    pub contig: String,
+    pub position: u64,
+    /// Whether the joined sequence extends to the right of the mate position (`[`) rather than
+    /// to its left (`]`).
+    pub extends_right: bool,
+}
+
+impl Breakend {
+    /// The reference base at the breakend position.
+    pub fn reference_base(&self) -> Option<char> {
+        if self.joined_after {
+            self.sequence.chars().next()
+        } else {
+            self.sequence.chars().last()
+        }
+    }
+
+    /// Bases inserted between the breakend and its mate.
+    pub fn inserted_sequence(&self) -> &str {
+        match self.sequence.len() {
+            0 => "",
+            _ if self.joined_after => &self.sequence[1..],
+            n => &self.sequence[..n - 1],
+        }
+    }
+}
+
+impl Allele {
+    pub fn is_symbolic(&self) -> bool {
+        matches!(self, Allele::Symbolic(..))
+    }
+
+    /// `<*>` or `<NON_REF>`: any possible alternate allele, as used in gVCF reference blocks.
+    pub fn is_unspecified(&self) -> bool {
+        matches!(self, Allele::Symbolic(id) if id == "*" || id == "NON_REF")
+    }
+
+    /// The kind of change this allele makes to `reference`.
+    ///
+    /// Bases are compared after removing those shared by the start and then the end of both
+    /// alleles, so the padding base of an indel is not part of the change.
+    pub fn variant_type(&self, reference: &str) -> VariantType {
+        match self {
+            Allele::Bases(bases) => match trim(reference.as_bytes(), bases.as_bytes()) {
+                ([], []) => VariantType::Reference,
+                ([_], [_]) => VariantType::Snv,
+                (r, a) if r.len() == a.len() => VariantType::Mnv,
+                ([], _) => VariantType::Insertion,
+                (_, []) => VariantType::Deletion,
+                _ => VariantType::Complex,
+            },
+            Allele::Symbolic(_) if self.is_unspecified() => VariantType::Unspecified,
+            Allele::Symbolic(_) => VariantType::Symbolic,
+            Allele::Breakend(_) => VariantType::Breakend,
+            Allele::OverlappingDeletion => VariantType::OverlappingDeletion,
+            Allele::Missing => VariantType::Reference,
+        }
+    }
+
+    /// Whether this allele is a transition or a transversion of `reference`, if it is a SNV.
+    pub fn substitution(&self, reference: &str) -> Option<Substitution> {
+        let Allele::Bases(bases) = self else {
+            return None;
+        };
+        match trim(reference.as_bytes(), bases.as_bytes()) {
+            ([reference], [alt]) => Substitution::of(*reference, *alt),
+            _ => None,
+        }
+    }
+}
+
+/// Remove the bases shared by the start and then the end of two alleles.
+fn trim<'a>(mut reference: &'a [u8], mut alt: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+    while let ([r, rest_r @ ..], [a, rest_a @ ..]) = (reference, alt) {
+        if !r.eq_ignore_ascii_case(a) {
+            break;
+        }
+        (reference, alt) = (rest_r, rest_a);
+    }
+    while let ([rest_r @ .., r], [rest_a @ .., a]) = (reference, alt) {
+        if !r.eq_ignore_ascii_case(a) {
+            break;
+        }
+        (reference, alt) = (rest_r, rest_a);
+    }
+    (reference, alt)
+}
+
+/// The kind of a variant, for an ALT allele or for a whole record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum VariantType {
+    /// The allele is the reference, or the site has no ALT allele (`.`).
+    Reference,
+    /// One base is substituted, e.g. `A` to `G`, or `ACG` to `AGG`.
+    Snv,
+    /// Several bases are substituted, keeping the length, e.g. `AC` to `GT`.
+    Mnv,
+    /// Bases are inserted, e.g. `A` to `ACT`.
+    Insertion,
+    /// Bases are deleted, e.g. `ACT` to `A`.
+    Deletion,
+    /// Bases are both substituted and inserted or deleted, e.g. `AC` to `GTT`.
+    Complex,
+    /// A symbolic allele such as `<DEL>` or `<DUP:TANDEM>`.
+    Symbolic,
+    /// A breakend, mated or single.
+    Breakend,
+    /// `*`: the allele is missing because of an overlapping deletion.
+    OverlappingDeletion,
+    /// `<*>` or `<NON_REF>`: any allele not listed, as in gVCF reference blocks.
+    Unspecified,
+    /// Only for records: ALT alleles of more than one kind.
+    Mixed,
+}
+
+/// The two kinds of single-base substitution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Substitution {
+    /// Between two purines (`A`, `G`) or two pyrimidines (`C`, `T`).
+    Transition,
+    /// Between a purine and a pyrimidine.
+    Transversion,
+}
+
+impl Substitution {
+    /// The substitution of `reference` by `alt`, or `None` unless both are different bases
+    /// among `A`, `C`, `G` and `T`, in either case.
+    pub fn of(reference: u8, alt: u8) -> Option<Self> {
+        let purine = |base: u8| match base.to_ascii_uppercase() {
+            b'A' | b'G' => Some(true),
+            b'C' | b'T' => Some(false),
+            _ => None,
+        };
+        if reference.eq_ignore_ascii_case(&alt) {
+            return None;
+        }
+        match purine(reference)? == purine(alt)? {
+            true => Some(Substitution::Transition),
+            false => Some(Substitution::Transversion),
+        }
+    }
+}
+
+fn is_bases(input: &str) -> bool {
+    !input.is_empty()
+        && input
+            .bytes()
+            .all(|b| matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N'))
+}
+
+impl FromStr for Allele {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "." => return Ok(Allele::Missing),
+            "*" => return Ok(Allele::OverlappingDeletion),
+            _ => {}
+        }
+        if let Some(id) = input.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            return if id.is_empty() {
+                Err(ParseError)
+            } else {
+                Ok(Allele::Symbolic(id.to_string()))
+            };
+        }
+        if let Some(open) = input.find(['[', ']']) {
+            let bracket = input.as_bytes()[open] as char;
+            let close = open + 1 + input[open + 1..].find(bracket).ok_or(ParseError)?;
+            let (contig, position) = input[open + 1..close].rsplit_once(':').ok_or(ParseError)?;
+            let mate = Mate {
+                contig: contig.to_string(),
+                position: position.parse().map_err(|_| ParseError)?,
+                extends_right: bracket == '[',
+            };
+            let (sequence, joined_after) = match (open, close + 1 == input.len()) {
+                (0, _) => (&input[close + 1..], false),
+                (_, true) => (&input[..open], true),
+                _ => return Err(ParseError),
+            };
+            if !is_bases(sequence) || contig.is_empty() {
+                return Err(ParseError);
+            }
+            return Ok(Allele::Breakend(Breakend {
+                sequence: sequence.to_string(),
+                joined_after,
+                mate: Some(mate),
+            }));
+        }
+        let single = match (input.strip_prefix('.'), input.strip_suffix('.')) {
+            (Some(sequence), _) => Some((sequence, false)),
+            (_, Some(sequence)) => Some((sequence, true)),
+            _ => None,
+        };
+        match single {
+            Some((sequence, joined_after)) if is_bases(sequence) => {
+                Ok(Allele::Breakend(Breakend {
+                    sequence: sequence.to_string(),
+                    joined_after,
+                    mate: None,
+                }))
+            }
+            None if is_bases(input) => Ok(Allele::Bases(input.to_string())),
+            _ => Err(ParseError),
+        }
+    }
+}
+
+impl fmt::Display for Allele {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Allele::Bases(bases) => write!(f, "{}", bases),
+            Allele::Symbolic(id) => write!(f, "<{}>", id),
+            Allele::Breakend(breakend) => write!(f, "{}", breakend),
+            Allele::OverlappingDeletion => write!(f, "*"),
+            Allele::Missing => write!(f, "."),
+        }
+    }
+}
+
+impl fmt::Display for Breakend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mate = match &self.mate {
+            Some(mate) => {
+                let bracket = if mate.extends_right { '[' } else { ']' };
+                format!("{}{}:{}{}", bracket, mate.contig, mate.position, bracket)
+            }
+            None => ".".to_string(),
+        };
+        if self.joined_after {
+            write!(f, "{}{}", self.sequence, mate)
+        } else {
+            write!(f, "{}{}", mate, self.sequence)
+        }
+    }
+}
+
+/// Check that every symbolic allele is defined by an `##ALT` line, given the defined IDs.
+///
+/// The reserved types `<*>`, `<DEL>`, `<INS>`, `<DUP>`, `<INV>` and `<CNV>` need no definition,
+/// and a subtype such as `<DUP:TANDEM>` is accepted when any of its parent types is defined
+/// or reserved.
+pub fn check_symbolic_alleles(
+    alleles: &[Allele],
+    defined: &HashSet<String>,
+) -> Result<(), VCFError> {
+    for allele in alleles {
+        if let Allele::Symbolic(id) = allele {
+            let is_defined = std::iter::successors(Some(id.as_str()), |id| {
+                id.rsplit_once(':').map(|(parent, _)| parent)
+            })
+            .any(|id| defined.contains(id) || RESERVED_SYMBOLIC.contains(&id));
+            if !is_defined {
+                return Err(VCFError::InvalidRecord(format!(
+                    "symbolic allele <{}> is not defined in the header",
+                    id
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_simple_alleles() {
+        assert_eq!("ACGT".parse(), Ok(Allele::Bases("ACGT".to_string())));
+        assert_eq!("*".parse(), Ok(Allele::OverlappingDeletion));
+        assert_eq!(".".parse(), Ok(Allele::Missing));
+        assert_eq!(
+            "<DUP:TANDEM>".parse(),
+            Ok(Allele::Symbolic("DUP:TANDEM".to_string()))
+        );
+        assert!("<*>".parse::<Allele>().unwrap().is_unspecified());
+        assert!("<NON_REF>".parse::<Allele>().unwrap().is_unspecified());
+        assert!("AXG".parse::<Allele>().is_err());
+        assert!("<>".parse::<Allele>().is_err());
+    }
+
+    #[test]
+    fn parses_mated_breakends() {
+        let allele: Allele = "G]17:198982]".parse().unwrap();
+        let Allele::Breakend(breakend) = &allele else {
+            panic!()
+        };
+        assert!(breakend.joined_after);
+        assert_eq!(breakend.reference_base(), Some('G'));
+        assert_eq!(
+            breakend.mate,
+            Some(Mate {
+                contig: "17".to_string(),
+                position: 198982,
+                extends_right: false
+            })
+        );
+
+        let allele: Allele = "[13:123457[AGTNNNNNCA".parse().unwrap();
+        let Allele::Breakend(breakend) = &allele else {
+            panic!()
+        };
+        assert!(!breakend.joined_after);
+        assert!(breakend.mate.as_ref().unwrap().extends_right);
+        assert_eq!(breakend.reference_base(), Some('A'));
+        assert_eq!(breakend.inserted_sequence(), "AGTNNNNNC");
+    }
+
+    #[test]
+    fn parses_single_breakends() {
+        let allele: Allele = ".A".parse().unwrap();
+        assert_eq!(
+            allele,
+            Allele::Breakend(Breakend {
+                sequence: "A".to_string(),
+                joined_after: false,
+                mate: None
+            })
+        );
+        let allele: Allele = "GTC.".parse().unwrap();
+        let Allele::Breakend(breakend) = &allele else {
+            panic!()
+        };
+        assert!(breakend.joined_after);
+        assert_eq!(breakend.inserted_sequence(), "TC");
+    }
+
+    #[test]
+    fn rejects_malformed_breakends() {
+        for input in [
+            "G]17:198982",
+            "G]17198982]",
+            "G]17:198982]A",
+            "G]:1]",
+            "]17:x]G",
+        ] {
+            assert!(input.parse::<Allele>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn classifies_alleles() {
+        let kinds = |reference: &str, alts: &[&str]| -> Vec<VariantType> {
+            alts.iter()
+                .map(|alt| alt.parse::<Allele>().unwrap().variant_type(reference))
+                .collect()
+        };
+        use VariantType::*;
+        assert_eq!(
+            kinds(
+                "A",
+                &["G", "ACT", "A", ".", "*", "<*>", "<DEL>", "G[2:5[", ".A"]
+            ),
+            [
+                Snv,
+                Insertion,
+                Reference,
+                Reference,
+                OverlappingDeletion,
+                Unspecified,
+                Symbolic,
+                Breakend,
+                Breakend
+            ]
+        );
+        assert_eq!(
+            kinds("ACG", &["AGG", "TCA", "A", "acg", "GTTG", "ACGACG"]),
+            [Snv, Mnv, Deletion, Reference, Complex, Insertion]
+        );
+    }
+
+    #[test]
+    fn classifies_substitutions() {
+        let substitution =
+            |reference: &str, alt: &str| alt.parse::<Allele>().unwrap().substitution(reference);
+        assert_eq!(substitution("A", "G"), Some(Substitution::Transition));
+        assert_eq!(substitution("c", "T"), Some(Substitution::Transition));
+        assert_eq!(substitution("A", "C"), Some(Substitution::Transversion));
+        assert_eq!(substitution("ACG", "ATG"), Some(Substitution::Transition));
+        assert_eq!(substitution("A", "N"), None);
+        assert_eq!(substitution("A", "AT"), None);
+        assert_eq!(substitution("A", "A"), None);
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        for input in [
+            "A",
+            "<DEL>",
+            "G]17:198982]",
+            "]13:123456]AGTNNNNNCAT",
+            "C[2:321682[",
+            ".A",
+            "A.",
+            "*",
+        ] {
+            assert_eq!(input.parse::<Allele>().unwrap().to_string(), input);
+        }
+    }
+
+    #[test]
+    fn checks_symbolic_alleles_against_definitions() {
+        let defined = HashSet::from(["NON_REF".to_string(), "DEL:ME".to_string()]);
+        let alleles =
+            |ids: &[&str]| -> Vec<Allele> { ids.iter().map(|id| id.parse().unwrap()).collect() };
+        assert!(check_symbolic_alleles(
+            &alleles(&["<NON_REF>", "<DEL:ME:ALU>", "<DUP:TANDEM>", "A"]),
+            &defined
+        )
+        .is_ok());
+        assert!(check_symbolic_alleles(&alleles(&["<INS>", "<CN0>"]), &defined).is_err());
+    }
+}
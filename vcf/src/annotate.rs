@@ -0,0 +1,778 @@
+//! Annotating records with the IDs and INFO values of another file: a VCF, a BED file or a
+//! table of tab-separated columns.
+//!
+//! The source is read alongside the records being annotated, so both must be sorted by position
+//! with their contigs in the same order: that of the `##contig` lines of the annotated file,
+//! then natural order for contigs it does not list. A bgzipped source read with its tabix index
+//! (see [`VcfSource::indexed`]) skips ahead to the records being annotated, so annotating a few
+//! regions of a large source only reads the parts of it around them.
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, Read, Seek};
+
+use crate::tabix::IndexedReader;
+use crate::vcf::{Reader, VCFError, VCF};
+use crate::{ContigOrder, Header, HeaderValue, Interval, NumberField, Record};
+
+/// A record or line of an annotation source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub chrom: String,
+    /// The 1-based reference positions covered.
+    pub interval: Interval,
+    /// The REF allele, when the source gives alleles.
+    pub reference: Option<String>,
+    pub alt: Vec<String>,
+    pub id: Vec<String>,
+    /// Values by INFO key; flags have no value.
+    pub info: Vec<(String, Option<String>)>,
+}
+
+/// A file of annotations, read in order.
+pub trait AnnotationSource {
+    /// The next entry, or `None` at the end of the source.
+    fn next_entry(&mut self) -> Result<Option<Entry>, VCFError>;
+
+    /// The `##INFO` line defining `key`, if the source has one.
+    fn definition(&self, key: &str) -> Option<String>;
+
+    /// Skip ahead to the entries that may overlap `pos` on `chrom`, if the source can, and
+    /// return whether it did. Entries read after skipping may still end before `pos`.
+    fn seek(&mut self, _chrom: &str, _pos: u64) -> Result<bool, VCFError> {
+        Ok(false)
+    }
+}
+
+impl<S: AnnotationSource + ?Sized> AnnotationSource for Box<S> {
+    fn next_entry(&mut self) -> Result<Option<Entry>, VCFError> {
+        (**self).next_entry()
+    }
+
+    fn definition(&self, key: &str) -> Option<String> {
+        (**self).definition(key)
+    }
+
+    fn seek(&mut self, chrom: &str, pos: u64) -> Result<bool, VCFError> {
+        (**self).seek(chrom, pos)
+    }
+}
+
+/// How a source skips ahead in its input: not at all, unless the input is indexed.
+type Seeker<R> = fn(&mut R, &str, u64) -> io::Result<bool>;
+
+fn no_seek<R>(_: &mut R, _: &str, _: u64) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Annotations from the records of a VCF.
+pub struct VcfSource<R> {
+    reader: Reader<R>,
+    seek: Seeker<R>,
+}
+
+impl<R: BufRead> VcfSource<R> {
+    pub fn new(reader: Reader<R>) -> Self {
+        Self {
+            reader,
+            seek: no_seek,
+        }
+    }
+}
+
+impl<R: Read + Seek> VcfSource<IndexedReader<R>> {
+    /// Skip ahead with the index of the input to the records being annotated.
+    pub fn indexed(self) -> Self {
+        Self {
+            seek: IndexedReader::seek,
+            ..self
+        }
+    }
+}
+
+impl<R: BufRead> AnnotationSource for VcfSource<R> {
+    fn next_entry(&mut self) -> Result<Option<Entry>, VCFError> {
+        let Some(record) = self.reader.read_record()? else {
+            return Ok(None);
+        };
+        Ok(Some(Entry {
+            interval: record.interval(),
+            chrom: record.chrom,
+            reference: Some(record.reference),
+            alt: record.alt,
+            id: record.id,
+            info: record.info,
+        }))
+    }
+
+    fn definition(&self, key: &str) -> Option<String> {
+        self.reader
+            .header()
+            .headers()
+            .find(|header| header.key == "INFO" && header.id() == Some(key))
+            .map(|header| header.to_string())
+    }
+
+    fn seek(&mut self, chrom: &str, pos: u64) -> Result<bool, VCFError> {
+        Ok((self.seek)(self.reader.get_mut(), chrom, pos)?)
+    }
+}
+
+/// What a column of a table holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Chrom,
+    /// A 1-based position; the entry covers the length of REF from there.
+    Pos,
+    /// The first position of a region, 1-based in tables and 0-based in BED files.
+    From,
+    /// The last position of a region.
+    To,
+    Ref,
+    /// Comma-separated ALT alleles.
+    Alt,
+    Id,
+    /// A column that is not used.
+    Skip,
+    /// The value of an INFO key.
+    Info(String),
+}
+
+impl Column {
+    /// Parse comma-separated column names: `CHROM`, `POS`, `FROM`, `TO`, `REF`, `ALT`, `ID`,
+    /// `-` for a column to skip, and `INFO/KEY` or `KEY` for an INFO value.
+    pub fn parse_list(list: &str) -> Vec<Column> {
+        list.split(',')
+            .map(|name| match name {
+                "CHROM" => Column::Chrom,
+                "POS" => Column::Pos,
+                "FROM" => Column::From,
+                "TO" => Column::To,
+                "REF" => Column::Ref,
+                "ALT" => Column::Alt,
+                "ID" => Column::Id,
+                "-" => Column::Skip,
+                key => Column::Info(key.strip_prefix("INFO/").unwrap_or(key).to_string()),
+            })
+            .collect()
+    }
+}
+
+/// Annotations from the lines of a tab-separated table or BED file.
+///
+/// Blank lines, and lines starting with `#`, `track` or `browser`, are skipped. Values of `.`
+/// are missing.
+pub struct TableSource<R> {
+    input: R,
+    columns: Vec<Column>,
+    zero_based: bool,
+    line: String,
+    seek: Seeker<R>,
+}
+
+impl<R: BufRead> TableSource<R> {
+    /// Read a table whose columns are given by `columns`, which must include `CHROM` and
+    /// either `POS` or both `FROM` and `TO`.
+    pub fn new(input: R, columns: Vec<Column>) -> Result<Self, VCFError> {
+        let has = |column: Column| columns.contains(&column);
+        if !has(Column::Chrom) || !(has(Column::Pos) || has(Column::From) && has(Column::To)) {
+            return Err(VCFError::InvalidExpression(
+                "columns must include CHROM, and POS or FROM and TO".to_string(),
+            ));
+        }
+        Ok(Self {
+            input,
+            columns,
+            zero_based: false,
+            line: String::new(),
+            seek: no_seek,
+        })
+    }
+
+    /// Read a BED file, whose `FROM` column is 0-based.
+    pub fn bed(input: R, columns: Vec<Column>) -> Result<Self, VCFError> {
+        Ok(Self {
+            zero_based: true,
+            ..Self::new(input, columns)?
+        })
+    }
+}
+
+impl<R: Read + Seek> TableSource<IndexedReader<R>> {
+    /// Skip ahead with the index of the input to the lines being annotated.
+    pub fn indexed(self) -> Self {
+        Self {
+            seek: IndexedReader::seek,
+            ..self
+        }
+    }
+}
+
+impl<R: BufRead> AnnotationSource for TableSource<R> {
+    fn next_entry(&mut self) -> Result<Option<Entry>, VCFError> {
+        let line = loop {
+            self.line.clear();
+            if self.input.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            let line = self.line.trim_end_matches(['\n', '\r']);
+            let skip = ["#", "track", "browser"];
+            if !line.trim().is_empty() && !skip.iter().any(|prefix| line.starts_with(prefix)) {
+                break line;
+            }
+        };
+        let invalid = || VCFError::InvalidRecord(format!("cannot read annotation line {:?}", line));
+        let number = |value: &str| value.parse::<u64>().map_err(|_| invalid());
+        let mut entry = Entry {
+            chrom: String::new(),
+            interval: Interval::new(0, 0),
+            reference: None,
+            alt: Vec::new(),
+            id: Vec::new(),
+            info: Vec::new(),
+        };
+        let (mut pos, mut from, mut to) = (None, None, None);
+        for (column, value) in self.columns.iter().zip(line.split('\t')) {
+            let list = || value.split([',', ';']).map(str::to_string).collect();
+            match column {
+                Column::Chrom => entry.chrom = value.to_string(),
+                Column::Pos => pos = Some(number(value)?),
+                Column::From => from = Some(number(value)? + self.zero_based as u64),
+                Column::To => to = Some(number(value)?),
+                Column::Skip => {}
+                _ if value == "." || value.is_empty() => {}
+                Column::Ref => entry.reference = Some(value.to_string()),
+                Column::Alt => entry.alt = value.split(',').map(str::to_string).collect(),
+                Column::Id => entry.id = list(),
+                Column::Info(key) => entry.info.push((key.clone(), Some(value.to_string()))),
+            }
+        }
+        entry.interval = match (pos, from, to) {
+            (Some(pos), _, _) => {
+                let length = entry.reference.as_ref().map_or(1, |r| r.len().max(1));
+                Interval::new(pos, pos + length as u64 - 1)
+            }
+            (None, Some(from), Some(to)) if from <= to => Interval::new(from, to),
+            _ => return Err(invalid()),
+        };
+        if entry.chrom.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Some(entry))
+    }
+
+    fn definition(&self, _key: &str) -> Option<String> {
+        None
+    }
+
+    fn seek(&mut self, chrom: &str, pos: u64) -> Result<bool, VCFError> {
+        Ok((self.seek)(&mut self.input, chrom, pos)?)
+    }
+}
+
+/// Which source entries annotate a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Matching {
+    /// Entries at the same position with the same REF, sharing an ALT allele. Entries without
+    /// alleles match by position. Alleles are compared as written, so both files should be
+    /// normalized the same way.
+    Alleles,
+    /// Entries at the same position.
+    Position,
+    /// Entries whose reference interval overlaps that of the record.
+    Overlap,
+}
+
+/// What to do with the values a record already has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Existing {
+    /// Replace them with source values, keeping them where the source has none.
+    Overwrite,
+    /// Only add values the record does not have.
+    Keep,
+    /// Replace them with source values, removing them where the source has none.
+    Clear,
+}
+
+/// What to copy from the source, and how.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// INFO keys to copy.
+    pub keys: Vec<String>,
+    /// Whether to copy the ID.
+    pub id: bool,
+    pub matching: Matching,
+    pub existing: Existing,
+}
+
+/// Annotates records, in order, from a source.
+///
+/// With [`Matching::Alleles`], values of `Number=A` and `Number=R` fields, as defined by the
+/// source or, failing that, by the annotated file's header, are matched to the record's alleles, so a source with one record per allele can
+/// annotate a multiallelic record. Alleles the source has no value for are written `.`, or keep
+/// the record's existing value with [`Existing::Overwrite`]. Other values come from the first
+/// matching entry that has them, and IDs from all matching entries.
+///
+/// ```
+/// use vcf::annotate::{Annotator, Column, Existing, Matching, Options, TableSource};
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let target = b"##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+/// 1\t100\t.\tA\tG\t.\t.\t.\n1\t200\t.\tC\tT\t.\t.\t.\n";
+/// let table = "1\t90\t150\tpromoter\n1\t180\t190\tenhancer\n";
+/// let columns = Column::parse_list("CHROM,FROM,TO,REGION");
+/// let source = TableSource::bed(table.as_bytes(), columns)?;
+/// let options = Options {
+///     keys: vec!["REGION".to_string()],
+///     id: false,
+///     matching: Matching::Overlap,
+///     existing: Existing::Overwrite,
+/// };
+/// let mut reader = Reader::new(&target[..])?;
+/// let mut annotator = Annotator::new(source, reader.header(), options);
+/// let mut records = Vec::new();
+/// for record in reader.records() {
+///     let mut record = record?;
+///     annotator.annotate(&mut record)?;
+///     records.push(record);
+/// }
+/// assert_eq!(records[0].info("REGION"), Some(Some("promoter")));
+/// assert_eq!(records[1].info("REGION"), None);
+///# Ok::<(), VCFError>(())
+/// ```
+pub struct Annotator<S> {
+    source: S,
+    options: Options,
+    order: ContigOrder,
+    /// The numbers of the fields whose values are matched to alleles.
+    per_allele: HashMap<String, NumberField>,
+    /// Entries read from the source that may annotate the next records.
+    pending: VecDeque<Entry>,
+    /// The next entry of the source, and whether the source has ended.
+    next: Option<Entry>,
+    ended: bool,
+    /// The contig and start of the last entry read, to check the source is sorted.
+    previous: Option<(String, u64)>,
+}
+
+impl<S: AnnotationSource> Annotator<S> {
+    /// Annotate records with the header `header` from `source`.
+    pub fn new(source: S, header: &VCF, options: Options) -> Self {
+        let mut per_allele = HashMap::new();
+        let definitions = header.definitions();
+        for key in &options.keys {
+            let number = match source.definition(key) {
+                Some(line) => {
+                    let mut definition = VCF::default();
+                    definition.meta.push(line);
+                    let info = definition.definitions().info.get(key).cloned();
+                    info.map(|info| info.fieldtype.number())
+                }
+                None => definitions
+                    .info
+                    .get(key)
+                    .map(|info| info.fieldtype.number()),
+            };
+            if let Some(number @ (NumberField::A | NumberField::R)) = number {
+                per_allele.insert(key.clone(), number);
+            }
+        }
+        Self {
+            source,
+            options,
+            order: ContigOrder::new(header.contigs()),
+            per_allele,
+            pending: VecDeque::new(),
+            next: None,
+            ended: false,
+            previous: None,
+        }
+    }
+
+    /// Define the copied INFO keys in `header`, with the source's definitions when it has
+    /// them, and as strings otherwise.
+    pub fn add_definitions(&self, header: &mut VCF) {
+        for key in &self.options.keys {
+            match self.source.definition(key) {
+                Some(line) => {
+                    if let Ok(definition) = Header::parse(&line) {
+                        header.set_header(&definition);
+                    }
+                }
+                None if header.definitions().info.contains_key(key) => {}
+                None => header.set_header(&Header {
+                    key: "INFO",
+                    value: HeaderValue::Nested(HashMap::from([
+                        ("ID", key.as_str()),
+                        ("Number", "."),
+                        ("Type", "String"),
+                        ("Description", "Added by annotation"),
+                    ])),
+                }),
+            }
+        }
+    }
+
+    /// Annotate the next record. Records must come in sorted order.
+    pub fn annotate(&mut self, record: &mut Record) -> Result<(), VCFError> {
+        let interval = record.interval();
+        self.read_until(&record.chrom, interval)?;
+        let matches: Vec<&Entry> = self
+            .pending
+            .iter()
+            .filter(|entry| self.matches(entry, record, interval))
+            .collect();
+
+        if self.options.id {
+            let mut ids: Vec<String> = Vec::new();
+            for id in matches.iter().flat_map(|entry| &entry.id) {
+                if !ids.contains(id) {
+                    ids.push(id.clone());
+                }
+            }
+            match self.options.existing {
+                Existing::Keep if !record.id.is_empty() => {}
+                Existing::Overwrite if ids.is_empty() => {}
+                _ => record.id = ids,
+            }
+        }
+        for key in &self.options.keys {
+            match (self.value(key, &matches, record), self.options.existing) {
+                (Some(_), Existing::Keep) if record.info(key).is_some() => {}
+                (Some(value), _) => record.set_info(key, value),
+                (None, Existing::Clear) => {
+                    record.remove_info(key);
+                }
+                (None, _) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the entries that start up to the end of `interval` on `chrom`, and drop those that
+    /// end before it.
+    fn read_until(&mut self, chrom: &str, interval: Interval) -> Result<(), VCFError> {
+        let order = &self.order;
+        self.pending
+            .retain(|entry| match order.compare(&entry.chrom, chrom) {
+                Ordering::Less => false,
+                Ordering::Equal => entry.interval.end >= interval.start,
+                Ordering::Greater => true,
+            });
+        let behind =
+            self.next
+                .as_ref()
+                .is_none_or(|next| match self.order.compare(&next.chrom, chrom) {
+                    Ordering::Less => true,
+                    Ordering::Equal => next.interval.end < interval.start,
+                    Ordering::Greater => false,
+                });
+        if behind && !self.ended && self.source.seek(chrom, interval.start)? {
+            self.next = None;
+        }
+        loop {
+            if self.next.is_none() && !self.ended {
+                self.next = self.source.next_entry()?;
+                self.ended = self.next.is_none();
+                if let Some(next) = &self.next {
+                    if let Some((chrom, start)) = &self.previous {
+                        let ordering = self
+                            .order
+                            .compare(chrom, &next.chrom)
+                            .then(start.cmp(&next.interval.start));
+                        if ordering == Ordering::Greater {
+                            return Err(VCFError::InvalidRecord(format!(
+                                "annotation at {}:{} comes after {}:{}; sources must be sorted",
+                                next.chrom, next.interval.start, chrom, start
+                            )));
+                        }
+                    }
+                    self.previous = Some((next.chrom.clone(), next.interval.start));
+                }
+            }
+            let Some(next) = &self.next else {
+                return Ok(());
+            };
+            match self.order.compare(&next.chrom, chrom) {
+                Ordering::Less => self.next = None,
+                Ordering::Equal if next.interval.start <= interval.end => {
+                    self.pending.extend(self.next.take());
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn matches(&self, entry: &Entry, record: &Record, interval: Interval) -> bool {
+        if entry.chrom != record.chrom {
+            return false;
+        }
+        match (self.options.matching, &entry.reference) {
+            (Matching::Overlap, _) => entry.interval.overlaps(&interval),
+            (Matching::Position, _) | (Matching::Alleles, None) => {
+                entry.interval.start == record.pos
+            }
+            (Matching::Alleles, Some(reference)) => {
+                entry.interval.start == record.pos
+                    && reference.eq_ignore_ascii_case(&record.reference)
+                    && (entry.alt.is_empty() && record.alt.is_empty()
+                        || entry.alt.iter().any(|alt| record.alt.contains(alt)))
+            }
+        }
+    }
+
+    /// The value of `key` for `record` from the matching entries, or `None` if they have none.
+    fn value(&self, key: &str, matches: &[&Entry], record: &Record) -> Option<Option<String>> {
+        let per_allele = match self.options.matching {
+            Matching::Alleles => self.per_allele.get(key),
+            _ => None,
+        };
+        let Some(number) = per_allele else {
+            return matches.iter().find_map(|entry| {
+                entry
+                    .info
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, value)| value.clone())
+            });
+        };
+        let with_reference = *number == NumberField::R;
+        let alleles = with_reference
+            .then_some(&record.reference)
+            .into_iter()
+            .chain(&record.alt);
+        let values: Vec<Option<&str>> = alleles
+            .map(|allele| {
+                matches.iter().find_map(|entry| {
+                    let value = entry.info.iter().find(|(k, _)| k == key)?.1.as_deref()?;
+                    let index = match entry.reference.as_ref() {
+                        Some(reference) if with_reference && reference == allele => 0,
+                        _ => {
+                            let index = entry.alt.iter().position(|alt| alt == allele)?;
+                            index + with_reference as usize
+                        }
+                    };
+                    value.split(',').nth(index)
+                })
+            })
+            .collect();
+        if values.iter().all(Option::is_none) {
+            return None;
+        }
+        // Alleles the source has no value for keep the record's own when overwriting.
+        let existing: Vec<&str> = match (self.options.existing, record.info(key)) {
+            (Existing::Overwrite, Some(Some(existing))) => existing.split(',').collect(),
+            _ => Vec::new(),
+        };
+        let existing = |index: usize| match existing.len() == values.len() {
+            true => existing[index],
+            false => ".",
+        };
+        let values: Vec<&str> = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| value.unwrap_or_else(|| existing(index)))
+            .collect();
+        Some(Some(values.join(",")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "##fileformat=VCFv4.3
+##contig=<ID=2>
+##contig=<ID=1>
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Old frequency\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+2\t100\trs0\tA\tG,T\t.\t.\tAF=0.9,0.9
+2\t150\t.\tC\tCA\t.\t.\t.
+2\t300\t.\tG\t<DEL>\t.\t.\tSVLEN=-100
+1\t50\t.\tT\tC\t.\t.\tAF=0.1
+";
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Frequency in the source\">
+##INFO=<ID=DB,Number=0,Type=Flag,Description=\"In the database\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+2\t100\trs1\tA\tT\t.\t.\tAF=0.2;DB
+2\t100\trs2\tA\tC,G\t.\t.\tAF=0.3,0.4
+2\t150\trs3\tC\tCAA\t.\t.\tAF=0.5
+1\t50\trs4\tT\tA\t.\t.\t.
+";
+
+    fn annotate<S: AnnotationSource>(source: S, options: Options) -> (VCF, Vec<Record>) {
+        let mut reader = Reader::new(TARGET.as_bytes()).unwrap();
+        let mut header = reader.header().clone();
+        let mut annotator = Annotator::new(source, &header, options);
+        annotator.add_definitions(&mut header);
+        let records = reader
+            .records()
+            .map(|record| {
+                let mut record = record.unwrap();
+                annotator.annotate(&mut record).unwrap();
+                record
+            })
+            .collect();
+        (header, records)
+    }
+
+    fn options(keys: &[&str], id: bool, matching: Matching, existing: Existing) -> Options {
+        Options {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            id,
+            matching,
+            existing,
+        }
+    }
+
+    #[test]
+    fn copies_values_by_allele() {
+        let source = VcfSource::new(Reader::new(SOURCE.as_bytes()).unwrap());
+        let options = options(&["AF", "DB"], true, Matching::Alleles, Existing::Overwrite);
+        let (header, records) = annotate(source, options);
+        assert_eq!(records[0].id, ["rs1", "rs2"]);
+        assert_eq!(records[0].info("AF"), Some(Some("0.4,0.2")));
+        assert_eq!(records[0].info("DB"), Some(None));
+        // CA and CAA are different alleles.
+        assert_eq!(records[1].info("AF"), None);
+        assert!(records[1].id.is_empty());
+        assert_eq!(records[3].info("AF"), Some(Some("0.1")));
+        assert!(header.meta[2].contains("Frequency in the source"));
+        assert!(header.definitions().info.contains_key("DB"));
+    }
+
+    #[test]
+    fn handles_existing_values() {
+        let source = || VcfSource::new(Reader::new(SOURCE.as_bytes()).unwrap());
+        let (_, records) = annotate(
+            source(),
+            options(&["AF"], true, Matching::Position, Existing::Keep),
+        );
+        assert_eq!(records[0].id, ["rs0"]);
+        assert_eq!(records[0].info("AF"), Some(Some("0.9,0.9")));
+        assert_eq!(records[1].info("AF"), Some(Some("0.5")));
+        assert_eq!(records[3].id, ["rs4"]);
+
+        let (_, records) = annotate(
+            source(),
+            options(&["AF"], true, Matching::Position, Existing::Clear),
+        );
+        assert_eq!(records[0].info("AF"), Some(Some("0.2")));
+        assert_eq!(records[3].info("AF"), None);
+        assert!(records[2].id.is_empty());
+    }
+
+    #[test]
+    fn overwriting_keeps_existing_values_of_alleles_the_source_lacks() {
+        let source = || {
+            let text = SOURCE.replace("2\t100\trs1\tA\tT\t.\t.\tAF=0.2;DB\n", "");
+            VcfSource::new(Reader::new(std::io::Cursor::new(text)).unwrap())
+        };
+        let (_, records) = annotate(
+            source(),
+            options(&["AF"], false, Matching::Alleles, Existing::Overwrite),
+        );
+        assert_eq!(records[0].info("AF"), Some(Some("0.4,0.9")));
+        let (_, records) = annotate(
+            source(),
+            options(&["AF"], false, Matching::Alleles, Existing::Clear),
+        );
+        assert_eq!(records[0].info("AF"), Some(Some("0.4,.")));
+    }
+
+    #[test]
+    fn reads_tables_and_bed_files() {
+        let table = "# a comment\n2\t100\tA\tT\tx\t7\n2\t160\tG\tC\ty\t8\n1\t50\tT\tC\tz\t.\n";
+        let columns = Column::parse_list("CHROM,POS,REF,ALT,-,INFO/SCORE");
+        assert_eq!(columns[5], Column::Info("SCORE".to_string()));
+        let source = TableSource::new(table.as_bytes(), columns).unwrap();
+        let (header, records) = annotate(
+            source,
+            options(&["SCORE"], false, Matching::Alleles, Existing::Overwrite),
+        );
+        let scores: Vec<_> = records.iter().map(|record| record.info("SCORE")).collect();
+        assert_eq!(scores, [Some(Some("7")), None, None, None]);
+        assert!(header
+            .to_string()
+            .contains("##INFO=<ID=SCORE,Number=.,Type=String"));
+
+        // The deletion at 2:300 covers 300-400.
+        let bed = "track name=x\n2\t99\t100\texon1\n2\t349\t360\texon2\n1\t0\t10\texon3\n";
+        let source = TableSource::bed(bed.as_bytes(), Column::parse_list("CHROM,FROM,TO,EXON"));
+        let (_, records) = annotate(
+            source.unwrap(),
+            options(&["EXON"], false, Matching::Overlap, Existing::Overwrite),
+        );
+        let exons: Vec<_> = records.iter().map(|record| record.info("EXON")).collect();
+        assert_eq!(
+            exons,
+            [Some(Some("exon1")), None, Some(Some("exon2")), None]
+        );
+
+        assert!(TableSource::new("".as_bytes(), Column::parse_list("CHROM,FROM")).is_err());
+        let unsorted = "2\t100\tA\n2\t90\tA\n";
+        let source = TableSource::new(unsorted.as_bytes(), Column::parse_list("CHROM,POS,ID"));
+        let mut reader = Reader::new(TARGET.as_bytes()).unwrap();
+        let options = options(&[], true, Matching::Position, Existing::Overwrite);
+        let mut annotator = Annotator::new(source.unwrap(), reader.header(), options);
+        let mut record = reader.read_record().unwrap().unwrap();
+        assert!(annotator.annotate(&mut record).is_err());
+    }
+
+    #[test]
+    fn matches_table_values_to_alleles_defined_by_the_header() {
+        let table = "2\t100\tA\tT\t0.2\n2\t100\tA\tG\t0.4\n";
+        let columns = Column::parse_list("CHROM,POS,REF,ALT,AF");
+        let source = TableSource::new(table.as_bytes(), columns).unwrap();
+        let (_, records) = annotate(
+            source,
+            options(&["AF"], false, Matching::Alleles, Existing::Overwrite),
+        );
+        assert_eq!(records[0].info("AF"), Some(Some("0.4,0.2")));
+    }
+
+    #[test]
+    fn skips_ahead_in_indexed_sources() {
+        use crate::tabix::{tests::indexed, Index, IndexedReader};
+
+        // The third line is out of order, so reading it fails.
+        let lines = [
+            "1\t100\trs1",
+            "1\t20000\trs2",
+            "1\t10\tunsorted",
+            "1\t70000\trs3",
+            "2\t5\trs4",
+        ];
+        let (data, index) = indexed(&lines, false);
+        let target = "##fileformat=VCFv4.3
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+1\t100\t.\tA\tG\t.\t.\t.
+1\t70000\t.\tA\tG\t.\t.\t.
+2\t5\t.\tA\tG\t.\t.\t.
+";
+        let options = options(&[], true, Matching::Position, Existing::Overwrite);
+        let annotate = |source: TableSource<_>| {
+            let mut reader = Reader::new(target.as_bytes()).unwrap();
+            let mut annotator = Annotator::new(source, reader.header(), options.clone());
+            let mut ids = Vec::new();
+            for record in reader.records() {
+                let mut record = record?;
+                annotator.annotate(&mut record)?;
+                ids.push(record.id.join(";"));
+            }
+            Ok::<_, VCFError>(ids)
+        };
+        let columns = Column::parse_list("CHROM,POS,ID");
+        let input = || {
+            IndexedReader::new(
+                io::Cursor::new(data.clone()),
+                Index::read(&index[..]).unwrap(),
+            )
+        };
+        let source = TableSource::new(input(), columns.clone()).unwrap();
+        assert!(annotate(source).is_err());
+        let source = TableSource::new(input(), columns).unwrap().indexed();
+        assert_eq!(annotate(source).unwrap(), ["rs1", "rs3", "rs4"]);
+    }
+}
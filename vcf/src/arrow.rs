@@ -0,0 +1,507 @@
+//! Converting records to Arrow record batches and writing them as Parquet.
+//!
+//! The schema follows the header:
+//!
+//! - the fixed columns `chrom`, `pos`, `id`, `ref`, `alt`, `qual` and `filter`, with the
+//!   semicolon- and comma-separated columns as lists;
+//! - a column `info_KEY` for each INFO definition, typed by its `Type` (Integer as Int32,
+//!   Float as Float32, Flag as Boolean, Character and String as Utf8), which is a list unless
+//!   the field has `Number=1`. Flags are false when absent, and other absent values are null;
+//! - a struct column `sample_NAME` for each sample, with a field for each FORMAT definition
+//!   typed in the same way.
+//!
+//! Values that do not match their definition are reported as errors, rather than written as
+//! nulls. INFO and FORMAT keys the header does not define are left out.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+use ::arrow::array::{
+    ArrayRef, BooleanBuilder, Float32Builder, Int32Builder, ListBuilder, StringBuilder,
+    StructArray, UInt64Builder,
+};
+use ::arrow::datatypes::{DataType as ArrowType, Field, Fields, Schema, SchemaRef};
+use ::arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::vcf::{Reader, VCFError, VCF};
+use crate::{DataType, InfoFormat, NumberField, Record};
+
+/// The default number of rows in a Parquet row group.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+fn other_error(error: impl std::error::Error + Send + Sync + 'static) -> VCFError {
+    VCFError::IoError(io::Error::other(error))
+}
+
+/// The Arrow type of an INFO or FORMAT field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ColumnType {
+    kind: Kind,
+    list: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Integer,
+    Float,
+    Flag,
+    String,
+}
+
+impl ColumnType {
+    fn new(definition: &InfoFormat) -> Self {
+        let kind = match definition.fieldtype {
+            DataType::Integer(_) => Kind::Integer,
+            DataType::Float(_) => Kind::Float,
+            DataType::Flag => Kind::Flag,
+            DataType::Character(_) | DataType::String(_) => Kind::String,
+        };
+        let list = !matches!(
+            definition.fieldtype.number(),
+            NumberField::Number(0) | NumberField::Number(1)
+        );
+        Self { kind, list }
+    }
+
+    fn arrow_type(self) -> ArrowType {
+        let item = match self.kind {
+            Kind::Integer => ArrowType::Int32,
+            Kind::Float => ArrowType::Float32,
+            Kind::Flag => ArrowType::Boolean,
+            Kind::String => ArrowType::Utf8,
+        };
+        match self.list {
+            true => ArrowType::List(Arc::new(Field::new_list_field(item, true))),
+            false => item,
+        }
+    }
+
+    fn builder(self) -> ColumnBuilder {
+        match (self.kind, self.list) {
+            (Kind::Flag, _) => ColumnBuilder::Flag(BooleanBuilder::new()),
+            (Kind::Integer, false) => ColumnBuilder::Integer(Int32Builder::new()),
+            (Kind::Integer, true) => {
+                ColumnBuilder::IntegerList(ListBuilder::new(Int32Builder::new()))
+            }
+            (Kind::Float, false) => ColumnBuilder::Float(Float32Builder::new()),
+            (Kind::Float, true) => {
+                ColumnBuilder::FloatList(ListBuilder::new(Float32Builder::new()))
+            }
+            (Kind::String, false) => ColumnBuilder::String(StringBuilder::new()),
+            (Kind::String, true) => {
+                ColumnBuilder::StringList(ListBuilder::new(StringBuilder::new()))
+            }
+        }
+    }
+}
+
+/// Builds the array of an INFO or FORMAT field from its values as text.
+enum ColumnBuilder {
+    Flag(BooleanBuilder),
+    Integer(Int32Builder),
+    IntegerList(ListBuilder<Int32Builder>),
+    Float(Float32Builder),
+    FloatList(ListBuilder<Float32Builder>),
+    String(StringBuilder),
+    StringList(ListBuilder<StringBuilder>),
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<Option<T>, ()> {
+    match value {
+        "." => Ok(None),
+        value => value.parse().map(Some).map_err(|_| ()),
+    }
+}
+
+impl ColumnBuilder {
+    /// Append a value: `None` when the field is absent, and `Some(None)` for a flag.
+    fn append(&mut self, value: Option<Option<&str>>) -> Result<(), ()> {
+        if let ColumnBuilder::Flag(builder) = self {
+            builder.append_value(value.is_some());
+            return Ok(());
+        }
+        let text = match value {
+            None | Some(None) | Some(Some(".")) => {
+                self.append_null();
+                return Ok(());
+            }
+            Some(Some(text)) => text,
+        };
+        let items = text.split(',');
+        match self {
+            ColumnBuilder::Flag(_) => unreachable!("flags are handled above"),
+            ColumnBuilder::Integer(builder) => builder.append_option(parse(text)?),
+            ColumnBuilder::Float(builder) => builder.append_option(parse(text)?),
+            ColumnBuilder::String(builder) => builder.append_value(text),
+            ColumnBuilder::IntegerList(builder) => {
+                for item in items {
+                    builder.values().append_option(parse(item)?);
+                }
+                builder.append(true);
+            }
+            ColumnBuilder::FloatList(builder) => {
+                for item in items {
+                    builder.values().append_option(parse(item)?);
+                }
+                builder.append(true);
+            }
+            ColumnBuilder::StringList(builder) => {
+                for item in items {
+                    match item {
+                        "." => builder.values().append_null(),
+                        item => builder.values().append_value(item),
+                    }
+                }
+                builder.append(true);
+            }
+        }
+        Ok(())
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            ColumnBuilder::Flag(builder) => builder.append_value(false),
+            ColumnBuilder::Integer(builder) => builder.append_null(),
+            ColumnBuilder::IntegerList(builder) => builder.append_null(),
+            ColumnBuilder::Float(builder) => builder.append_null(),
+            ColumnBuilder::FloatList(builder) => builder.append_null(),
+            ColumnBuilder::String(builder) => builder.append_null(),
+            ColumnBuilder::StringList(builder) => builder.append_null(),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::Flag(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Integer(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::IntegerList(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Float(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::FloatList(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::String(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::StringList(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+fn append_list(builder: &mut ListBuilder<StringBuilder>, items: &[String]) {
+    for item in items {
+        builder.values().append_value(item);
+    }
+    builder.append(true);
+}
+
+/// Converts records to record batches with a schema derived from their header.
+///
+/// ```
+/// use vcf::arrow::Converter;
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let source = b"##fileformat=VCFv4.3\
+///     \n##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">\
+///     \n##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\
+///     \n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\
+///     \n1\t100\t.\tA\tG,T\t50\tPASS\tAF=0.25,0.5\tGT\t0/1\n";
+/// let mut reader = Reader::new(&source[..])?;
+/// let converter = Converter::new(reader.header());
+/// let names: Vec<_> = converter.schema().fields().iter().map(|f| f.name().clone()).collect();
+/// assert_eq!(names, ["chrom", "pos", "id", "ref", "alt", "qual", "filter", "info_AF", "sample_A"]);
+///
+/// let batch = converter.read_batch(&mut reader, 1024)?.unwrap();
+/// assert_eq!(batch.num_rows(), 1);
+///# Ok::<(), VCFError>(())
+/// ```
+pub struct Converter {
+    schema: SchemaRef,
+    info: Vec<(String, ColumnType)>,
+    format: Vec<(String, ColumnType)>,
+    samples: usize,
+}
+
+impl Converter {
+    pub fn new(header: &VCF) -> Self {
+        let definitions = header.definitions();
+        // Keep the header order of the definitions.
+        let ids = |key: &str| -> Vec<String> {
+            header
+                .headers()
+                .filter(|header| header.key == key)
+                .filter_map(|header| header.id().map(str::to_string))
+                .collect()
+        };
+        let typed = |ids: Vec<String>, definitions: &HashMap<String, InfoFormat>| {
+            let mut columns: Vec<(String, ColumnType)> = Vec::new();
+            for id in ids {
+                if let Some(definition) = definitions.get(&id) {
+                    if !columns.iter().any(|(existing, _)| *existing == id) {
+                        columns.push((id, ColumnType::new(definition)));
+                    }
+                }
+            }
+            columns
+        };
+        let info = typed(ids("INFO"), &definitions.info);
+        let format = typed(ids("FORMAT"), &definitions.format);
+
+        let string_list = ArrowType::List(Arc::new(Field::new_list_field(ArrowType::Utf8, true)));
+        let mut fields = vec![
+            Field::new("chrom", ArrowType::Utf8, false),
+            Field::new("pos", ArrowType::UInt64, false),
+            Field::new("id", string_list.clone(), true),
+            Field::new("ref", ArrowType::Utf8, false),
+            Field::new("alt", string_list.clone(), true),
+            Field::new("qual", ArrowType::Float32, true),
+            Field::new("filter", string_list, true),
+        ];
+        for (key, column) in &info {
+            fields.push(Field::new(
+                format!("info_{}", key),
+                column.arrow_type(),
+                true,
+            ));
+        }
+        let sample_fields = Self::sample_fields(&format);
+        for sample in &header.samples {
+            fields.push(Field::new(
+                format!("sample_{}", sample),
+                ArrowType::Struct(sample_fields.clone()),
+                false,
+            ));
+        }
+        Self {
+            schema: Arc::new(Schema::new(fields)),
+            info,
+            format,
+            samples: header.samples.len(),
+        }
+    }
+
+    fn sample_fields(format: &[(String, ColumnType)]) -> Fields {
+        format
+            .iter()
+            .map(|(key, column)| Field::new(key, column.arrow_type(), true))
+            .collect()
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Convert records to a record batch.
+    pub fn to_batch(&self, records: &[Record]) -> Result<RecordBatch, VCFError> {
+        let mut chrom = StringBuilder::new();
+        let mut pos = UInt64Builder::new();
+        let mut id = ListBuilder::new(StringBuilder::new());
+        let mut reference = StringBuilder::new();
+        let mut alt = ListBuilder::new(StringBuilder::new());
+        let mut qual = Float32Builder::new();
+        let mut filter = ListBuilder::new(StringBuilder::new());
+        let mut info: Vec<ColumnBuilder> = self.info.iter().map(|(_, c)| c.builder()).collect();
+        let mut samples: Vec<Vec<ColumnBuilder>> = (0..self.samples)
+            .map(|_| self.format.iter().map(|(_, c)| c.builder()).collect())
+            .collect();
+
+        for record in records {
+            let invalid = |kind: &str, key: &str| {
+                VCFError::InvalidRecord(format!(
+                    "{} value of {} at {}:{} does not match its definition",
+                    kind, key, record.chrom, record.pos
+                ))
+            };
+            chrom.append_value(&record.chrom);
+            pos.append_value(record.pos);
+            append_list(&mut id, &record.id);
+            reference.append_value(&record.reference);
+            append_list(&mut alt, &record.alt);
+            qual.append_option(record.qual.map(|qual| qual as f32));
+            append_list(&mut filter, &record.filter);
+            for ((key, _), builder) in self.info.iter().zip(&mut info) {
+                builder
+                    .append(record.info(key))
+                    .map_err(|_| invalid("INFO", key))?;
+            }
+            for (sample, builders) in samples.iter_mut().enumerate() {
+                for ((key, _), builder) in self.format.iter().zip(builders) {
+                    let value = record.sample_value(sample, key).map(Some);
+                    builder.append(value).map_err(|_| invalid("FORMAT", key))?;
+                }
+            }
+        }
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(chrom.finish()),
+            Arc::new(pos.finish()),
+            Arc::new(id.finish()),
+            Arc::new(reference.finish()),
+            Arc::new(alt.finish()),
+            Arc::new(qual.finish()),
+            Arc::new(filter.finish()),
+        ];
+        columns.extend(info.iter_mut().map(ColumnBuilder::finish));
+        let sample_fields = Self::sample_fields(&self.format);
+        for builders in &mut samples {
+            let arrays = builders.iter_mut().map(ColumnBuilder::finish).collect();
+            let array = match sample_fields.is_empty() {
+                true => StructArray::new_empty_fields(records.len(), None),
+                false => StructArray::try_new(sample_fields.clone(), arrays, None)
+                    .map_err(other_error)?,
+            };
+            columns.push(Arc::new(array));
+        }
+        RecordBatch::try_new(self.schema(), columns).map_err(other_error)
+    }
+
+    /// Read up to `size` records into a record batch, or `None` at the end of the input.
+    pub fn read_batch<R: BufRead>(
+        &self,
+        reader: &mut Reader<R>,
+        size: usize,
+    ) -> Result<Option<RecordBatch>, VCFError> {
+        let mut records = Vec::with_capacity(size.min(DEFAULT_ROW_GROUP_SIZE));
+        while records.len() < size {
+            match reader.read_record()? {
+                Some(record) => records.push(record),
+                None => break,
+            }
+        }
+        match records.is_empty() {
+            true => Ok(None),
+            false => self.to_batch(&records).map(Some),
+        }
+    }
+}
+
+/// Write records with the header `header` as Snappy-compressed Parquet, with
+/// `row_group_size` rows in each row group. A row group size of 0 is an error.
+pub fn write_parquet<W: Write + Send>(
+    header: &VCF,
+    records: impl IntoIterator<Item = Result<Record, VCFError>>,
+    output: W,
+    row_group_size: usize,
+) -> Result<(), VCFError> {
+    if row_group_size == 0 {
+        return Err(VCFError::InvalidExpression(
+            "the row group size must be at least 1".to_string(),
+        ));
+    }
+    let converter = Converter::new(header);
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(row_group_size)
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(output, converter.schema(), Some(properties)).map_err(other_error)?;
+    let mut batch = Vec::with_capacity(row_group_size.min(DEFAULT_ROW_GROUP_SIZE));
+    for record in records {
+        batch.push(record?);
+        if batch.len() == row_group_size {
+            writer
+                .write(&converter.to_batch(&batch)?)
+                .map_err(other_error)?;
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        writer
+            .write(&converter.to_batch(&batch)?)
+            .map_err(other_error)?;
+    }
+    writer.close().map_err(other_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::arrow::array::{Array, AsArray};
+    use ::arrow::datatypes::{Float32Type, Int32Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">
+##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Allele depths\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB
+1\t100\trs1\tA\tG\t50\tPASS\tDP=12;AF=0.5;DB\tGT:AD\t0/1:5,.\t./.
+1\t200\t.\tC\tT,G\t.\t.\tAF=.,0.1\tGT:AD\t1/1:0,9,1\t0/0:.
+";
+
+    #[test]
+    fn converts_records_to_typed_columns() {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        let converter = Converter::new(reader.header());
+        let batch = converter.read_batch(&mut reader, 10).unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+
+        let dp = batch.column_by_name("info_DP").unwrap();
+        let dp = dp.as_primitive::<Int32Type>();
+        assert_eq!((dp.value(0), dp.is_null(1)), (12, true));
+        let db = batch.column_by_name("info_DB").unwrap().as_boolean();
+        assert_eq!((db.value(0), db.value(1)), (true, false));
+        let af = batch.column_by_name("info_AF").unwrap().as_list::<i32>();
+        let second = af.value(1);
+        let second = second.as_primitive::<Float32Type>();
+        assert!(second.is_null(0));
+        assert_eq!(second.value(1), 0.1);
+        let qual = batch.column_by_name("qual").unwrap();
+        assert!(qual.is_null(1));
+
+        let b = batch.column_by_name("sample_B").unwrap().as_struct();
+        assert_eq!(
+            b.column_by_name("GT").unwrap().as_string::<i32>().value(0),
+            "./."
+        );
+        assert!(b.column_by_name("AD").unwrap().is_null(0));
+        let a = batch.column_by_name("sample_A").unwrap().as_struct();
+        let ad = a.column_by_name("AD").unwrap().as_list::<i32>().value(1);
+        assert_eq!(ad.as_primitive::<Int32Type>().values(), &[0, 9, 1]);
+    }
+
+    #[test]
+    fn rejects_values_that_do_not_match_their_type() {
+        let source = SOURCE.replace("DP=12", "DP=high");
+        let mut reader = Reader::new(source.as_bytes()).unwrap();
+        let converter = Converter::new(reader.header());
+        let error = converter.read_batch(&mut reader, 10).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid record: INFO value of DP at 1:100 does not match its definition"
+        );
+    }
+
+    #[test]
+    fn writes_parquet_row_groups() {
+        let records: String = (1..=5)
+            .map(|pos| {
+                format!(
+                    "1\t{}\t.\tA\tG\t.\t.\tDP={}\tGT:AD\t0/1:1,2\t./.\n",
+                    pos, pos
+                )
+            })
+            .collect();
+        let header: String = SOURCE
+            .lines()
+            .take(7)
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let source = header + &records;
+        let mut file = tempfile::tempfile().unwrap();
+        let mut reader = Reader::new(source.as_bytes()).unwrap();
+        let header = reader.header().clone();
+        assert!(write_parquet(&header, Vec::new(), Vec::new(), 0).is_err());
+        write_parquet(&header, reader.records(), &mut file, 2).unwrap();
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        let rows: usize = builder
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum();
+        assert_eq!(rows, 5);
+    }
+}
@@ -0,0 +1,506 @@
+//! Reading and writing BGZF, the blocked gzip format of compressed, indexable VCF files.
+//!
+//! A BGZF file is a series of gzip members holding at most 64 KiB each, with the compressed size
+//! of every member stored in a `BC` extra field so that blocks can be found and copied without
+//! decompressing them. An empty block marks the end of the file.
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+use crate::parallel::{map_ordered, Ordered};
+
+/// The largest size of a compressed block.
+pub const MAX_BLOCK_SIZE: usize = 65536;
+
+/// The most uncompressed data written to one block, leaving room for data that does not
+/// compress.
+const BLOCK_DATA_SIZE: usize = 0xff00;
+
+/// The empty block that ends a BGZF file.
+pub const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Whether `bytes` start with the gzip magic number.
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0x1f, 0x8b])
+}
+
+/// Whether `bytes` start with a BGZF block header.
+pub fn is_bgzf(bytes: &[u8]) -> bool {
+    bytes.len() >= 16 && bytes[..4] == [0x1f, 0x8b, 0x08, 0x04] && &bytes[12..14] == b"BC"
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("BGZF: {}", message))
+}
+
+/// A compressed block, header and footer included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    bytes: Vec<u8>,
+    /// The offset of the compressed data.
+    data_start: usize,
+}
+
+impl Block {
+    /// Read the next block, or `None` at the end of the input. Input that ends partway through
+    /// a block is an error.
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut bytes = vec![0; 12];
+        let mut read = 0;
+        while read < bytes.len() {
+            match reader.read(&mut bytes[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(length) => read += length,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        loop {
+            let length = Self::length(&bytes)?;
+            if length == bytes.len() {
+                return Ok(Some(Self::from_bytes(bytes)));
+            }
+            let read = bytes.len();
+            bytes.resize(length, 0);
+            reader.read_exact(&mut bytes[read..])?;
+        }
+    }
+
+    /// How many bytes the block starting with `bytes` has, as far as can be told from them:
+    /// the length of its header until the header is complete, then the length of the block.
+    pub(crate) fn length(bytes: &[u8]) -> io::Result<usize> {
+        if bytes.len() < 12 {
+            return Ok(12);
+        }
+        if bytes[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+            return Err(invalid("not a BGZF block"));
+        }
+        let data_start = 12 + u16::from_le_bytes([bytes[10], bytes[11]]) as usize;
+        if bytes.len() < data_start {
+            return Ok(data_start);
+        }
+
+        let mut block_size = None;
+        let mut extra = &bytes[12..data_start];
+        while extra.len() >= 4 {
+            let length = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+            if &extra[..2] == b"BC" && length == 2 && extra.len() >= 6 {
+                block_size = Some(u16::from_le_bytes([extra[4], extra[5]]) as usize + 1);
+            }
+            extra = extra.get(4 + length..).unwrap_or_default();
+        }
+        let block_size = block_size.ok_or_else(|| invalid("block size missing"))?;
+        if block_size < data_start + 8 {
+            return Err(invalid("block size too small"));
+        }
+        Ok(block_size)
+    }
+
+    /// Wrap the bytes of a whole block, once [`Block::length`] is their length.
+    pub(crate) fn from_bytes(bytes: Vec<u8>) -> Self {
+        let data_start = 12 + u16::from_le_bytes([bytes[10], bytes[11]]) as usize;
+        Self { bytes, data_start }
+    }
+
+    /// Compress up to 64 KiB of data into a block.
+    pub fn compress(data: &[u8], level: Compression) -> io::Result<Self> {
+        let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len() / 2), level);
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        let block_size = 18 + compressed.len() + 8;
+        if block_size > MAX_BLOCK_SIZE {
+            return Err(invalid("data does not fit in a block"));
+        }
+        let mut crc = Crc::new();
+        crc.update(data);
+
+        let mut bytes = Vec::with_capacity(block_size);
+        bytes.extend_from_slice(&EOF_BLOCK[..16]);
+        bytes.extend_from_slice(&(block_size as u16 - 1).to_le_bytes());
+        bytes.extend_from_slice(&compressed);
+        bytes.extend_from_slice(&crc.sum().to_le_bytes());
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        Ok(Self {
+            bytes,
+            data_start: 18,
+        })
+    }
+
+    /// The block as written to a file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The size of the data once decompressed.
+    pub fn uncompressed_size(&self) -> usize {
+        let footer = &self.bytes[self.bytes.len() - 4..];
+        u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]) as usize
+    }
+
+    /// Decompress the block, checking its CRC.
+    pub fn decompress(&self) -> io::Result<Vec<u8>> {
+        let footer = self.bytes.len() - 8;
+        let mut data = Vec::with_capacity(self.uncompressed_size());
+        DeflateDecoder::new(&self.bytes[self.data_start..footer]).read_to_end(&mut data)?;
+        let mut crc = Crc::new();
+        crc.update(&data);
+        let expected = &self.bytes[footer..footer + 4];
+        if crc.sum().to_le_bytes() != expected || data.len() != self.uncompressed_size() {
+            return Err(invalid("checksum mismatch"));
+        }
+        Ok(data)
+    }
+}
+
+/// Decompresses a BGZF stream one block at a time.
+///
+/// Positions in the stream are given as virtual offsets, as in indexes: the offset of a block
+/// in the compressed file shifted left 16 bits, plus an offset in its uncompressed data.
+pub struct Reader<R> {
+    inner: R,
+    data: Vec<u8>,
+    position: usize,
+    /// The offset of the current block in the input, and its compressed size.
+    block_offset: u64,
+    block_size: u64,
+}
+
+impl<R: Read> Reader<R> {
+    /// Read a stream that starts at the current position of `inner`, which is taken to be
+    /// offset 0.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            data: Vec::new(),
+            position: 0,
+            block_offset: 0,
+            block_size: 0,
+        }
+    }
+
+    /// The virtual offset of the next byte to be read.
+    pub fn virtual_offset(&self) -> u64 {
+        if self.position == self.data.len() {
+            (self.block_offset + self.block_size) << 16
+        } else {
+            self.block_offset << 16 | self.position as u64
+        }
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Move to a virtual offset, such as one taken from an index.
+    pub fn seek_virtual(&mut self, virtual_offset: u64) -> io::Result<()> {
+        let (block_offset, position) = (virtual_offset >> 16, (virtual_offset & 0xffff) as usize);
+        self.inner.seek(SeekFrom::Start(block_offset))?;
+        self.data.clear();
+        self.position = 0;
+        self.block_offset = block_offset;
+        self.block_size = 0;
+        if position > 0 {
+            self.fill_buf()?;
+            if position > self.data.len() {
+                return Err(invalid("virtual offset past the end of its block"));
+            }
+            self.position = position;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Ok(length)
+    }
+}
+
+impl<R: Read> BufRead for Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.position == self.data.len() {
+            match Block::read_from(&mut self.inner)? {
+                Some(block) => {
+                    self.data = block.decompress()?;
+                    self.position = 0;
+                    self.block_offset += self.block_size;
+                    self.block_size = block.as_bytes().len() as u64;
+                }
+                None => break,
+            }
+        }
+        Ok(&self.data[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.data.len());
+    }
+}
+
+/// Reads blocks until the end of the input or the first error.
+struct Blocks<R> {
+    inner: R,
+    done: bool,
+}
+
+impl<R: Read> Iterator for Blocks<R> {
+    type Item = io::Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let block = Block::read_from(&mut self.inner).transpose();
+        self.done = !matches!(block, Some(Ok(_)));
+        block
+    }
+}
+
+/// Decompresses a BGZF stream on a pool of threads, returning the same data as [`Reader`].
+///
+/// The compressed blocks are read on a thread of their own and handed to `threads` worker
+/// threads; the decompressed blocks are put back in order.
+pub struct ParallelReader {
+    blocks: Ordered<io::Result<Vec<u8>>>,
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl ParallelReader {
+    pub fn new<R: Read + Send + 'static>(inner: R, threads: usize) -> Self {
+        let blocks = Blocks { inner, done: false };
+        Self {
+            blocks: map_ordered(blocks, threads, |block| block?.decompress()),
+            data: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for ParallelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Ok(length)
+    }
+}
+
+impl BufRead for ParallelReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.position == self.data.len() {
+            match self.blocks.next() {
+                Some(data) => {
+                    self.data = data?;
+                    self.position = 0;
+                }
+                None => break,
+            }
+        }
+        Ok(&self.data[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.data.len());
+    }
+}
+
+/// Compresses data into BGZF blocks.
+///
+/// The end-of-file block is written by [`Writer::finish`], or when the writer is dropped.
+pub struct Writer<W: Write> {
+    inner: Option<W>,
+    buffer: Vec<u8>,
+    level: Compression,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_level(inner, Compression::default())
+    }
+
+    pub fn with_level(inner: W, level: Compression) -> Self {
+        Self {
+            inner: Some(inner),
+            buffer: Vec::with_capacity(BLOCK_DATA_SIZE),
+            level,
+        }
+    }
+
+    fn inner(&mut self) -> &mut W {
+        self.inner.as_mut().expect("writer used after finish")
+    }
+
+    fn write_buffered(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let block = Block::compress(&self.buffer, self.level)?;
+            self.buffer.clear();
+            self.inner().write_all(block.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Write an already compressed block, after any data written before it.
+    pub fn write_block(&mut self, block: &Block) -> io::Result<()> {
+        self.write_buffered()?;
+        self.inner().write_all(block.as_bytes())
+    }
+
+    /// Write the remaining data and the end-of-file block, and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_buffered()?;
+        let mut inner = self.inner.take().expect("writer used after finish");
+        inner.write_all(&EOF_BLOCK)?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let length = buf.len().min(BLOCK_DATA_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..length]);
+        if self.buffer.len() == BLOCK_DATA_SIZE {
+            self.write_buffered()?;
+        }
+        Ok(length)
+    }
+
+    /// Compress the data written so far into a block, so that it can be read back.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffered()?;
+        self.inner().flush()
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_buffered();
+            if let Some(mut inner) = self.inner.take() {
+                let _ = inner.write_all(&EOF_BLOCK);
+                let _ = inner.flush();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+
+    #[test]
+    fn round_trips_through_blocks() {
+        let data: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(&data).unwrap();
+        let compressed = writer.finish().unwrap();
+        assert!(is_bgzf(&compressed));
+        assert!(compressed.ends_with(&EOF_BLOCK));
+
+        let mut decoded = Vec::new();
+        Reader::new(&compressed[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let mut decoded = Vec::new();
+        ParallelReader::new(io::Cursor::new(compressed.clone()), 4)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        // Any gzip reader can read BGZF.
+        let mut decoded = Vec::new();
+        MultiGzDecoder::new(&compressed[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn seeks_to_virtual_offsets() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(&data).unwrap();
+        let compressed = writer.finish().unwrap();
+
+        let mut reader = Reader::new(&compressed[..]);
+        let mut offsets = Vec::new();
+        for position in [0, 1000, BLOCK_DATA_SIZE, 150_000] {
+            let mut skipped = vec![0; position - offsets.last().map_or(0, |(p, _)| *p)];
+            reader.read_exact(&mut skipped).unwrap();
+            offsets.push((position, reader.virtual_offset()));
+        }
+        // The end of a block is the start of the next one.
+        assert_eq!(offsets[2].1 & 0xffff, 0);
+        assert!(offsets[2].1 > 0);
+        let mut reader = Reader::new(io::Cursor::new(compressed.clone()));
+        for (position, offset) in offsets.into_iter().rev() {
+            reader.seek_virtual(offset).unwrap();
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, data[position..]);
+        }
+    }
+
+    #[test]
+    fn copies_blocks_unchanged() {
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(b"first\n").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"second\n").unwrap();
+        let compressed = writer.finish().unwrap();
+
+        let mut input = &compressed[..];
+        let mut blocks = Vec::new();
+        while let Some(block) = Block::read_from(&mut input).unwrap() {
+            blocks.push(block);
+        }
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].decompress().unwrap(), b"first\n");
+        assert_eq!(blocks[2].as_bytes(), EOF_BLOCK);
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(b"zeroth\n").unwrap();
+        writer.write_block(&blocks[1]).unwrap();
+        let copied = writer.finish().unwrap();
+        let mut decoded = String::new();
+        Reader::new(&copied[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "zeroth\nsecond\n");
+    }
+
+    #[test]
+    fn rejects_corrupt_blocks() {
+        let mut compressed = Writer::new(Vec::new()).finish().unwrap();
+        assert!(Block::read_from(&mut &compressed[..5]).is_err());
+        assert!(Block::read_from(&mut &compressed[..20]).is_err());
+        assert!(Block::read_from(&mut &compressed[..0]).unwrap().is_none());
+        compressed[0] = 0;
+        assert!(Block::read_from(&mut &compressed[..]).is_err());
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(b"data").unwrap();
+        let mut compressed = writer.finish().unwrap();
+        compressed[26] ^= 1;
+        let block = Block::read_from(&mut &compressed[..]).unwrap().unwrap();
+        assert!(block.decompress().is_err());
+        assert!(ParallelReader::new(io::Cursor::new(compressed), 2)
+            .read_to_end(&mut Vec::new())
+            .is_err());
+    }
+}
@@ -0,0 +1,285 @@
+//! Combining single-sample gVCFs into one multi-sample gVCF.
+//!
+//! The inputs are streamed together, one record per input at a time, so memory use depends on
+//! the number of inputs and not on the size of the genome. Reference blocks are split wherever
+//! another input starts or ends a record, so that every output record describes the same
+//! interval for all samples.
+use std::io::{BufRead, Write};
+
+use crate::fasta::ReferenceSequence;
+use crate::gvcf::is_reference_block;
+use crate::merge::{merge_headers, next_position, Input};
+use crate::multiallelic::{format_keys, merge_info, no_call, remap_sample, MergedAlleles};
+use crate::vcf::{Reader, VCFError};
+use crate::{ContigOrder, Definitions, Record};
+
+/// Combine position-sorted gVCFs into a multi-sample gVCF written to `output`.
+///
+/// At a variant site, the ALT alleles of all inputs are merged with `<NON_REF>` (or `<*>`) kept
+/// last, GT and Number=A/R/G FORMAT values are renumbered, and values for alleles a sample has
+/// no record of are taken from its `<NON_REF>` allele. Reference blocks overlapping a variant
+/// site contribute their genotype to it and continue after it. Samples without a record at a
+/// position are written as no-calls. Variants that overlap without starting at the same
+/// position are written as separate, overlapping records.
+///
+/// The reference is used to fill in REF where a reference block is split.
+pub fn combine_gvcfs<R: BufRead>(
+    readers: Vec<Reader<R>>,
+    reference: &mut impl ReferenceSequence,
+    mut output: impl Write,
+) -> Result<(), VCFError> {
+    let header = merge_headers(&readers.iter().map(Reader::header).collect::<Vec<_>>())?;
+    let definitions = header.definitions();
+    let mut order = ContigOrder::new(header.contigs());
+    write!(output, "{}", header)?;
+
+    let mut inputs = readers
+        .into_iter()
+        .map(|reader| Input::new(reader, &mut order))
+        .collect::<Result<Vec<_>, VCFError>>()?;
+
+    while let Some((chrom, pos)) = next_position(&inputs, &order) {
+        let starts_here =
+            |record: &Option<Record>| matches!(record, Some(r) if r.chrom == chrom && r.pos == pos);
+        let active: Vec<bool> = inputs
+            .iter()
+            .map(|input| starts_here(&input.current))
+            .collect();
+        let is_variant = inputs
+            .iter()
+            .zip(&active)
+            .any(|(input, &active)| active && !is_reference_block(input.current.as_ref().unwrap()));
+
+        let end = if is_variant {
+            pos
+        } else {
+            // The block ends where the first active block does, or before the next record.
+            inputs
+                .iter()
+                .filter_map(|input| input.current.as_ref())
+                .filter(|record| record.chrom == chrom)
+                .map(|record| match record.pos {
+                    start if start == pos => record.interval().end,
+                    start => start - 1,
+                })
+                .min()
+                .unwrap_or(pos)
+        };
+
+        let records: Vec<Option<&Record>> = inputs
+            .iter()
+            .zip(&active)
+            .map(|(input, &active)| input.current.as_ref().filter(|_| active))
+            .collect();
+        let samples: Vec<usize> = inputs.iter().map(|input| input.samples).collect();
+        let record = if is_variant {
+            combine_variant(&records, &samples, &definitions)
+        } else {
+            combine_block(&records, &samples, pos, end)
+        };
+        writeln!(output, "{}", record)?;
+
+        for (input, &active) in inputs.iter_mut().zip(&active) {
+            let Some(current) = input.current.as_mut().filter(|_| active) else {
+                continue;
+            };
+            let block_end = current.interval().end;
+            if is_reference_block(current) && block_end > end {
+                current.pos = end + 1;
+                current.reference = reference.fetch(&current.chrom, end + 1, end + 1)?;
+                if current.info("END").is_none() {
+                    current.set_info("END", Some(block_end.to_string()));
+                }
+            } else {
+                input.advance(&mut order)?;
+            }
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+fn combine_block(records: &[Option<&Record>], samples: &[usize], pos: u64, end: u64) -> Record {
+    let first = records
+        .iter()
+        .flatten()
+        .next()
+        .expect("a block has records");
+    let keys = format_keys(records.iter().flatten().copied());
+    let columns = records
+        .iter()
+        .zip(samples)
+        .flat_map(|(record, &count)| {
+            (0..count).map(|sample| match record {
+                Some(record) => keys
+                    .iter()
+                    .map(|key| record.sample_value(sample, key).unwrap_or(".").to_string())
+                    .collect(),
+                None => no_call(&keys),
+            })
+        })
+        .collect();
+    Record {
+        chrom: first.chrom.clone(),
+        pos,
+        id: Vec::new(),
+        reference: first.reference[..1].to_string(),
+        alt: first.alt.clone(),
+        qual: None,
+        qual_text: None,
+        filter: Vec::new(),
+        info: vec![("END".to_string(), Some(end.to_string()))],
+        format: keys,
+        samples: columns,
+    }
+}
+
+fn combine_variant(
+    records: &[Option<&Record>],
+    samples: &[usize],
+    definitions: &Definitions,
+) -> Record {
+    let present: Vec<&Record> = records.iter().flatten().copied().collect();
+    let first = present[0];
+    let merged = MergedAlleles::new(&present);
+    let allele_count = merged.allele_count();
+
+    let mut id: Vec<String> = Vec::new();
+    let mut info: Vec<(String, Option<String>)> = Vec::new();
+    for (record, map) in present.iter().zip(&merged.maps) {
+        for value in &record.id {
+            if !id.contains(value) {
+                id.push(value.clone());
+            }
+        }
+        merge_info(&mut info, record, map, allele_count, definitions);
+    }
+    info.retain(|(key, _)| key != "END");
+
+    let format = format_keys(present.iter().copied());
+    let mut maps = merged.maps.iter();
+    let columns = records
+        .iter()
+        .zip(samples)
+        .flat_map(|(record, &count)| {
+            let map = record.and_then(|_| maps.next());
+            let format = &format;
+            (0..count).map(move |sample| match (record, map) {
+                (Some(record), Some(map)) => {
+                    remap_sample(record, sample, format, map, allele_count, definitions)
+                }
+                _ => no_call(format),
+            })
+        })
+        .collect();
+
+    Record {
+        chrom: first.chrom.clone(),
+        pos: first.pos,
+        id,
+        reference: merged.reference,
+        alt: merged.alt,
+        qual: None,
+        qual_text: None,
+        filter: Vec::new(),
+        info,
+        format,
+        samples: columns,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use super::*;
+
+    const HEADER: &str = "##fileformat=VCFv4.2
+##contig=<ID=1,length=20>
+##INFO=<ID=END,Number=1,Type=Integer,Description=\"End\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Allele depths\">
+##FORMAT=<ID=PL,Number=G,Type=Integer,Description=\"Likelihoods\">
+";
+
+    fn reader(sample: &str, records: &[&str]) -> Reader<Cursor<Vec<u8>>> {
+        let text = format!(
+            "{}#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}\n{}\n",
+            HEADER,
+            sample,
+            records.join("\n")
+        );
+        Reader::new(Cursor::new(text.into_bytes())).unwrap()
+    }
+
+    fn combined(readers: Vec<Reader<Cursor<Vec<u8>>>>) -> Vec<String> {
+        let mut reference = HashMap::from([("1".to_string(), "ACGTACGTACGTACGTACGT".to_string())]);
+        let mut output = Vec::new();
+        combine_gvcfs(readers, &mut reference, &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn splits_blocks_at_variants() {
+        let a = reader("A", &["1\t1\t.\tA\t<NON_REF>\t.\t.\tEND=10\tGT:DP\t0/0:10"]);
+        let b = reader(
+            "B",
+            &[
+                "1\t1\t.\tA\t<NON_REF>\t.\t.\tEND=4\tGT:DP\t0/0:20",
+                "1\t5\t.\tA\tG,<NON_REF>\t.\t.\t.\tGT:AD:PL\t0/1:3,4,0:50,0,60,90,90,90",
+                "1\t6\t.\tC\t<NON_REF>\t.\t.\tEND=12\tGT:DP\t0/0:30",
+            ],
+        );
+        assert_eq!(
+            combined(vec![a, b]),
+            vec![
+                "1\t1\t.\tA\t<NON_REF>\t.\t.\tEND=4\tGT:DP\t0/0:10\t0/0:20",
+                "1\t5\t.\tA\tG,<NON_REF>\t.\t.\t.\tGT:DP:AD:PL\t0/0:10:.:.\t0/1:.:3,4,0:50,0,60,90,90,90",
+                "1\t6\t.\tC\t<NON_REF>\t.\t.\tEND=10\tGT:DP\t0/0:10\t0/0:30",
+                "1\t11\t.\tG\t<NON_REF>\t.\t.\tEND=12\tGT:DP\t./.:.\t0/0:30",
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_alleles_and_renumbers_fields() {
+        let a = reader(
+            "A",
+            &["1\t3\t.\tG\tT,<NON_REF>\t.\t.\t.\tGT:AD:PL\t1/1:0,9,0:90,30,0,91,31,92"],
+        );
+        let b = reader(
+            "B",
+            &["1\t3\t.\tGT\tG,<NON_REF>\t.\t.\t.\tGT:AD:PL\t0/1:5,5,1:40,0,40,70,70,99"],
+        );
+        assert_eq!(
+            combined(vec![a, b]),
+            vec![
+                "1\t3\t.\tGT\tTT,G,<NON_REF>\t.\t.\t.\tGT:AD:PL\t1/1:0,9,0,0:90,30,0,91,31,92,91,31,92,92\t0/2:5,1,5,1:40,70,99,0,70,40,70,99,70,99"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_samples_and_unsorted_input() {
+        let a = reader("A", &["1\t1\t.\tA\t<NON_REF>\t.\t.\tEND=2\tGT\t0/0"]);
+        let b = reader("A", &["1\t1\t.\tA\t<NON_REF>\t.\t.\tEND=2\tGT\t0/0"]);
+        let mut reference = HashMap::from([("1".to_string(), "ACGTACGT".to_string())]);
+        assert!(combine_gvcfs(vec![a, b], &mut reference, Vec::new()).is_err());
+
+        let a = reader(
+            "A",
+            &[
+                "1\t5\t.\tA\t<NON_REF>\t.\t.\tEND=6\tGT\t0/0",
+                "1\t2\t.\tC\t<NON_REF>\t.\t.\tEND=3\tGT\t0/0",
+            ],
+        );
+        assert!(combine_gvcfs(vec![a], &mut reference, Vec::new()).is_err());
+    }
+}
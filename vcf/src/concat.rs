@@ -0,0 +1,371 @@
+//! Concatenating VCFs that cover consecutive parts of the genome, such as the per-chromosome
+//! outputs of a scattered workflow.
+//!
+//! The inputs must have the same samples and compatible headers, and each input must start at
+//! or after the position where the one before it ends.
+use std::collections::VecDeque;
+use std::io::{BufRead, Read, Write};
+
+use crate::bgzf::{self, Block};
+use crate::merge::merge_meta;
+use crate::vcf::{Reader, VCFError, VCF};
+use crate::{ContigOrder, Record};
+
+/// The most blocks kept back, when copying blocks, to find the last record of an input.
+const TAIL_BLOCKS: usize = 8;
+
+/// The header of the concatenated file: the meta lines of all inputs, without repeating lines
+/// with the same key and ID, and their samples.
+///
+/// Every input must have the same samples in the same order, and INFO and FORMAT lines with
+/// the same ID must agree on Number and Type.
+pub fn concat_headers(headers: &[&VCF]) -> Result<VCF, VCFError> {
+    let mut conflicts = Vec::new();
+    let mut concatenated = merge_meta(headers, &mut conflicts);
+    concatenated.samples = headers
+        .first()
+        .map(|header| header.samples.clone())
+        .unwrap_or_default();
+    for (index, header) in headers.iter().enumerate().skip(1) {
+        if header.samples != concatenated.samples {
+            conflicts.push(format!(
+                "the samples of input {} differ from those of input 1",
+                index + 1
+            ));
+        }
+    }
+    if conflicts.is_empty() {
+        Ok(concatenated)
+    } else {
+        Err(VCFError::InvalidHeader(conflicts.join("; ")))
+    }
+}
+
+/// Checks that each input starts at or after the end of the one before.
+struct Boundaries {
+    order: ContigOrder,
+    last: Option<(String, u64)>,
+    input: usize,
+}
+
+impl Boundaries {
+    fn new(header: &VCF) -> Self {
+        Self {
+            order: ContigOrder::new(header.contigs()),
+            last: None,
+            input: 0,
+        }
+    }
+
+    /// Start the next input, given its first record.
+    fn start(&mut self, first: Option<&Record>) -> Result<(), VCFError> {
+        self.input += 1;
+        if let (Some((chrom, pos)), Some(first)) = (&self.last, first) {
+            let ordering = self
+                .order
+                .compare(chrom, &first.chrom)
+                .then(pos.cmp(&first.pos));
+            if ordering.is_gt() {
+                return Err(VCFError::InvalidRecord(format!(
+                    "input {} starts at {}:{}, before the end of the input before it at {}:{}",
+                    self.input, first.chrom, first.pos, chrom, pos
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// End the current input, given its last record.
+    fn end(&mut self, last: Option<&Record>) {
+        if let Some(last) = last {
+            self.last = Some((last.chrom.clone(), last.pos));
+        }
+    }
+}
+
+/// Concatenate VCFs in order, writing the records of each in turn to `output`.
+///
+/// ```
+/// use std::io::Cursor;
+/// use vcf::concat::concat;
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let input = |record: &str| {
+///     let text = format!(
+///         "##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n{}\n",
+///         record
+///     );
+///     Reader::new(Cursor::new(text.into_bytes()))
+/// };
+/// let mut output = Vec::new();
+/// concat(vec![input("1\t100\t.\tA\tG\t.\t.\t.")?, input("2\t50\t.\tC\tT\t.\t.\t.")?], &mut output)?;
+/// assert!(String::from_utf8(output).unwrap().ends_with("INFO\n1\t100\t.\tA\tG\t.\t.\t.\n2\t50\t.\tC\tT\t.\t.\t.\n"));
+///
+/// let mut output = Vec::new();
+/// let unsorted = vec![input("2\t50\t.\tC\tT\t.\t.\t.")?, input("1\t100\t.\tA\tG\t.\t.\t.")?];
+/// assert!(concat(unsorted, &mut output).is_err());
+///# Ok::<(), VCFError>(())
+/// ```
+pub fn concat<R: BufRead>(
+    mut readers: Vec<Reader<R>>,
+    mut output: impl Write,
+) -> Result<(), VCFError> {
+    let header = concat_headers(&readers.iter().map(Reader::header).collect::<Vec<_>>())?;
+    write!(output, "{}", header)?;
+    let mut boundaries = Boundaries::new(&header);
+    for reader in &mut readers {
+        let mut record = reader.read_record()?;
+        boundaries.start(record.as_ref())?;
+        let mut last = None;
+        while let Some(current) = record {
+            writeln!(output, "{}", current)?;
+            record = reader.read_record()?;
+            last = Some(current);
+        }
+        boundaries.end(last.as_ref());
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// A BGZF input whose header has been read.
+struct BlockInput<R> {
+    inner: R,
+    header: VCF,
+    /// Decompressed data following the header, ending with at least one complete line unless
+    /// the input ends first.
+    records: Vec<u8>,
+}
+
+impl<R: Read> BlockInput<R> {
+    fn new(mut inner: R) -> Result<Self, VCFError> {
+        let mut data = Vec::new();
+        let mut header_end = None;
+        loop {
+            if let Some(end) = header_end.or_else(|| find_header_end(&data)) {
+                header_end = Some(end);
+                if data[end..].contains(&b'\n') {
+                    break;
+                }
+            }
+            match Block::read_from(&mut inner)? {
+                Some(block) => data.extend(block.decompress()?),
+                None => break,
+            }
+        }
+        let end = header_end.ok_or(VCFError::ParseError)?;
+        let header = Reader::new(&data[..end])?.into_header();
+        Ok(Self {
+            inner,
+            header,
+            records: data.split_off(end),
+        })
+    }
+}
+
+/// The offset just after the `#CHROM` line, if all of it is in `data`.
+fn find_header_end(data: &[u8]) -> Option<usize> {
+    let mut start = 0;
+    while start < data.len() {
+        let end = start + data[start..].iter().position(|&b| b == b'\n')? + 1;
+        if data[start..].starts_with(b"#CHROM") {
+            return Some(end);
+        }
+        start = end;
+    }
+    None
+}
+
+fn first_record(data: &[u8]) -> Result<Option<Record>, VCFError> {
+    let text = String::from_utf8_lossy(data);
+    match text.lines().find(|line| !line.is_empty()) {
+        Some(line) => Ok(Some(Record::parse(line)?)),
+        None => Ok(None),
+    }
+}
+
+/// Concatenate BGZF-compressed VCFs in order, copying their compressed blocks to `output`
+/// without decompressing them.
+///
+/// Only the blocks holding the header, the first record and the last record of each input are
+/// decompressed, so that the headers can be combined and the boundaries between inputs checked.
+/// A line end is added after an input whose last line has none. The output is BGZF-compressed.
+pub fn concat_blocks<R: Read, W: Write>(inputs: Vec<R>, output: W) -> Result<W, VCFError> {
+    let mut inputs = inputs
+        .into_iter()
+        .map(BlockInput::new)
+        .collect::<Result<Vec<_>, VCFError>>()?;
+    let header = concat_headers(&inputs.iter().map(|input| &input.header).collect::<Vec<_>>())?;
+    let mut output = bgzf::Writer::new(output);
+    write!(output, "{}", header)?;
+    let mut boundaries = Boundaries::new(&header);
+
+    for input in &mut inputs {
+        let first = first_record(&input.records)?;
+        boundaries.start(first.as_ref())?;
+        output.write_all(&input.records)?;
+
+        // Keep the last few blocks, and the data before them if there are few, to find the
+        // last record once the input ends.
+        let mut tail: VecDeque<Block> = VecDeque::new();
+        let mut head = Some(&input.records);
+        while let Some(block) = Block::read_from(&mut input.inner)? {
+            if block.uncompressed_size() == 0 {
+                continue;
+            }
+            output.write_block(&block)?;
+            tail.push_back(block);
+            if tail.len() > TAIL_BLOCKS {
+                tail.pop_front();
+                head = None;
+            }
+        }
+        let mut data = head.cloned().unwrap_or_default();
+        for block in &tail {
+            data.extend(block.decompress()?);
+        }
+        if !data.is_empty() && !data.ends_with(b"\n") {
+            output.write_all(b"\n")?;
+        }
+        let text = String::from_utf8_lossy(&data);
+        let lines: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+        let last = match lines.last() {
+            // Without the data before the tail, the first line may be incomplete.
+            Some(_) if head.is_none() && lines.len() < 2 => {
+                return Err(VCFError::InvalidRecord(format!(
+                    "the last record of input {} is too long to find",
+                    boundaries.input
+                )));
+            }
+            Some(line) => Some(Record::parse(line)?),
+            None => first,
+        };
+        boundaries.end(last.as_ref());
+    }
+    Ok(output.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const HEADER: &str = "##fileformat=VCFv4.3
+##contig=<ID=1>
+##contig=<ID=2>
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB
+";
+
+    fn vcf(header: &str, records: &[String]) -> String {
+        let mut text = header.to_string();
+        for record in records {
+            text.push_str(record);
+            text.push('\n');
+        }
+        text
+    }
+
+    fn records(chrom: &str, positions: std::ops::Range<u64>) -> Vec<String> {
+        positions
+            .map(|pos| {
+                format!(
+                    "{}\t{}\t.\tA\tG\t.\t.\tDP={}\tGT\t0/1\t1/1",
+                    chrom, pos, pos
+                )
+            })
+            .collect()
+    }
+
+    fn compress(text: &str) -> Vec<u8> {
+        let mut writer = bgzf::Writer::new(Vec::new());
+        writer.write_all(text.as_bytes()).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn decompress(data: &[u8]) -> String {
+        let mut text = String::new();
+        bgzf::Reader::new(data).read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn checks_samples_and_definitions() {
+        let a = Reader::new(HEADER.as_bytes()).unwrap().into_header();
+        let b = Reader::new(HEADER.replace("\tA\tB", "\tB\tA").as_bytes())
+            .unwrap()
+            .into_header();
+        let c = Reader::new(HEADER.replace("Type=Integer", "Type=Float").as_bytes())
+            .unwrap()
+            .into_header();
+        assert_eq!(concat_headers(&[&a, &a]).unwrap(), a);
+        assert!(concat_headers(&[&a, &b]).is_err());
+        assert!(concat_headers(&[&a, &c]).is_err());
+    }
+
+    #[test]
+    fn concatenates_records_in_order() {
+        let first = vcf(HEADER, &records("1", 1..4));
+        let second = vcf(
+            &HEADER.replace("#CHROM", "##source=other\n#CHROM"),
+            &records("2", 1..3),
+        );
+        let mut output = Vec::new();
+        let readers = vec![
+            Reader::new(Cursor::new(first.clone())).unwrap(),
+            Reader::new(Cursor::new(second.clone())).unwrap(),
+        ];
+        concat(readers, &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.contains("##source=other\n"));
+        let lines: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[3].starts_with("2\t1\t"));
+
+        let readers = vec![
+            Reader::new(Cursor::new(second)).unwrap(),
+            Reader::new(Cursor::new(first)).unwrap(),
+        ];
+        assert!(concat(readers, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn copies_blocks_between_boundaries() {
+        // Large enough for several blocks per input.
+        let first = vcf(HEADER, &records("1", 1..5000));
+        let second = vcf(HEADER, &records("1", 5000..10000));
+        let empty = vcf(HEADER, &[]);
+        let third = vcf(HEADER, &records("2", 1..3000));
+
+        let mut expected = Vec::new();
+        let readers = [&first, &second, &empty, &third]
+            .iter()
+            .map(|text| Reader::new(Cursor::new(text.to_string())).unwrap())
+            .collect();
+        concat(readers, &mut expected).unwrap();
+
+        let inputs = [&first, &second, &empty, &third]
+            .iter()
+            .map(|text| Cursor::new(compress(text)))
+            .collect();
+        let output = concat_blocks(inputs, Vec::new()).unwrap();
+        assert!(output.ends_with(&bgzf::EOF_BLOCK));
+        assert_eq!(decompress(&output), String::from_utf8(expected).unwrap());
+
+        let inputs = [&second, &empty, &first]
+            .iter()
+            .map(|text| Cursor::new(compress(text)))
+            .collect();
+        assert!(concat_blocks(inputs, Vec::new()).is_err());
+
+        // An input without a final line end does not run into the next one.
+        let unterminated = first.trim_end();
+        let inputs = [unterminated, &second]
+            .iter()
+            .map(|text| Cursor::new(compress(text)))
+            .collect();
+        let output = decompress(&concat_blocks(inputs, Vec::new()).unwrap());
+        assert!(output.contains("\tDP=4999\tGT\t0/1\t1/1\n1\t5000\t"));
+        assert!(output.ends_with('\n'));
+    }
+}
@@ -0,0 +1,295 @@
+//! Removing and renaming INFO keys, FORMAT keys and FILTERs.
+//!
+//! [`Edits`] are applied to the header once, with [`Edits::apply_header`], and to each record
+//! with [`Edits::apply`], so that the definitions in the header keep matching the records.
+use crate::vcf::{VCFError, VCF};
+use crate::Record;
+
+/// An INFO key, FORMAT key or FILTER.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Field {
+    Info(String),
+    Format(String),
+    Filter(String),
+}
+
+impl Field {
+    /// Parse a field written as `INFO/KEY`, `FORMAT/KEY` or `FILTER/ID`.
+    pub fn parse(name: &str) -> Result<Self, VCFError> {
+        let field = match name.split_once('/') {
+            Some(("INFO", key)) if !key.is_empty() => Field::Info(key.to_string()),
+            Some(("FORMAT", key)) if !key.is_empty() => Field::Format(key.to_string()),
+            Some(("FILTER", id)) if !id.is_empty() => Field::Filter(id.to_string()),
+            _ => {
+                return Err(VCFError::InvalidExpression(format!(
+                    "{:?} is not INFO/KEY, FORMAT/KEY or FILTER/ID",
+                    name
+                )))
+            }
+        };
+        Ok(field)
+    }
+
+    /// The key of the header lines defining the field.
+    pub fn header_key(&self) -> &'static str {
+        match self {
+            Field::Info(_) => "INFO",
+            Field::Format(_) => "FORMAT",
+            Field::Filter(_) => "FILTER",
+        }
+    }
+
+    /// The key or ID of the field.
+    pub fn id(&self) -> &str {
+        match self {
+            Field::Info(id) | Field::Format(id) | Field::Filter(id) => id,
+        }
+    }
+}
+
+/// Fields to remove and rename, in the order they are applied.
+///
+/// ```
+/// use vcf::edit::{Edits, Field};
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let source = b"##fileformat=VCFv4.3\
+///     \n##INFO=<ID=AF,Number=A,Type=Float,Description=\"Frequency\">\
+///     \n##INFO=<ID=CSQ,Number=.,Type=String,Description=\"Consequences\">\
+///     \n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\
+///     \n1\t100\t.\tA\tG\t.\t.\tAF=0.5;CSQ=missense\n";
+/// let mut edits = Edits::default();
+/// edits.remove(Field::parse("INFO/CSQ")?);
+/// edits.rename(Field::parse("INFO/AF")?, "gnomAD_AF");
+///
+/// let mut reader = Reader::new(&source[..])?;
+/// let mut header = reader.header().clone();
+/// edits.apply_header(&mut header);
+/// assert_eq!(
+///     header.meta,
+///     ["##INFO=<ID=gnomAD_AF,Number=A,Type=Float,Description=\"Frequency\">"]
+/// );
+/// let mut record = reader.read_record()?.unwrap();
+/// edits.apply(&mut record);
+/// assert_eq!(record.info, [("gnomAD_AF".to_string(), Some("0.5".to_string()))]);
+///# Ok::<(), VCFError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Edits {
+    edits: Vec<Edit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Edit {
+    Remove(Field),
+    Rename(Field, String),
+}
+
+impl Edits {
+    /// Remove a field from the header and the records. A record left with no FILTER is marked
+    /// `PASS`.
+    pub fn remove(&mut self, field: Field) {
+        self.edits.push(Edit::Remove(field));
+    }
+
+    /// Rename a field in the header and the records, replacing any INFO or FORMAT key that
+    /// already had the new name. A FILTER renamed to an existing ID is merged into it: records
+    /// that failed either filter fail the existing one, and its header line is kept.
+    pub fn rename(&mut self, field: Field, name: &str) {
+        self.edits.push(Edit::Rename(field, name.to_string()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Update the definitions in a header.
+    pub fn apply_header(&self, header: &mut VCF) {
+        for edit in &self.edits {
+            match edit {
+                Edit::Remove(field) => {
+                    header.remove_header(field.header_key(), field.id());
+                }
+                Edit::Rename(Field::Filter(id), name) => {
+                    let exists = header
+                        .headers()
+                        .any(|line| line.key == "FILTER" && line.id() == Some(name));
+                    if !exists {
+                        header.rename_header("FILTER", id, name);
+                    } else if id != name {
+                        header.remove_header("FILTER", id);
+                    }
+                }
+                Edit::Rename(field, name) => {
+                    if !header.rename_header(field.header_key(), field.id(), name) {
+                        header.remove_header(field.header_key(), name);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Update a record.
+    pub fn apply(&self, record: &mut Record) {
+        for edit in &self.edits {
+            match edit {
+                Edit::Remove(Field::Info(key)) => {
+                    record.remove_info(key);
+                }
+                Edit::Remove(Field::Format(key)) => remove_format(record, key),
+                Edit::Remove(Field::Filter(id)) => remove_filter(record, id),
+                Edit::Rename(Field::Info(key), name) => {
+                    if key != name {
+                        record.remove_info(name);
+                    }
+                    for (k, _) in record.info.iter_mut().filter(|(k, _)| k == key) {
+                        *k = name.clone();
+                    }
+                }
+                Edit::Rename(Field::Format(key), name) => {
+                    if key != name {
+                        remove_format(record, name);
+                    }
+                    for format in record.format.iter_mut().filter(|format| *format == key) {
+                        *format = name.clone();
+                    }
+                }
+                Edit::Rename(Field::Filter(id), name) => {
+                    for filter in record.filter.iter_mut().filter(|filter| *filter == id) {
+                        *filter = name.clone();
+                    }
+                    let mut seen = Vec::new();
+                    record.filter.retain(|filter| {
+                        let first = !seen.contains(filter);
+                        seen.push(filter.clone());
+                        first
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Remove a FILTER, marking the record `PASS` if it failed no other filter.
+fn remove_filter(record: &mut Record, id: &str) {
+    let length = record.filter.len();
+    record.filter.retain(|filter| filter != id);
+    if record.filter.is_empty() && length > 0 {
+        record.filter.push("PASS".to_string());
+    }
+}
+
+/// Remove a FORMAT key and its sample values. Samples left with no values keep a `.` under a
+/// FORMAT of `.`, so that the sample columns are still written.
+fn remove_format(record: &mut Record, key: &str) {
+    let Some(index) = record.format.iter().position(|format| format == key) else {
+        return;
+    };
+    record.format.remove(index);
+    for sample in &mut record.samples {
+        if index < sample.len() {
+            sample.remove(index);
+        }
+    }
+    if record.format.is_empty() && !record.samples.is_empty() {
+        record.format.push(".".to_string());
+        for sample in &mut record.samples {
+            *sample = vec![".".to_string()];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::Reader;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Frequency\">
+##INFO=<ID=OLD,Number=1,Type=Integer,Description=\"Old\">
+##FILTER=<ID=q10,Description=\"Quality below 10\">
+##FILTER=<ID=lowdp,Description=\"Low depth\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Allele depths\">
+##FORMAT=<ID=PL,Number=G,Type=Integer,Description=\"Likelihoods\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB
+1\t100\t.\tA\tG\t5\tq10\tOLD=1;DP=3;AF=0.5\tGT:AD:PL\t0/1:3,4:1,0,9\t./.
+1\t200\t.\tC\tT\t50\tq10;lowdp\tDP=5\tAD\t1,2\t.
+";
+
+    fn edit(edits: &Edits) -> (VCF, Vec<String>) {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        let mut header = reader.header().clone();
+        edits.apply_header(&mut header);
+        let records = reader
+            .records()
+            .map(|record| {
+                let mut record = record.unwrap();
+                edits.apply(&mut record);
+                record.to_string()
+            })
+            .collect();
+        (header, records)
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(
+            Field::parse("FORMAT/AD").unwrap(),
+            Field::Format("AD".to_string())
+        );
+        assert_eq!(Field::parse("FILTER/q10").unwrap().header_key(), "FILTER");
+        assert!(Field::parse("AF").is_err());
+        assert!(Field::parse("INFO/").is_err());
+    }
+
+    #[test]
+    fn removes_fields() {
+        let mut edits = Edits::default();
+        for field in ["INFO/AF", "FORMAT/AD", "FILTER/q10"] {
+            edits.remove(Field::parse(field).unwrap());
+        }
+        let (header, records) = edit(&edits);
+        let definitions = header.definitions();
+        assert!(!definitions.info.contains_key("AF"));
+        assert!(!definitions.format.contains_key("AD"));
+        assert!(!header.meta.iter().any(|line| line.contains("q10")));
+        assert_eq!(
+            records,
+            [
+                "1\t100\t.\tA\tG\t5\tPASS\tOLD=1;DP=3\tGT:PL\t0/1:1,0,9\t./.",
+                "1\t200\t.\tC\tT\t50\tlowdp\tDP=5\t.\t.\t.",
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_fields() {
+        let mut edits = Edits::default();
+        edits.rename(Field::parse("INFO/AF").unwrap(), "gnomAD_AF");
+        edits.rename(Field::parse("INFO/DP").unwrap(), "OLD");
+        edits.rename(Field::parse("FORMAT/AD").unwrap(), "PL");
+        edits.rename(Field::parse("FILTER/lowdp").unwrap(), "q10");
+        let (header, records) = edit(&edits);
+        assert_eq!(
+            header.meta[..1],
+            ["##INFO=<ID=gnomAD_AF,Number=A,Type=Float,Description=\"Frequency\">"]
+        );
+        let definitions = header.definitions();
+        assert!(!definitions.info.contains_key("OLD"));
+        assert_eq!(
+            definitions.format["PL"].fieldtype.number(),
+            crate::NumberField::R
+        );
+        let filters: Vec<_> = header.headers().filter(|h| h.key == "FILTER").collect();
+        assert_eq!(filters.len(), 1);
+        assert_eq!(filters[0].id(), Some("q10"));
+        assert_eq!(
+            records,
+            [
+                "1\t100\t.\tA\tG\t5\tq10\tOLD=3;gnomAD_AF=0.5\tGT:PL\t0/1:3,4\t./.",
+                "1\t200\t.\tC\tT\t50\tq10\tOLD=5\tPL\t1,2\t.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::vcf::VCFError;
+
+/// Random access to reference bases.
+pub trait ReferenceSequence {
+    /// Fetch the bases in the 1-based closed interval `start..=end` of `chrom`, upper-cased.
+    fn fetch(&mut self, chrom: &str, start: u64, end: u64) -> Result<String, VCFError>;
+}
+
+/// A single line of a `.fai` index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaiEntry {
+    pub length: u64,
+    pub offset: u64,
+    pub line_bases: u64,
+    pub line_width: u64,
+}
+
+/// A FASTA file accessed through its `.fai` index.
+pub struct IndexedFasta<R> {
+    inner: R,
+    index: HashMap<String, FaiEntry>,
+}
+
+impl IndexedFasta<BufReader<File>> {
+    /// Open `path`, reading the index from `path.fai`, or building it if there is none.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, VCFError> {
+        let path = path.as_ref();
+        let mut inner = BufReader::new(File::open(path)?);
+        let mut fai = path.as_os_str().to_owned();
+        fai.push(".fai");
+        let index = match File::open(&fai) {
+            Ok(file) => parse_fai(BufReader::new(file))?,
+            Err(_) => {
+                let index = build_fai(&mut inner)?;
+                inner.rewind()?;
+                index
+            }
+        };
+        Ok(Self { inner, index })
+    }
+}
+
+impl<R: Read + Seek> IndexedFasta<R> {
+    pub fn new(inner: R, index: HashMap<String, FaiEntry>) -> Self {
+        Self { inner, index }
+    }
+
+    pub fn contig_length(&self, chrom: &str) -> Option<u64> {
+        self.index.get(chrom).map(|entry| entry.length)
+    }
+}
+
+impl<R: Read + Seek> ReferenceSequence for IndexedFasta<R> {
+    fn fetch(&mut self, chrom: &str, start: u64, end: u64) -> Result<String, VCFError> {
+        let entry = self
+            .index
+            .get(chrom)
+            .ok_or_else(|| VCFError::ReferenceError(format!("unknown contig {}", chrom)))?;
+        if start < 1 || end > entry.length || start > end + 1 {
+            return Err(VCFError::ReferenceError(format!(
+                "{}:{}-{} is outside the contig",
+                chrom, start, end
+            )));
+        }
+        let mut bases = String::with_capacity((end + 1 - start) as usize);
+        let mut position = start - 1;
+        while position < end {
+            let line = position / entry.line_bases;
+            let column = position % entry.line_bases;
+            let take = (entry.line_bases - column).min(end - position);
+            self.inner.seek(SeekFrom::Start(
+                entry.offset + line * entry.line_width + column,
+            ))?;
+            let mut buffer = vec![0; take as usize];
+            self.inner.read_exact(&mut buffer)?;
+            bases.extend(buffer.iter().map(|b| b.to_ascii_uppercase() as char));
+            position += take;
+        }
+        Ok(bases)
+    }
+}
+
+/// In-memory sequences, keyed by contig name.
+impl ReferenceSequence for HashMap<String, String> {
+    fn fetch(&mut self, chrom: &str, start: u64, end: u64) -> Result<String, VCFError> {
+        let sequence = self
+            .get(chrom)
+            .ok_or_else(|| VCFError::ReferenceError(format!("unknown contig {}", chrom)))?;
+        if start < 1 || end as usize > sequence.len() || start > end + 1 {
+            return Err(VCFError::ReferenceError(format!(
+                "{}:{}-{} is outside the contig",
+                chrom, start, end
+            )));
+        }
+        Ok(sequence[start as usize - 1..end as usize].to_ascii_uppercase())
+    }
+}
+
+pub fn parse_fai(source: impl BufRead) -> Result<HashMap<String, FaiEntry>, VCFError> {
+    let mut index = HashMap::new();
+    for line in source.lines() {
+        let line = line?;
+        let columns: Vec<&str> = line.split('\t').collect();
+        if columns.len() < 5 {
+            return Err(VCFError::ParseError);
+        }
+        let number = |i: usize| columns[i].parse::<u64>().map_err(|_| VCFError::ParseError);
+        let entry = FaiEntry {
+            length: number(1)?,
+            offset: number(2)?,
+            line_bases: number(3)?,
+            line_width: number(4)?,
+        };
+        // Only an empty sequence can have no bases on its lines.
+        if entry.line_bases == 0 && entry.length > 0 {
+            return Err(VCFError::ParseError);
+        }
+        index.insert(columns[0].to_string(), entry);
+    }
+    Ok(index)
+}
+
+/// Build an index by scanning a FASTA file. Lines within a sequence must have equal length.
+pub fn build_fai(source: impl BufRead) -> Result<HashMap<String, FaiEntry>, VCFError> {
+    let mut index = HashMap::new();
+    let mut current: Option<(String, FaiEntry)> = None;
+    let mut offset = 0;
+    for line in source.split(b'\n') {
+        let line = line?;
+        let width = line.len() as u64 + 1;
+        if let Some(name) = line.strip_prefix(b">") {
+            index.extend(current.take());
+            let name = String::from_utf8_lossy(name);
+            let name = name
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            let entry = FaiEntry {
+                length: 0,
+                offset: offset + width,
+                line_bases: 0,
+                line_width: 0,
+            };
+            current = Some((name, entry));
+        } else if let Some((_, entry)) = current.as_mut() {
+            let bases = line.strip_suffix(b"\r").unwrap_or(&line).len() as u64;
+            if entry.line_bases == 0 {
+                entry.line_bases = bases;
+                entry.line_width = width;
+            }
+            entry.length += bases;
+        }
+        offset += width;
+    }
+    index.extend(current);
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const FASTA: &str = ">chr1 description\nACGTA\nCGTAC\nGT\n>chr2\nttttt\n";
+
+    fn fasta() -> IndexedFasta<Cursor<&'static str>> {
+        let index = build_fai(FASTA.as_bytes()).unwrap();
+        IndexedFasta::new(Cursor::new(FASTA), index)
+    }
+
+    #[test]
+    fn builds_index() {
+        let index = build_fai(FASTA.as_bytes()).unwrap();
+        assert_eq!(
+            index["chr1"],
+            FaiEntry {
+                length: 12,
+                offset: 18,
+                line_bases: 5,
+                line_width: 6
+            }
+        );
+        assert_eq!(index["chr2"].length, 5);
+    }
+
+    #[test]
+    fn fetches_across_line_breaks() {
+        let mut fasta = fasta();
+        assert_eq!(fasta.fetch("chr1", 4, 11).unwrap(), "TACGTACG");
+        assert_eq!(fasta.fetch("chr1", 12, 12).unwrap(), "T");
+        assert_eq!(fasta.fetch("chr2", 1, 2).unwrap(), "TT");
+    }
+
+    #[test]
+    fn out_of_range_fetch_is_an_error() {
+        let mut fasta = fasta();
+        assert!(fasta.fetch("chr1", 10, 13).is_err());
+        assert!(fasta.fetch("chr3", 1, 1).is_err());
+    }
+
+    #[test]
+    fn parses_fai() {
+        let index = parse_fai("chr1\t12\t18\t5\t6\n".as_bytes()).unwrap();
+        assert_eq!(index["chr1"].offset, 18);
+        assert!(parse_fai("empty\t0\t7\t0\t0\n".as_bytes()).is_ok());
+        assert!(parse_fai("chr1\t12\t18\t0\t6\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+//! Computing allele frequencies, genotype counts and Hardy-Weinberg statistics from the
+//! genotypes of a record, and writing them to INFO.
+//!
+//! Allele counts use every called allele of every genotype, whatever its ploidy. A genotype
+//! counts as homozygous when all its alleles are the same, as heterozygous otherwise, and as
+//! missing when any of its alleles is missing. `ExcHet` and `HWE` are computed for each ALT
+//! allele against all other alleles, from the diploid genotypes only, with the exact test of
+//! Wigginton et al. (2005).
+//!
+//! Tags can also be computed for groups of samples, such as populations. They are then written
+//! with the group name as a suffix, e.g. `AF_EUR`.
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
+
+use crate::vcf::{VCFError, VCF};
+use crate::{escape_header_value, Genotype, Header, HeaderValue, Record};
+
+/// A tag, or a set of tags, that can be filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    /// `AC`: the count of each ALT allele.
+    AC,
+    /// `AN`: the number of called alleles.
+    AN,
+    /// `AF`: the frequency of each ALT allele.
+    AF,
+    /// `MAF`: the frequency of the second most common allele.
+    MAF,
+    /// `N_HOM_REF`, `N_HET`, `N_HOM_ALT` and `N_MISSING`: the numbers of genotypes of each kind.
+    GenotypeCounts,
+    /// `ExcHet`: the p-value of excess heterozygosity for each ALT allele.
+    ExcHet,
+    /// `HWE`: the p-value of Hardy-Weinberg equilibrium for each ALT allele.
+    HWE,
+}
+
+impl Tag {
+    pub const ALL: [Tag; 7] = [
+        Tag::AC,
+        Tag::AN,
+        Tag::AF,
+        Tag::MAF,
+        Tag::GenotypeCounts,
+        Tag::ExcHet,
+        Tag::HWE,
+    ];
+
+    /// Parse a tag name, ignoring case. The genotype counts are named `GTC`.
+    pub fn parse(name: &str) -> Option<Self> {
+        let names = ["AC", "AN", "AF", "MAF", "GTC", "EXCHET", "HWE"];
+        let index = names
+            .iter()
+            .position(|tag| tag.eq_ignore_ascii_case(name))?;
+        Some(Self::ALL[index])
+    }
+
+    /// The INFO definitions of the tag: ID, Number, Type and Description.
+    fn definitions(self) -> &'static [(&'static str, &'static str, &'static str, &'static str)] {
+        match self {
+            Tag::AC => &[(
+                "AC",
+                "A",
+                "Integer",
+                "Allele count in genotypes, for each ALT allele",
+            )],
+            Tag::AN => &[(
+                "AN",
+                "1",
+                "Integer",
+                "Total number of alleles in called genotypes",
+            )],
+            Tag::AF => &[("AF", "A", "Float", "Allele frequency, for each ALT allele")],
+            Tag::MAF => &[(
+                "MAF",
+                "1",
+                "Float",
+                "Frequency of the second most common allele",
+            )],
+            Tag::GenotypeCounts => &[
+                (
+                    "N_HOM_REF",
+                    "1",
+                    "Integer",
+                    "Number of homozygous reference genotypes",
+                ),
+                ("N_HET", "1", "Integer", "Number of heterozygous genotypes"),
+                (
+                    "N_HOM_ALT",
+                    "1",
+                    "Integer",
+                    "Number of homozygous alternate genotypes",
+                ),
+                (
+                    "N_MISSING",
+                    "1",
+                    "Integer",
+                    "Number of genotypes with a missing allele",
+                ),
+            ],
+            Tag::ExcHet => &[(
+                "ExcHet",
+                "A",
+                "Float",
+                "Test excess heterozygosity; 1=good, 0=bad",
+            )],
+            Tag::HWE => &[(
+                "HWE",
+                "A",
+                "Float",
+                "HWE test (PMID:15789306); 1=good, 0=bad",
+            )],
+        }
+    }
+}
+
+/// Read a map from sample names to group names, one `sample<TAB>group` pair per line.
+pub fn read_groups(input: impl BufRead) -> Result<HashMap<String, String>, VCFError> {
+    let mut groups = HashMap::new();
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((sample, group)) = line.trim().split_once(char::is_whitespace) else {
+            return Err(VCFError::InvalidHeader(format!(
+                "no group given for sample {}",
+                line.trim()
+            )));
+        };
+        groups.insert(sample.to_string(), group.trim().to_string());
+    }
+    Ok(groups)
+}
+
+/// Fills the chosen tags into records with the samples of a header.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use vcf::fill_tags::{FillTags, Tag};
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let source = b"##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tC\n\
+/// 1\t100\t.\tA\tG\t.\t.\t.\tGT\t0/1\t1/1\t0/0/1\n";
+/// let mut reader = Reader::new(&source[..])?;
+/// let groups = HashMap::from([("A".to_string(), "EUR".to_string())]);
+/// let fill = FillTags::new(reader.header(), &[Tag::AC, Tag::AF], &groups)?;
+/// let mut record = reader.read_record()?.unwrap();
+/// fill.fill(&mut record);
+/// assert_eq!(record.info("AC"), Some(Some("4")));
+/// assert_eq!(record.info("AF"), Some(Some("0.571429")));
+/// assert_eq!(record.info("AF_EUR"), Some(Some("0.5")));
+///# Ok::<(), VCFError>(())
+/// ```
+pub struct FillTags {
+    tags: Vec<Tag>,
+    /// The suffix of each group's tags, empty for all samples, and the samples in the group.
+    groups: Vec<(String, Vec<usize>)>,
+}
+
+impl FillTags {
+    /// Fill `tags` for all the samples of `header`, and for each group of samples given by
+    /// `groups`, a map from sample names to group names. Samples without a group are only
+    /// counted with all samples.
+    pub fn new(
+        header: &VCF,
+        tags: &[Tag],
+        groups: &HashMap<String, String>,
+    ) -> Result<Self, VCFError> {
+        let positions: HashMap<&str, usize> = header
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i))
+            .collect();
+        let mut members: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (sample, group) in groups {
+            let position = positions.get(sample.as_str()).ok_or_else(|| {
+                VCFError::InvalidHeader(format!("sample {} is not in the header", sample))
+            })?;
+            if group.is_empty() || group.contains([',', ';', '=', ' ', '\t']) {
+                return Err(VCFError::InvalidHeader(format!(
+                    "{:?} cannot be used as a group name",
+                    group
+                )));
+            }
+            members.entry(group).or_default().push(*position);
+        }
+        let mut groups = vec![(String::new(), (0..header.samples.len()).collect())];
+        for (group, mut samples) in members {
+            samples.sort_unstable();
+            groups.push((format!("_{}", group), samples));
+        }
+        Ok(Self {
+            tags: tags.to_vec(),
+            groups,
+        })
+    }
+
+    /// Define the filled tags in `header`, replacing any definitions they already have.
+    pub fn add_definitions(&self, header: &mut VCF) {
+        for (suffix, _) in &self.groups {
+            let group = suffix.strip_prefix('_');
+            for tag in &self.tags {
+                for (id, number, data_type, description) in tag.definitions() {
+                    let id = format!("{}{}", id, suffix);
+                    let description = match group {
+                        Some(group) => {
+                            format!("{} (group {})", description, escape_header_value(group))
+                        }
+                        None => description.to_string(),
+                    };
+                    header.set_header(&Header {
+                        key: "INFO",
+                        value: HeaderValue::Nested(HashMap::from([
+                            ("ID", id.as_str()),
+                            ("Number", *number),
+                            ("Type", *data_type),
+                            ("Description", description.as_str()),
+                        ])),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Compute the tags from the genotypes of `record` and set them in its INFO, removing the
+    /// tags with a value per ALT allele from records without ALT alleles. Records without
+    /// genotypes are left unchanged.
+    pub fn fill(&self, record: &mut Record) {
+        let Some(index) = record.format.iter().position(|key| key == "GT") else {
+            return;
+        };
+        let genotypes: Vec<Option<Genotype>> = record
+            .samples
+            .iter()
+            .map(|sample| sample.get(index)?.parse().ok())
+            .collect();
+        for (suffix, samples) in &self.groups {
+            let genotypes = samples.iter().filter_map(|&i| genotypes.get(i)?.as_ref());
+            let counts = Counts::new(genotypes, record.alt.len());
+            for tag in &self.tags {
+                let values = counts.values(*tag);
+                if values.is_empty() {
+                    for (id, ..) in tag.definitions() {
+                        record.remove_info(&format!("{}{}", id, suffix));
+                    }
+                }
+                for (id, value) in values {
+                    record.set_info(&format!("{}{}", id, suffix), value);
+                }
+            }
+        }
+    }
+}
+
+/// The counts of alleles and genotypes in a set of samples.
+struct Counts {
+    /// The count of each allele, REF first.
+    alleles: Vec<usize>,
+    hom_ref: usize,
+    het: usize,
+    hom_alt: usize,
+    missing: usize,
+    /// For each ALT allele, the numbers of diploid genotypes with two, one and none of it.
+    diploid: Vec<[usize; 3]>,
+}
+
+impl Counts {
+    fn new<'g>(genotypes: impl Iterator<Item = &'g Genotype>, alt_count: usize) -> Self {
+        let mut counts = Counts {
+            alleles: vec![0; alt_count + 1],
+            hom_ref: 0,
+            het: 0,
+            hom_alt: 0,
+            missing: 0,
+            diploid: vec![[0; 3]; alt_count],
+        };
+        for genotype in genotypes {
+            for allele in genotype.alleles.iter().flatten() {
+                if let Some(count) = counts.alleles.get_mut(*allele) {
+                    *count += 1;
+                }
+            }
+            let called: Option<Vec<usize>> = genotype.alleles.iter().copied().collect();
+            let Some(called) = called else {
+                counts.missing += 1;
+                continue;
+            };
+            match called.iter().all(|allele| *allele == called[0]) {
+                true if called[0] == 0 => counts.hom_ref += 1,
+                true => counts.hom_alt += 1,
+                false => counts.het += 1,
+            }
+            if let [first, second] = called[..] {
+                for (alt, diploid) in counts.diploid.iter_mut().enumerate() {
+                    let copies = [first, second].iter().filter(|a| **a == alt + 1).count();
+                    diploid[2 - copies] += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    /// The INFO entries of a tag.
+    fn values(&self, tag: Tag) -> Vec<(&'static str, Option<String>)> {
+        let total: usize = self.alleles.iter().sum();
+        let frequency = |count: usize| match total {
+            0 => ".".to_string(),
+            _ => float(count as f64 / total as f64),
+        };
+        let join = |values: Vec<String>| Some(values.join(","));
+        match tag {
+            Tag::AC | Tag::AF | Tag::ExcHet | Tag::HWE if self.alleles.len() == 1 => Vec::new(),
+            Tag::AC => vec![(
+                "AC",
+                join(self.alleles[1..].iter().map(usize::to_string).collect()),
+            )],
+            Tag::AN => vec![("AN", Some(total.to_string()))],
+            Tag::AF => vec![(
+                "AF",
+                join(self.alleles[1..].iter().map(|c| frequency(*c)).collect()),
+            )],
+            Tag::MAF => {
+                let mut sorted = self.alleles.clone();
+                sorted.sort_unstable_by(|a, b| b.cmp(a));
+                vec![("MAF", Some(frequency(sorted.get(1).copied().unwrap_or(0))))]
+            }
+            Tag::GenotypeCounts => vec![
+                ("N_HOM_REF", Some(self.hom_ref.to_string())),
+                ("N_HET", Some(self.het.to_string())),
+                ("N_HOM_ALT", Some(self.hom_alt.to_string())),
+                ("N_MISSING", Some(self.missing.to_string())),
+            ],
+            Tag::ExcHet | Tag::HWE => {
+                let values =
+                    self.diploid
+                        .iter()
+                        .map(|&[hom, het, other]| match hom + het + other {
+                            0 => ".".to_string(),
+                            _ => {
+                                let (hwe, exc_het) = hardy_weinberg(het, hom, other);
+                                float(if tag == Tag::HWE { hwe } else { exc_het })
+                            }
+                        });
+                let id = if tag == Tag::HWE { "HWE" } else { "ExcHet" };
+                vec![(id, join(values.collect()))]
+            }
+        }
+    }
+}
+
+/// The p-values of Hardy-Weinberg equilibrium and of excess heterozygosity, by the exact test
+/// of Wigginton, Cutler and Abecasis (2005), given the numbers of heterozygous and of each kind
+/// of homozygous genotype.
+fn hardy_weinberg(het: usize, hom_1: usize, hom_2: usize) -> (f64, f64) {
+    let (rare_hom, common_hom) = (hom_1.min(hom_2), hom_1.max(hom_2));
+    let genotypes = het + rare_hom + common_hom;
+    let rare = 2 * rare_hom + het;
+    // The probability of each number of heterozygotes, up to a constant factor, starting from
+    // the most likely one and going down and up in steps of two.
+    let mut probabilities = vec![0.0; rare + 1];
+    let mut middle = rare * (2 * genotypes - rare) / (2 * genotypes);
+    if middle % 2 != rare % 2 {
+        middle += 1;
+    }
+    probabilities[middle] = 1.0;
+    let start_rare_homs = (rare - middle) / 2;
+    let start_common_homs = genotypes - middle - start_rare_homs;
+    let (mut hets, mut rare_homs, mut common_homs) = (middle, start_rare_homs, start_common_homs);
+    while hets >= 2 {
+        probabilities[hets - 2] = probabilities[hets] * (hets * (hets - 1)) as f64
+            / (4 * (rare_homs + 1) * (common_homs + 1)) as f64;
+        hets -= 2;
+        rare_homs += 1;
+        common_homs += 1;
+    }
+    let (mut hets, mut rare_homs, mut common_homs) = (middle, start_rare_homs, start_common_homs);
+    while hets + 2 <= rare {
+        probabilities[hets + 2] = probabilities[hets] * (4 * rare_homs * common_homs) as f64
+            / ((hets + 2) * (hets + 1)) as f64;
+        hets += 2;
+        rare_homs -= 1;
+        common_homs -= 1;
+    }
+    let sum: f64 = probabilities.iter().sum();
+    let observed = probabilities[het];
+    let hwe = probabilities
+        .iter()
+        .filter(|p| **p <= observed * (1.0 + 1e-8))
+        .sum::<f64>()
+        / sum;
+    let exc_het = probabilities[het..].iter().sum::<f64>() / sum;
+    (hwe.min(1.0), exc_het.min(1.0))
+}
+
+/// Write a number with at most six decimals, or in scientific notation when it is very small.
+fn float(value: f64) -> String {
+    if value != 0.0 && value.abs() < 1e-4 {
+        format!("{:.4e}", value)
+    } else {
+        let fixed = format!("{:.6}", value);
+        fixed
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::Reader;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=AC,Number=A,Type=Integer,Description=\"Old count\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tC\tD\tE
+1\t100\t.\tA\tG,T\t.\t.\tAC=9,9\tGT:DP\t0/1:5\t1|2:6\t0/0/2/2:7\t./.:8\t1/.:9
+1\t200\t.\tA\t.\t.\t.\tAC=1;AF=0.1;ExcHet=1;HWE=1;AC_P1=1\tGT\t0/0\t0\t.\t0/0\t0/0
+1\t300\t.\tC\tT\t.\t.\t.\tDP\t1\t2\t3\t4\t5
+";
+
+    fn groups() -> HashMap<String, String> {
+        [("A", "P1"), ("C", "P1"), ("E", "P2")]
+            .iter()
+            .map(|(sample, group)| (sample.to_string(), group.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn counts_alleles_of_any_ploidy() {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        let fill = FillTags::new(reader.header(), &Tag::ALL, &groups()).unwrap();
+        let mut record = reader.read_record().unwrap().unwrap();
+        fill.fill(&mut record);
+        let info = |key: &str| record.info(key).flatten().unwrap().to_string();
+        assert_eq!(info("AC"), "3,3");
+        assert_eq!(info("AN"), "9");
+        assert_eq!(info("AF"), "0.333333,0.333333");
+        assert_eq!(info("MAF"), "0.333333");
+        assert_eq!(
+            ["N_HOM_REF", "N_HET", "N_HOM_ALT", "N_MISSING"].map(info),
+            ["0", "3", "0", "2"]
+        );
+        assert_eq!(info("AC_P1"), "1,2");
+        assert_eq!(info("AN_P1"), "6");
+        assert_eq!(info("AC_P2"), "1,0");
+        assert_eq!(info("HWE_P2"), ".,.");
+        assert_eq!(record.sample_value(0, "DP"), Some("5"));
+
+        // Monomorphic sites have no per-ALT values, and lose the ones they had.
+        let mut record = reader.read_record().unwrap().unwrap();
+        fill.fill(&mut record);
+        for key in ["AC", "AF", "ExcHet", "HWE", "AC_P1"] {
+            assert_eq!(record.info(key), None);
+        }
+        assert_eq!(record.info("AN"), Some(Some("7")));
+        assert_eq!(record.info("MAF"), Some(Some("0")));
+        assert_eq!(record.info("N_MISSING"), Some(Some("1")));
+
+        let mut record = reader.read_record().unwrap().unwrap();
+        let before = record.clone();
+        fill.fill(&mut record);
+        assert_eq!(record, before);
+    }
+
+    #[test]
+    fn tests_hardy_weinberg_equilibrium() {
+        // Checked against the probabilities summed directly from the exact distribution.
+        let (hwe, exc_het) = hardy_weinberg(57, 14, 29);
+        assert!((hwe - 0.150680).abs() < 1e-6, "{}", hwe);
+        assert!((exc_het - 0.078634).abs() < 1e-6, "{}", exc_het);
+        let (hwe, exc_het) = hardy_weinberg(0, 50, 50);
+        assert!((hwe - 1.114224e-30).abs() < 1e-35, "{}", hwe);
+        assert!((exc_het - 1.0).abs() < 1e-12);
+        assert_eq!(hardy_weinberg(0, 0, 10), (1.0, 1.0));
+        assert_eq!(float(1.0), "1");
+        assert_eq!(float(0.000012345), "1.2345e-5");
+    }
+
+    #[test]
+    fn defines_tags_in_the_header() {
+        let mut header = Reader::new(SOURCE.as_bytes()).unwrap().into_header();
+        let fill = FillTags::new(&header, &[Tag::AC, Tag::HWE], &groups()).unwrap();
+        fill.add_definitions(&mut header);
+        let definitions = header.definitions();
+        assert_eq!(definitions.info.len(), 6);
+        assert!(header.meta[0].contains("Allele count in genotypes"));
+        assert!(header.to_string().contains(
+            "##INFO=<ID=HWE_P2,Number=A,Type=Float,Description=\"HWE test (PMID:15789306); 1=good, 0=bad (group P2)\">"
+        ));
+
+        let mut unknown = groups();
+        unknown.insert("F".to_string(), "P1".to_string());
+        assert!(FillTags::new(&header, &[Tag::AC], &unknown).is_err());
+        assert_eq!(Tag::parse("exchet"), Some(Tag::ExcHet));
+        assert_eq!(Tag::parse("XX"), None);
+        assert_eq!(
+            read_groups("A\tP1\n\nB P2\n".as_bytes()).unwrap(),
+            HashMap::from([
+                ("A".to_string(), "P1".to_string()),
+                ("B".to_string(), "P2".to_string())
+            ])
+        );
+    }
+}
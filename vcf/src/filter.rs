@@ -0,0 +1,1147 @@
+//! A small expression language for selecting records, such as
+//! `QUAL > 30 && INFO/DP >= 10 && FORMAT/GQ[*] > 20 && FILTER == "PASS"`.
+//!
+//! Expressions refer to the fixed columns (`CHROM`, `POS`, `ID`, `REF`, `ALT`, `QUAL`,
+//! `FILTER`), to INFO fields as `INFO/KEY` and to FORMAT fields as `FORMAT/KEY`. They are
+//! checked against the types of the INFO and FORMAT definitions in the header before use.
+//!
+//! - Fields with several values are subscripted from 0, as in `INFO/AF[1]`. Without a
+//!   subscript, a comparison holds when it holds for any of the values.
+//! - FORMAT fields have a value per sample. `FORMAT/GQ` and `FORMAT/GQ[*]` refer to all
+//!   samples, `FORMAT/GQ[2]` to the third sample, and `FORMAT/AD[*:1]` to the second value of
+//!   each sample. An expression over samples selects a record when it holds for any sample,
+//!   unless it is wrapped in `any(...)` or `all(...)`.
+//! - `.` is the missing value. A comparison with a missing value is false, except that
+//!   `INFO/DP == .` holds when DP is missing and `INFO/DP != .` when it is present. Flags are
+//!   used as booleans, e.g. `INFO/DB && !INFO/SOMATIC`.
+//!
+//! The operators, from loosest to tightest binding, are `||`; `&&`; `==` (or `=`), `!=`, `<`,
+//! `<=`, `>`, `>=` and the regular expression matches `~` and `!~`; `+` and `-`; `*` and `/`;
+//! and the unary `!` and `-`.
+//!
+//! Besides dropping records, a filter can be applied softly: records that fail it keep their
+//! place in the output with a named ID in their FILTER column ([`soft_filter`]), or in the `FT`
+//! field of the samples that fail it ([`soft_filter_samples`]). The IDs are declared in the
+//! header with [`add_filter_definition`] and [`add_sample_filter_definition`].
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::vcf::{VCFError, VCF};
+use crate::{escape_header_value, DataType, Definitions, Header, HeaderValue, NumberField, Record};
+
+/// A parsed and type-checked filter expression.
+///
+/// ```
+/// use vcf::filter::Filter;
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let source = b"##fileformat=VCFv4.3\
+///     \n##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\
+///     \n##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype quality\">\
+///     \n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\
+///     \n1\t100\t.\tA\tG\t50\tPASS\tDP=12\tGQ\t30\t10\n";
+/// let mut reader = Reader::new(&source[..])?;
+/// let definitions = reader.header().definitions();
+/// let record = reader.read_record()?.unwrap();
+///
+/// let filter = Filter::parse("QUAL > 30 && INFO/DP >= 10 && FORMAT/GQ[*] > 20 && FILTER == \"PASS\"", &definitions)?;
+/// assert!(filter.matches(&record));
+/// assert!(!Filter::parse("all(FORMAT/GQ > 20)", &definitions)?.matches(&record));
+/// assert!(Filter::parse("INFO/XX > 1", &definitions).is_err());
+///# Ok::<(), VCFError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expression: Expression,
+}
+
+impl Filter {
+    /// Parse an expression, checking it against the INFO and FORMAT definitions of a header.
+    pub fn parse(input: &str, definitions: &Definitions) -> Result<Self, VCFError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            definitions,
+        };
+        let expression = parser.expression()?;
+        if let Some((offset, token)) = tokens.get(parser.position) {
+            return Err(error(format!("unexpected {} at {}", token, offset)));
+        }
+        match expression.check()? {
+            (Type::Bool, _) => Ok(Self { expression }),
+            (found, _) => Err(error(format!(
+                "the expression is {}, not a condition",
+                found
+            ))),
+        }
+    }
+
+    /// Whether the expression holds for the record, or for any of its samples.
+    pub fn matches(&self, record: &Record) -> bool {
+        match self.expression.evaluate(record) {
+            Evaluated::Site(values) => is_true(&values),
+            Evaluated::Samples(samples) => samples.iter().any(|values| is_true(values)),
+        }
+    }
+
+    /// Whether the expression holds for each sample of the record. Expressions that do not refer
+    /// to FORMAT fields hold for all samples or for none.
+    pub fn matches_samples(&self, record: &Record) -> Vec<bool> {
+        match self.expression.evaluate(record) {
+            Evaluated::Site(values) => vec![is_true(&values); record.samples.len()],
+            Evaluated::Samples(samples) => samples.iter().map(|values| is_true(values)).collect(),
+        }
+    }
+
+    /// Whether the expression refers to FORMAT fields outside `any(...)` and `all(...)`, so
+    /// that it can hold for some samples and not others.
+    pub fn is_per_sample(&self) -> bool {
+        matches!(self.expression.check(), Ok((_, true)))
+    }
+}
+
+/// Declare a FILTER ID in the header, replacing any existing `##FILTER` line for it.
+///
+/// ```
+/// use vcf::filter::{add_filter_definition, soft_filter};
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let source = b"##fileformat=VCFv4.3\
+///     \n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\
+///     \n1\t100\t.\tA\tG\t5\tPASS\t.\n";
+/// let mut reader = Reader::new(&source[..])?;
+/// let mut header = reader.header().clone();
+/// add_filter_definition(&mut header, "q10", "Quality below 10")?;
+/// assert_eq!(header.meta, vec!["##FILTER=<ID=q10,Description=\"Quality below 10\">"]);
+///
+/// let mut record = reader.read_record()?.unwrap();
+/// soft_filter(&mut record, "q10", true);
+/// assert_eq!(record.filter, vec!["q10"]);
+///# Ok::<(), VCFError>(())
+/// ```
+pub fn add_filter_definition(
+    header: &mut VCF,
+    id: &str,
+    description: &str,
+) -> Result<(), VCFError> {
+    if id.is_empty()
+        || id == "0"
+        || id == "PASS"
+        || id.contains(|c: char| c.is_whitespace() || ";,=<>\"".contains(c))
+    {
+        return Err(VCFError::InvalidHeader(format!(
+            "{:?} cannot be used as a FILTER ID",
+            id
+        )));
+    }
+    let description = escape_header_value(description);
+    header.set_header(&Header {
+        key: "FILTER",
+        value: HeaderValue::Nested(HashMap::from([("ID", id), ("Description", &*description)])),
+    });
+    Ok(())
+}
+
+/// Declare the `FT` FORMAT field used for per-sample filters, unless the header already does.
+pub fn add_sample_filter_definition(header: &mut VCF) {
+    if !header.definitions().format.contains_key("FT") {
+        header.set_header(&Header {
+            key: "FORMAT",
+            value: HeaderValue::Nested(HashMap::from([
+                ("ID", "FT"),
+                ("Number", "1"),
+                ("Type", "String"),
+                (
+                    "Description",
+                    "Filter indicating if this genotype was called",
+                ),
+            ])),
+        });
+    }
+}
+
+/// Record whether a record failed the filter `id`.
+///
+/// A failing record gets `id` added to its FILTER column, replacing `PASS`. A passing record
+/// keeps any filters it already failed, and is marked `PASS` if it had not been filtered.
+pub fn soft_filter(record: &mut Record, id: &str, failed: bool) {
+    if failed {
+        record.filter.retain(|filter| filter != "PASS");
+        if !record.filter.iter().any(|filter| filter == id) {
+            record.filter.push(id.to_string());
+        }
+    } else if record.filter.is_empty() {
+        record.filter.push("PASS".to_string());
+    }
+}
+
+/// Record which samples of a record failed the filter `id`, in their `FT` field.
+///
+/// `failed` has an entry per sample, as returned by [`Filter::matches_samples`]. The `FT` field
+/// is added to the record if it is not there yet, and is handled like the FILTER column in
+/// [`soft_filter`].
+pub fn soft_filter_samples(record: &mut Record, id: &str, failed: &[bool]) {
+    let index = match record.format.iter().position(|key| key == "FT") {
+        Some(index) => index,
+        None => {
+            record.format.push("FT".to_string());
+            record.format.len() - 1
+        }
+    };
+    for (sample, &failed) in record.samples.iter_mut().zip(failed) {
+        if sample.len() <= index {
+            sample.resize(index + 1, ".".to_string());
+        }
+        let value = &mut sample[index];
+        if failed {
+            if value == "." || value == "PASS" {
+                *value = id.to_string();
+            } else if !value.split(';').any(|filter| filter == id) {
+                value.push(';');
+                value.push_str(id);
+            }
+        } else if value == "." {
+            *value = "PASS".to_string();
+        }
+    }
+}
+
+fn error(message: String) -> VCFError {
+    VCFError::InvalidExpression(message)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Identifier(String),
+    Missing,
+    Operator(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::String(string) => write!(f, "\"{}\"", string),
+            Token::Identifier(identifier) => write!(f, "{}", identifier),
+            Token::Missing => write!(f, "."),
+            Token::Operator(operator) => write!(f, "{}", operator),
+        }
+    }
+}
+
+/// Operators, longest first so that `<=` is not read as `<`.
+const OPERATORS: [&str; 21] = [
+    "&&", "||", "==", "!=", "<=", ">=", "!~", "=", "<", ">", "~", "!", "+", "-", "*", "/", "(",
+    ")", "[", "]", ":",
+];
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, VCFError> {
+    let mut tokens = Vec::new();
+    let mut rest = input;
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[start..];
+        let offset = input.len() - rest.len();
+        let c = rest.chars().next().unwrap_or_default();
+        let length = if c.is_ascii_digit()
+            || c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())
+        {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            // Allow signed exponents, as in 1e-5.
+            let length = match rest[length..].chars().next() {
+                Some('-' | '+') if rest[..length].ends_with(['e', 'E']) => {
+                    length
+                        + 1
+                        + rest[length + 1..]
+                            .find(|c: char| !c.is_ascii_digit())
+                            .unwrap_or(rest.len() - length - 1)
+                }
+                _ => length,
+            };
+            let number = rest[..length]
+                .parse()
+                .map_err(|_| error(format!("invalid number {} at {}", &rest[..length], offset)))?;
+            tokens.push((offset, Token::Number(number)));
+            length
+        } else if c == '"' {
+            let end = rest[1..]
+                .find('"')
+                .ok_or_else(|| error(format!("unterminated string at {}", offset)))?;
+            tokens.push((offset, Token::String(rest[1..end + 1].to_string())));
+            end + 2
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '/')))
+                .unwrap_or(rest.len());
+            tokens.push((offset, Token::Identifier(rest[..length].to_string())));
+            length
+        } else if c == '.' {
+            tokens.push((offset, Token::Missing));
+            1
+        } else {
+            let operator = OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+                .ok_or_else(|| error(format!("unexpected {} at {}", c, offset)))?;
+            tokens.push((offset, Token::Operator(operator)));
+            operator.len()
+        };
+        rest = &rest[length..];
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Bool,
+    Number,
+    String,
+    /// The missing value, `.`, which can be compared with any type.
+    Missing,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Bool => write!(f, "a condition"),
+            Type::Number => write!(f, "a number"),
+            Type::String => write!(f, "a string"),
+            Type::Missing => write!(f, "missing"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Column {
+    Chrom,
+    Pos,
+    Id,
+    Ref,
+    Alt,
+    Qual,
+    Filter,
+}
+
+#[derive(Debug, Clone)]
+enum Field {
+    Column(Column),
+    Info {
+        key: String,
+        data_type: DataType,
+        index: Option<usize>,
+    },
+    Format {
+        key: String,
+        data_type: DataType,
+        /// The sample, or all samples.
+        sample: Option<usize>,
+        index: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Operator {
+    fn parse(token: &str) -> Option<(Self, u8)> {
+        let operator = match token {
+            "||" => (Operator::Or, 1),
+            "&&" => (Operator::And, 2),
+            "==" | "=" => (Operator::Equal, 3),
+            "!=" => (Operator::NotEqual, 3),
+            "<" => (Operator::Less, 3),
+            "<=" => (Operator::LessOrEqual, 3),
+            ">" => (Operator::Greater, 3),
+            ">=" => (Operator::GreaterOrEqual, 3),
+            "+" => (Operator::Add, 4),
+            "-" => (Operator::Subtract, 4),
+            "*" => (Operator::Multiply, 5),
+            "/" => (Operator::Divide, 5),
+            _ => return None,
+        };
+        Some(operator)
+    }
+}
+
+/// The binding strength of `~` and `!~`, the same as the other comparisons.
+const MATCH_PRECEDENCE: u8 = 3;
+
+#[derive(Debug, Clone)]
+enum Expression {
+    Number(f64),
+    String(String),
+    Missing,
+    Field(Field),
+    Not(Box<Expression>),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    Match {
+        value: Box<Expression>,
+        pattern: Regex,
+        negated: bool,
+    },
+    Any(Box<Expression>),
+    All(Box<Expression>),
+}
+
+struct Parser<'t> {
+    tokens: &'t [(usize, Token)],
+    position: usize,
+    definitions: &'t Definitions,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Result<&Token, VCFError> {
+        let (_, token) = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| error("unexpected end of expression".to_string()))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, operator: &str) -> Result<(), VCFError> {
+        let offset = self.tokens.get(self.position).map(|(offset, _)| *offset);
+        match self.next()? {
+            Token::Operator(found) if *found == operator => Ok(()),
+            found => Err(error(format!(
+                "expected {} but found {} at {}",
+                operator,
+                found,
+                offset.unwrap_or_default()
+            ))),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expression, VCFError> {
+        self.binary(1)
+    }
+
+    /// Parse operators binding at least as tightly as `precedence`.
+    fn binary(&mut self, precedence: u8) -> Result<Expression, VCFError> {
+        let mut left = self.unary()?;
+        while let Some(Token::Operator(operator)) = self.peek() {
+            let operator = *operator;
+            if matches!(operator, "~" | "!~") {
+                if MATCH_PRECEDENCE < precedence {
+                    break;
+                }
+                self.position += 1;
+                let pattern = match self.next()? {
+                    Token::String(pattern) => Regex::new(pattern)
+                        .map_err(|e| error(format!("invalid regular expression: {}", e)))?,
+                    found => {
+                        return Err(error(format!(
+                            "expected a string after {} but found {}",
+                            operator, found
+                        )))
+                    }
+                };
+                left = Expression::Match {
+                    value: Box::new(left),
+                    pattern,
+                    negated: operator == "!~",
+                };
+                continue;
+            }
+            let Some((operator, binding)) = Operator::parse(operator) else {
+                break;
+            };
+            if binding < precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(binding + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, VCFError> {
+        match self.peek() {
+            Some(Token::Operator("!")) => {
+                self.position += 1;
+                Ok(Expression::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Operator("-")) => {
+                self.position += 1;
+                Ok(Expression::Negate(Box::new(self.unary()?)))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, VCFError> {
+        let offset = self
+            .tokens
+            .get(self.position)
+            .map(|(offset, _)| *offset)
+            .unwrap_or_default();
+        match self.next()?.clone() {
+            Token::Number(number) => Ok(Expression::Number(number)),
+            Token::String(string) => Ok(Expression::String(string)),
+            Token::Missing => Ok(Expression::Missing),
+            Token::Operator("(") => {
+                let expression = self.expression()?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Token::Identifier(name) if matches!(self.peek(), Some(Token::Operator("("))) => {
+                self.position += 1;
+                let argument = Box::new(self.expression()?);
+                self.expect(")")?;
+                match name.as_str() {
+                    "any" => Ok(Expression::Any(argument)),
+                    "all" => Ok(Expression::All(argument)),
+                    _ => Err(error(format!("unknown function {} at {}", name, offset))),
+                }
+            }
+            Token::Identifier(name) => self.field(&name, offset),
+            token => Err(error(format!("unexpected {} at {}", token, offset))),
+        }
+    }
+
+    fn field(&mut self, name: &str, offset: usize) -> Result<Expression, VCFError> {
+        let column = match name {
+            "CHROM" => Some(Column::Chrom),
+            "POS" => Some(Column::Pos),
+            "ID" => Some(Column::Id),
+            "REF" => Some(Column::Ref),
+            "ALT" => Some(Column::Alt),
+            "QUAL" => Some(Column::Qual),
+            "FILTER" => Some(Column::Filter),
+            _ => None,
+        };
+        if let Some(column) = column {
+            return Ok(Expression::Field(Field::Column(column)));
+        }
+        let unknown = || {
+            error(format!(
+                "{} at {} is not a column, INFO or FORMAT field",
+                name, offset
+            ))
+        };
+        let (section, key) = name.split_once('/').ok_or_else(unknown)?;
+        let definitions = match section {
+            "INFO" => &self.definitions.info,
+            "FORMAT" | "FMT" => &self.definitions.format,
+            _ => return Err(unknown()),
+        };
+        let data_type = definitions
+            .get(key)
+            .ok_or_else(|| error(format!("{} is not defined in the header", name)))?
+            .fieldtype;
+        let key = key.to_string();
+
+        if section == "INFO" {
+            let index = match self.peek() {
+                Some(Token::Operator("[")) => {
+                    self.position += 1;
+                    let index = self.index()?;
+                    self.expect("]")?;
+                    index
+                }
+                _ => None,
+            };
+            return Ok(Expression::Field(Field::Info {
+                key,
+                data_type,
+                index,
+            }));
+        }
+        let (sample, index) = match self.peek() {
+            Some(Token::Operator("[")) => {
+                self.position += 1;
+                let sample = self.index()?;
+                let index = match self.peek() {
+                    Some(Token::Operator(":")) => {
+                        self.position += 1;
+                        self.index()?
+                    }
+                    _ => None,
+                };
+                self.expect("]")?;
+                (sample, index)
+            }
+            _ => (None, None),
+        };
+        Ok(Expression::Field(Field::Format {
+            key,
+            data_type,
+            sample,
+            index,
+        }))
+    }
+
+    /// A subscript: a number, or `*` for all.
+    fn index(&mut self) -> Result<Option<usize>, VCFError> {
+        match self.next()? {
+            Token::Operator("*") => Ok(None),
+            Token::Number(number) if number.fract() == 0.0 && *number >= 0.0 => {
+                Ok(Some(*number as usize))
+            }
+            found => Err(error(format!("expected a subscript but found {}", found))),
+        }
+    }
+}
+
+fn value_type(data_type: DataType) -> Type {
+    match data_type {
+        DataType::Integer(_) | DataType::Float(_) => Type::Number,
+        DataType::Character(_) | DataType::String(_) => Type::String,
+        DataType::Flag => Type::Bool,
+    }
+}
+
+impl Expression {
+    /// The type of the expression, and whether it has a value per sample.
+    fn check(&self) -> Result<(Type, bool), VCFError> {
+        let mismatch = |what: &str, found: Type| {
+            Err(error(format!(
+                "{} needs {} but found {}",
+                what,
+                Type::Number,
+                found
+            )))
+        };
+        match self {
+            Expression::Number(_) => Ok((Type::Number, false)),
+            Expression::String(_) => Ok((Type::String, false)),
+            Expression::Missing => Ok((Type::Missing, false)),
+            Expression::Field(Field::Column(column)) => match column {
+                Column::Pos | Column::Qual => Ok((Type::Number, false)),
+                _ => Ok((Type::String, false)),
+            },
+            Expression::Field(Field::Info { data_type, .. }) => Ok((value_type(*data_type), false)),
+            Expression::Field(Field::Format {
+                data_type, sample, ..
+            }) => Ok((value_type(*data_type), sample.is_none())),
+            Expression::Not(operand) => match operand.check()? {
+                (Type::Bool, per_sample) => Ok((Type::Bool, per_sample)),
+                (found, _) => Err(error(format!("! needs a condition but found {}", found))),
+            },
+            Expression::Negate(operand) => match operand.check()? {
+                (Type::Number, per_sample) => Ok((Type::Number, per_sample)),
+                (found, _) => mismatch("-", found),
+            },
+            Expression::Match { value, .. } => match value.check()? {
+                (Type::String, per_sample) => Ok((Type::Bool, per_sample)),
+                (found, _) => Err(error(format!("~ needs a string but found {}", found))),
+            },
+            Expression::Any(operand) | Expression::All(operand) => match operand.check()? {
+                (Type::Bool, _) => Ok((Type::Bool, false)),
+                (found, _) => Err(error(format!(
+                    "any and all need a condition but found {}",
+                    found
+                ))),
+            },
+            Expression::Binary(operator, left, right) => {
+                let (left, left_samples) = left.check()?;
+                let (right, right_samples) = right.check()?;
+                let per_sample = left_samples || right_samples;
+                let result = match operator {
+                    Operator::And | Operator::Or => match (left, right) {
+                        (Type::Bool, Type::Bool) => Type::Bool,
+                        _ => {
+                            return Err(error(format!(
+                                "&& and || need conditions but found {} and {}",
+                                left, right
+                            )))
+                        }
+                    },
+                    Operator::Equal | Operator::NotEqual => {
+                        if left != right && left != Type::Missing && right != Type::Missing {
+                            return Err(error(format!("cannot compare {} with {}", left, right)));
+                        }
+                        Type::Bool
+                    }
+                    Operator::Less
+                    | Operator::LessOrEqual
+                    | Operator::Greater
+                    | Operator::GreaterOrEqual => match (left, right) {
+                        (Type::Number, Type::Number) => Type::Bool,
+                        (Type::Number, found) | (found, _) => return mismatch("comparison", found),
+                    },
+                    Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide => {
+                        match (left, right) {
+                            (Type::Number, Type::Number) => Type::Number,
+                            (Type::Number, found) | (found, _) => {
+                                return mismatch("arithmetic", found)
+                            }
+                        }
+                    }
+                };
+                Ok((result, per_sample))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Missing,
+}
+
+/// The values of an expression for a record: a list of values for the site, or for each sample.
+enum Evaluated {
+    Site(Vec<Value>),
+    Samples(Vec<Vec<Value>>),
+}
+
+impl Evaluated {
+    fn map(self, f: impl Fn(Vec<Value>) -> Vec<Value>) -> Self {
+        match self {
+            Evaluated::Site(values) => Evaluated::Site(f(values)),
+            Evaluated::Samples(samples) => Evaluated::Samples(samples.into_iter().map(f).collect()),
+        }
+    }
+
+    /// Combine two results, applying a site result to every sample.
+    fn zip(self, other: Self, f: impl Fn(&[Value], &[Value]) -> Vec<Value>) -> Self {
+        match (self, other) {
+            (Evaluated::Site(a), Evaluated::Site(b)) => Evaluated::Site(f(&a, &b)),
+            (Evaluated::Site(a), Evaluated::Samples(b)) => {
+                Evaluated::Samples(b.iter().map(|b| f(&a, b)).collect())
+            }
+            (Evaluated::Samples(a), Evaluated::Site(b)) => {
+                Evaluated::Samples(a.iter().map(|a| f(a, &b)).collect())
+            }
+            (Evaluated::Samples(a), Evaluated::Samples(b)) => {
+                Evaluated::Samples(a.iter().zip(&b).map(|(a, b)| f(a, b)).collect())
+            }
+        }
+    }
+}
+
+fn is_true(values: &[Value]) -> bool {
+    values.contains(&Value::Bool(true))
+}
+
+fn parse_value(value: &str, data_type: DataType) -> Value {
+    match (value, value_type(data_type)) {
+        (".", _) => Value::Missing,
+        (value, Type::Number) => value.parse().map_or(Value::Missing, Value::Number),
+        (value, _) => Value::String(value.to_string()),
+    }
+}
+
+/// Split a field into values, selecting one when `index` is given.
+fn field_values(value: Option<&str>, data_type: DataType, index: Option<usize>) -> Vec<Value> {
+    let Some(value) = value else {
+        return vec![Value::Missing];
+    };
+    // Genotypes, and strings with a single value, may contain commas.
+    let values: Vec<&str> = match data_type {
+        DataType::String(NumberField::Number(1)) => vec![value],
+        _ => value.split(',').collect(),
+    };
+    match index {
+        Some(index) => vec![values
+            .get(index)
+            .map_or(Value::Missing, |value| parse_value(value, data_type))],
+        None => values
+            .into_iter()
+            .map(|value| parse_value(value, data_type))
+            .collect(),
+    }
+}
+
+fn strings(values: &[String]) -> Vec<Value> {
+    if values.is_empty() {
+        vec![Value::Missing]
+    } else {
+        values.iter().cloned().map(Value::String).collect()
+    }
+}
+
+/// Whether any pair of values, one from each side, satisfies a comparison.
+///
+/// Comparisons with missing values are false, unless one side is the literal `.`
+/// (`missing_literal`), in which case `==` tests for a missing value and `!=` for a present one.
+fn compare(operator: Operator, left: &[Value], right: &[Value], missing_literal: bool) -> bool {
+    left.iter().any(|left| {
+        right.iter().any(|right| match (left, right) {
+            (Value::Missing, Value::Missing) => missing_literal && operator == Operator::Equal,
+            (Value::Missing, _) | (_, Value::Missing) => {
+                missing_literal && operator == Operator::NotEqual
+            }
+            _ => {
+                let ordering = match (left, right) {
+                    (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+                    (a, b) => (a == b).then_some(std::cmp::Ordering::Equal),
+                };
+                match operator {
+                    Operator::Equal => ordering.is_some_and(|o| o.is_eq()),
+                    Operator::NotEqual => !ordering.is_some_and(|o| o.is_eq()),
+                    Operator::Less => ordering.is_some_and(|o| o.is_lt()),
+                    Operator::LessOrEqual => ordering.is_some_and(|o| o.is_le()),
+                    Operator::Greater => ordering.is_some_and(|o| o.is_gt()),
+                    Operator::GreaterOrEqual => ordering.is_some_and(|o| o.is_ge()),
+                    _ => false,
+                }
+            }
+        })
+    })
+}
+
+/// Apply an arithmetic operator value by value, repeating a single value to match the other
+/// side.
+fn arithmetic(operator: Operator, left: &[Value], right: &[Value]) -> Vec<Value> {
+    let length = if left.len() == 1 || right.len() == 1 {
+        left.len().max(right.len())
+    } else {
+        left.len().min(right.len())
+    };
+    (0..length)
+        .map(|i| {
+            let left = &left[i.min(left.len() - 1)];
+            let right = &right[i.min(right.len() - 1)];
+            match (left, right) {
+                (Value::Number(a), Value::Number(b)) => Value::Number(match operator {
+                    Operator::Add => a + b,
+                    Operator::Subtract => a - b,
+                    Operator::Multiply => a * b,
+                    _ => a / b,
+                }),
+                _ => Value::Missing,
+            }
+        })
+        .collect()
+}
+
+impl Expression {
+    fn evaluate(&self, record: &Record) -> Evaluated {
+        let truth = |value: bool| vec![Value::Bool(value)];
+        match self {
+            Expression::Number(number) => Evaluated::Site(vec![Value::Number(*number)]),
+            Expression::String(string) => Evaluated::Site(vec![Value::String(string.clone())]),
+            Expression::Missing => Evaluated::Site(vec![Value::Missing]),
+            Expression::Field(field) => field.evaluate(record),
+            Expression::Not(operand) => operand
+                .evaluate(record)
+                .map(|values| truth(!is_true(&values))),
+            Expression::Negate(operand) => operand.evaluate(record).map(|values| {
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::Number(number) => Value::Number(-number),
+                        _ => Value::Missing,
+                    })
+                    .collect()
+            }),
+            Expression::Match {
+                value,
+                pattern,
+                negated,
+            } => value.evaluate(record).map(|values| {
+                let matched = values.iter().any(|value| match value {
+                    Value::String(string) => pattern.is_match(string) != *negated,
+                    _ => false,
+                });
+                truth(matched)
+            }),
+            Expression::Any(operand) => match operand.evaluate(record) {
+                Evaluated::Site(values) => Evaluated::Site(truth(is_true(&values))),
+                Evaluated::Samples(samples) => {
+                    Evaluated::Site(truth(samples.iter().any(|values| is_true(values))))
+                }
+            },
+            Expression::All(operand) => match operand.evaluate(record) {
+                Evaluated::Site(values) => Evaluated::Site(truth(is_true(&values))),
+                Evaluated::Samples(samples) => Evaluated::Site(truth(
+                    !samples.is_empty() && samples.iter().all(|values| is_true(values)),
+                )),
+            },
+            Expression::Binary(operator, left, right) => {
+                let literal = [left, right]
+                    .iter()
+                    .any(|side| matches!(***side, Expression::Missing));
+                let (left, right) = (left.evaluate(record), right.evaluate(record));
+                match operator {
+                    Operator::And => left.zip(right, |a, b| truth(is_true(a) && is_true(b))),
+                    Operator::Or => left.zip(right, |a, b| truth(is_true(a) || is_true(b))),
+                    Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide => {
+                        left.zip(right, |a, b| arithmetic(*operator, a, b))
+                    }
+                    _ => left.zip(right, |a, b| truth(compare(*operator, a, b, literal))),
+                }
+            }
+        }
+    }
+}
+
+impl Field {
+    fn evaluate(&self, record: &Record) -> Evaluated {
+        match self {
+            Field::Column(column) => Evaluated::Site(match column {
+                Column::Chrom => vec![Value::String(record.chrom.clone())],
+                Column::Pos => vec![Value::Number(record.pos as f64)],
+                Column::Id => strings(&record.id),
+                Column::Ref => vec![Value::String(record.reference.clone())],
+                Column::Alt => strings(&record.alt),
+                Column::Qual => vec![record.qual.map_or(Value::Missing, Value::Number)],
+                Column::Filter => strings(&record.filter),
+            }),
+            Field::Info {
+                key,
+                data_type,
+                index,
+            } => Evaluated::Site(match (data_type, record.info(key)) {
+                (DataType::Flag, value) => vec![Value::Bool(value.is_some())],
+                (_, value) => field_values(value.flatten(), *data_type, *index),
+            }),
+            Field::Format {
+                key,
+                data_type,
+                sample,
+                index,
+            } => {
+                let values = |sample: usize| {
+                    let value = record.sample_value(sample, key);
+                    match data_type {
+                        DataType::Flag => vec![Value::Bool(value.is_some())],
+                        _ => field_values(value, *data_type, *index),
+                    }
+                };
+                match sample {
+                    Some(sample) => Evaluated::Site(values(*sample)),
+                    None => Evaluated::Samples((0..record.samples.len()).map(values).collect()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::Reader;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">
+##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP\">
+##INFO=<ID=GENE,Number=1,Type=String,Description=\"Gene\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype quality\">
+##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Allele depths\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tC
+1\t100\trs1\tA\tG,T\t50\tPASS\tDP=12;AF=0.25,0.5;DB;GENE=BRCA2\tGT:GQ:AD\t0/1:30:5,5,0\t1/1:10:0,9,1\t./.:.:.
+2\t200\t.\tC\tA\t.\tq10;s50\t.\tGT:GQ\t0/0:40\t0/1:25\t0/0:60
+";
+
+    fn records() -> (Definitions, Vec<Record>) {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        let definitions = reader.header().definitions();
+        let records = reader.records().collect::<Result<_, _>>().unwrap();
+        (definitions, records)
+    }
+
+    fn selects(expression: &str) -> Vec<bool> {
+        let (definitions, records) = records();
+        let filter = Filter::parse(expression, &definitions).unwrap();
+        records
+            .iter()
+            .map(|record| filter.matches(record))
+            .collect()
+    }
+
+    #[test]
+    fn compares_columns() {
+        assert_eq!(selects("QUAL > 30"), vec![true, false]);
+        assert_eq!(selects("QUAL == ."), vec![false, true]);
+        assert_eq!(selects("FILTER == \"PASS\""), vec![true, false]);
+        assert_eq!(selects("FILTER = \"s50\""), vec![false, true]);
+        assert_eq!(selects("CHROM == \"2\" && POS >= 200"), vec![false, true]);
+        assert_eq!(selects("ALT == \"T\""), vec![true, false]);
+        assert_eq!(selects("ID != ."), vec![true, false]);
+    }
+
+    #[test]
+    fn reads_info_fields() {
+        assert_eq!(selects("INFO/DP >= 10"), vec![true, false]);
+        assert_eq!(selects("INFO/DP == ."), vec![false, true]);
+        assert_eq!(selects("INFO/DP != ."), vec![true, false]);
+        assert_eq!(selects("INFO/DP != 5"), vec![true, false]);
+        assert_eq!(selects("INFO/DP < 100"), vec![true, false]);
+        assert_eq!(selects("!(INFO/DP < 100)"), vec![false, true]);
+        assert_eq!(selects("INFO/AF > 0.4"), vec![true, false]);
+        assert_eq!(selects("INFO/AF[0] > 0.4"), vec![false, false]);
+        assert_eq!(selects("INFO/DB"), vec![true, false]);
+        assert_eq!(selects("!INFO/DB"), vec![false, true]);
+        assert_eq!(selects("INFO/GENE ~ \"^BRCA\""), vec![true, false]);
+        assert_eq!(selects("INFO/GENE !~ \"^BRCA\""), vec![false, false]);
+        assert_eq!(selects("INFO/DP * 2 + 1 == 25"), vec![true, false]);
+        assert_eq!(selects("-INFO/DP < -10"), vec![true, false]);
+    }
+
+    #[test]
+    fn quantifies_over_samples() {
+        assert_eq!(selects("FORMAT/GQ > 20"), vec![true, true]);
+        assert_eq!(selects("FORMAT/GQ[*] > 35"), vec![false, true]);
+        assert_eq!(selects("all(FORMAT/GQ > 20)"), vec![false, true]);
+        assert_eq!(selects("any(FORMAT/GQ > 50)"), vec![false, true]);
+        assert_eq!(selects("FORMAT/GQ[1] > 20"), vec![false, true]);
+        assert_eq!(selects("FORMAT/AD[*:2] > 0"), vec![true, false]);
+        assert_eq!(
+            selects("FORMAT/GT == \"1/1\" && FORMAT/GQ > 20"),
+            vec![false, false]
+        );
+        assert_eq!(selects("FORMAT/GQ == ."), vec![true, false]);
+
+        let (definitions, records) = records();
+        let filter = Filter::parse("FORMAT/GQ >= 30 || QUAL > 100", &definitions).unwrap();
+        assert!(filter.is_per_sample());
+        assert_eq!(
+            filter.matches_samples(&records[0]),
+            vec![true, false, false]
+        );
+        let filter = Filter::parse("any(FORMAT/GQ >= 30)", &definitions).unwrap();
+        assert!(!filter.is_per_sample());
+        assert_eq!(filter.matches_samples(&records[1]), vec![true; 3]);
+    }
+
+    #[test]
+    fn reports_syntax_and_type_errors() {
+        let (definitions, _) = records();
+        let message = |expression: &str| {
+            Filter::parse(expression, &definitions)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            message("INFO/XX > 1"),
+            "invalid expression: INFO/XX is not defined in the header"
+        );
+        assert_eq!(
+            message("QUAL > \"high\""),
+            "invalid expression: comparison needs a number but found a string"
+        );
+        assert_eq!(
+            message("INFO/DP"),
+            "invalid expression: the expression is a number, not a condition"
+        );
+        assert_eq!(
+            message("QUAL > 30 &&"),
+            "invalid expression: unexpected end of expression"
+        );
+        assert_eq!(
+            message("(QUAL > 30"),
+            "invalid expression: unexpected end of expression"
+        );
+        assert_eq!(
+            message("QUAL > 30 )"),
+            "invalid expression: unexpected ) at 10"
+        );
+        assert_eq!(
+            message("QUAL # 30"),
+            "invalid expression: unexpected # at 5"
+        );
+        assert!(message("INFO/GENE ~ \"[\"").starts_with("invalid expression: invalid regular"));
+        assert!(message("INFO/DB > 1").contains("needs a number but found a condition"));
+    }
+
+    #[test]
+    fn tokenizes_numbers() {
+        let tokens: Vec<Token> = tokenize("1e-5 .5 3 . 2.0")
+            .unwrap()
+            .into_iter()
+            .map(|(_, token)| token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(1e-5),
+                Token::Number(0.5),
+                Token::Number(3.0),
+                Token::Missing,
+                Token::Number(2.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn sets_filter_columns() {
+        let (_, mut records) = records();
+        soft_filter(&mut records[0], "lowdp", true);
+        soft_filter(&mut records[1], "lowdp", false);
+        assert_eq!(records[0].filter, vec!["lowdp"]);
+        assert_eq!(records[1].filter, vec!["q10", "s50"]);
+        soft_filter(&mut records[1], "q10", true);
+        assert_eq!(records[1].filter, vec!["q10", "s50"]);
+        records[1].filter.clear();
+        soft_filter(&mut records[1], "lowdp", false);
+        assert_eq!(records[1].filter, vec!["PASS"]);
+    }
+
+    #[test]
+    fn sets_sample_filters() {
+        let (definitions, mut records) = records();
+        let filter = Filter::parse("FORMAT/GQ < 20", &definitions).unwrap();
+        let failed = filter.matches_samples(&records[0]);
+        soft_filter_samples(&mut records[0], "lowgq", &failed);
+        assert_eq!(
+            records[0]
+                .to_string()
+                .split('\t')
+                .skip(8)
+                .collect::<Vec<_>>(),
+            vec![
+                "GT:GQ:AD:FT",
+                "0/1:30:5,5,0:PASS",
+                "1/1:10:0,9,1:lowgq",
+                "./.:.:.:PASS"
+            ]
+        );
+        soft_filter_samples(&mut records[0], "other", &[true, true, false]);
+        assert_eq!(records[0].sample_value(0, "FT"), Some("other"));
+        assert_eq!(records[0].sample_value(1, "FT"), Some("lowgq;other"));
+
+        // Samples with trailing fields dropped are padded before FT.
+        records[1].samples[0].truncate(1);
+        soft_filter_samples(&mut records[1], "lowgq", &[true, false, false]);
+        assert_eq!(records[1].samples[0], vec!["0/0", ".", "lowgq"]);
+    }
+
+    #[test]
+    fn declares_filters_in_the_header() {
+        let mut header = Reader::new(SOURCE.as_bytes()).unwrap().into_header();
+        add_filter_definition(&mut header, "lowgq", "GQ below 20").unwrap();
+        add_filter_definition(&mut header, "lowgq", "GQ below 20").unwrap();
+        add_sample_filter_definition(&mut header);
+        add_sample_filter_definition(&mut header);
+        let filters: Vec<_> = header.headers().filter(|h| h.key == "FILTER").collect();
+        assert_eq!(filters.len(), 1);
+        assert_eq!(filters[0].id(), Some("lowgq"));
+        assert!(header.definitions().format.contains_key("FT"));
+        assert_eq!(header.meta.len(), 9);
+        assert!(add_filter_definition(&mut header, "low gq", "").is_err());
+        assert!(add_filter_definition(&mut header, "0", "").is_err());
+    }
+}
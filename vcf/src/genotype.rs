@@ -0,0 +1,137 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::parse::ParseError;
+
+/// A parsed GT value.
+///
+/// `phased[i]` records whether the separator before allele `i` was `|`. For the first allele
+/// this is the VCF 4.4 explicit phasing prefix, e.g. `|0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Genotype {
+    pub alleles: Vec<Option<usize>>,
+    pub phased: Vec<bool>,
+}
+
+impl Genotype {
+    pub fn ploidy(&self) -> usize {
+        self.alleles.len()
+    }
+
+    pub fn is_missing(&self) -> bool {
+        self.alleles.iter().all(Option::is_none)
+    }
+}
+
+impl FromStr for Genotype {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut alleles = Vec::new();
+        let mut phased = Vec::new();
+        let mut rest = input;
+        let mut separator = match rest.strip_prefix(['|', '/']) {
+            Some(stripped) => {
+                let prefix = rest.starts_with('|');
+                rest = stripped;
+                prefix
+            }
+            None => false,
+        };
+        loop {
+            let end = rest.find(['|', '/']).unwrap_or(rest.len());
+            let allele = match &rest[..end] {
+                "." => None,
+                index => Some(index.parse().map_err(|_| ParseError)?),
+            };
+            alleles.push(allele);
+            phased.push(separator);
+            if end == rest.len() {
+                break;
+            }
+            separator = rest[end..].starts_with('|');
+            rest = &rest[end + 1..];
+        }
+        Ok(Self { alleles, phased })
+    }
+}
+
+impl fmt::Display for Genotype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (allele, phased)) in self.alleles.iter().zip(&self.phased).enumerate() {
+            match (i, phased) {
+                (_, true) => write!(f, "|")?,
+                (0, false) => {}
+                (_, false) => write!(f, "/")?,
+            }
+            match allele {
+                Some(allele) => write!(f, "{}", allele)?,
+                None => write!(f, ".")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The position of a genotype in the ordering used by Number=G fields.
+///
+/// `alleles` must be sorted in increasing order.
+pub fn genotype_index(alleles: &[usize]) -> usize {
+    alleles
+        .iter()
+        .enumerate()
+        .map(|(k, &allele)| binomial(allele + k, k + 1))
+        .sum()
+}
+
+/// The number of values of a Number=G field for the given allele count and ploidy.
+pub fn genotype_count(allele_count: usize, ploidy: usize) -> usize {
+    binomial(allele_count + ploidy - 1, ploidy)
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_genotypes() {
+        for gt in ["0/1", "1|2", "./.", ".", "0", "|0|1", "0/1/2"] {
+            assert_eq!(gt.parse::<Genotype>().unwrap().to_string(), gt);
+        }
+    }
+
+    #[test]
+    fn parses_alleles_and_phasing() {
+        let gt: Genotype = "0|.".parse().unwrap();
+        assert_eq!(gt.alleles, vec![Some(0), None]);
+        assert_eq!(gt.phased, vec![false, true]);
+        assert_eq!(gt.ploidy(), 2);
+        assert!(!gt.is_missing());
+        assert!("./.".parse::<Genotype>().unwrap().is_missing());
+    }
+
+    #[test]
+    fn rejects_invalid_genotypes() {
+        assert!("0/x".parse::<Genotype>().is_err());
+        assert!("0//1".parse::<Genotype>().is_err());
+    }
+
+    #[test]
+    fn computes_genotype_ordering() {
+        // Diploid, three alleles: 0/0 0/1 1/1 0/2 1/2 2/2
+        assert_eq!(genotype_index(&[0, 0]), 0);
+        assert_eq!(genotype_index(&[1, 1]), 2);
+        assert_eq!(genotype_index(&[0, 2]), 3);
+        assert_eq!(genotype_index(&[2, 2]), 5);
+        assert_eq!(genotype_index(&[2]), 2);
+        assert_eq!(genotype_count(3, 2), 6);
+        assert_eq!(genotype_count(2, 3), 4);
+    }
+}
@@ -0,0 +1,413 @@
+//! gVCF reference blocks.
+//!
+//! A reference block is a record whose only ALT allele is `<*>` or `<NON_REF>`, and which
+//! covers the positions from POS to INFO END with a single set of genotypes.
+use crate::fasta::ReferenceSequence;
+use crate::vcf::{VCFError, VCF};
+use crate::{Allele, Genotype, Interval, Record};
+
+/// Header lines used by reference blocks, with the IDs they define.
+const DEFINITIONS: [(&str, &str, &str); 2] = [
+    (
+        "INFO",
+        "END",
+        "##INFO=<ID=END,Number=1,Type=Integer,Description=\"Stop position of the interval\">",
+    ),
+    (
+        "FORMAT",
+        "MIN_DP",
+        "##FORMAT=<ID=MIN_DP,Number=1,Type=Integer,Description=\"Minimum DP observed within the GVCF block\">",
+    ),
+];
+
+/// Whether `record` is a reference block, or a single reference-only site in a gVCF.
+pub fn is_reference_block(record: &Record) -> bool {
+    !record.alt.is_empty()
+        && record
+            .alt
+            .iter()
+            .all(|alt| alt.parse::<Allele>().is_ok_and(|a| a.is_unspecified()))
+}
+
+/// A view of a reference block record.
+#[derive(Debug, Clone, Copy)]
+pub struct ReferenceBlock<'r> {
+    record: &'r Record,
+}
+
+impl<'r> ReferenceBlock<'r> {
+    pub fn new(record: &'r Record) -> Option<Self> {
+        is_reference_block(record).then_some(Self { record })
+    }
+
+    pub fn interval(&self) -> Interval {
+        self.record.interval()
+    }
+
+    /// The genotype of `sample` at `position`, or `None` if the block does not cover it.
+    pub fn genotype_at(&self, position: u64, sample: usize) -> Option<Genotype> {
+        if !self.interval().contains(position) {
+            return None;
+        }
+        self.record.sample_value(sample, "GT")?.parse().ok()
+    }
+
+    /// The minimum depth of `sample` over the block, from MIN_DP or, failing that, DP.
+    pub fn min_dp(&self, sample: usize) -> Option<u32> {
+        self.record
+            .sample_value(sample, "MIN_DP")
+            .or_else(|| self.record.sample_value(sample, "DP"))?
+            .parse()
+            .ok()
+    }
+}
+
+/// Add the `##INFO` END and `##FORMAT` MIN_DP definitions to the header if they are missing.
+pub fn add_definitions(vcf: &mut VCF) {
+    let definitions = vcf.definitions();
+    for (key, id, line) in DEFINITIONS {
+        let defined = match key {
+            "INFO" => definitions.info.contains_key(id),
+            _ => definitions.format.contains_key(id),
+        };
+        if !defined {
+            vcf.meta.push(line.to_string());
+        }
+    }
+}
+
+/// Expand a reference block into one record per position, taking REF from the reference.
+///
+/// INFO END is dropped; all other fields are copied to every record. Records that are not
+/// reference blocks are returned unchanged.
+pub fn expand(
+    record: &Record,
+    reference: &mut impl ReferenceSequence,
+) -> Result<Vec<Record>, VCFError> {
+    if !is_reference_block(record) {
+        return Ok(vec![record.clone()]);
+    }
+    let interval = record.interval();
+    let bases = reference.fetch(&record.chrom, interval.start, interval.end)?;
+    let mut template = record.clone();
+    template.remove_info("END");
+    Ok(bases
+        .chars()
+        .zip(interval.start..)
+        .map(|(base, pos)| Record {
+            pos,
+            reference: base.to_string(),
+            ..template.clone()
+        })
+        .collect())
+}
+
+/// Collapse runs of adjacent reference-only records into reference blocks.
+///
+/// Records are joined while they are contiguous, and every sample keeps the same genotype
+/// and a GQ in the same band. `bands` are the ascending lower bounds of the GQ bands after
+/// the first: with `[20, 60]`, GQs 0-19, 20-59 and 60 upwards each form a band.
+///
+/// A block has FORMAT GT:DP:GQ:MIN_DP, with the median DP (the upper of the two middle values
+/// when there is an even number), the minimum GQ and the minimum depth of each sample over its
+/// records. Other INFO and FORMAT keys are kept where every record of the run has the same
+/// values for them, and dropped otherwise. Other records pass through unchanged.
+pub fn collapse<I>(records: I, bands: &[u32]) -> Collapse<'_, I>
+where
+    I: Iterator<Item = Result<Record, VCFError>>,
+{
+    Collapse {
+        records,
+        bands,
+        pending: None,
+    }
+}
+
+/// The FORMAT keys of a block whose values are computed from its records.
+const BLOCK_FORMAT: [&str; 4] = ["GT", "DP", "GQ", "MIN_DP"];
+
+pub struct Collapse<'b, I> {
+    records: I,
+    bands: &'b [u32],
+    pending: Option<Record>,
+}
+
+/// The per-sample values a block is built from.
+struct Run {
+    first: Record,
+    end: u64,
+    keys: Vec<(Option<String>, Option<usize>)>,
+    depths: Vec<Vec<u32>>,
+    min_gq: Vec<Option<u32>>,
+    min_dp: Vec<Option<u32>>,
+    /// The INFO entries, and the other FORMAT keys with their sample values, shared by every
+    /// record so far.
+    info: Vec<(String, Option<String>)>,
+    format: Vec<(String, Vec<Option<String>>)>,
+}
+
+/// For each sample, the genotype and GQ band that must stay the same across a block.
+fn keys(record: &Record, bands: &[u32]) -> Vec<(Option<String>, Option<usize>)> {
+    (0..record.samples.len())
+        .map(|sample| {
+            let gt = record.sample_value(sample, "GT").map(str::to_string);
+            let band =
+                gq(record, sample).map(|gq| bands.iter().take_while(|&&bound| bound <= gq).count());
+            (gt, band)
+        })
+        .collect()
+}
+
+fn sample_values(record: &Record, key: &str) -> Vec<Option<String>> {
+    (0..record.samples.len())
+        .map(|sample| record.sample_value(sample, key).map(str::to_string))
+        .collect()
+}
+
+fn gq(record: &Record, sample: usize) -> Option<u32> {
+    record.sample_value(sample, "GQ")?.parse().ok()
+}
+
+fn min(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl Run {
+    fn new(record: Record, bands: &[u32]) -> Self {
+        let samples = record.samples.len();
+        let mut run = Run {
+            end: record.interval().end,
+            keys: keys(&record, bands),
+            depths: vec![Vec::new(); samples],
+            min_gq: vec![None; samples],
+            min_dp: vec![None; samples],
+            info: record
+                .info
+                .iter()
+                .filter(|(key, _)| key != "END")
+                .cloned()
+                .collect(),
+            format: record
+                .format
+                .iter()
+                .filter(|key| !BLOCK_FORMAT.contains(&key.as_str()))
+                .map(|key| (key.clone(), sample_values(&record, key)))
+                .collect(),
+            first: record.clone(),
+        };
+        run.add_values(&record);
+        run
+    }
+
+    fn add_values(&mut self, record: &Record) {
+        for sample in 0..self.depths.len() {
+            let dp = record
+                .sample_value(sample, "DP")
+                .and_then(|dp| dp.parse::<u32>().ok());
+            let block = ReferenceBlock { record };
+            self.depths[sample].extend(dp);
+            self.min_gq[sample] = min(self.min_gq[sample], gq(record, sample));
+            self.min_dp[sample] = min(self.min_dp[sample], block.min_dp(sample));
+        }
+        self.info
+            .retain(|(key, value)| record.info(key) == Some(value.as_deref()));
+        self.format
+            .retain(|(key, values)| sample_values(record, key) == *values);
+        self.end = record.interval().end;
+    }
+
+    fn into_record(mut self) -> Record {
+        if self.end == self.first.interval().end {
+            return self.first;
+        }
+        let missing = |value: Option<u32>| value.map_or(".".to_string(), |v| v.to_string());
+        let samples =
+            (0..self.depths.len())
+                .map(|sample| {
+                    let depths = &mut self.depths[sample];
+                    depths.sort_unstable();
+                    let mut values = vec![
+                        self.keys[sample]
+                            .0
+                            .clone()
+                            .unwrap_or_else(|| ".".to_string()),
+                        missing(depths.get(depths.len() / 2).copied()),
+                        missing(self.min_gq[sample]),
+                        missing(self.min_dp[sample]),
+                    ];
+                    values.extend(self.format.iter().map(|(_, values)| {
+                        values[sample].clone().unwrap_or_else(|| ".".to_string())
+                    }));
+                    values
+                })
+                .collect();
+        let mut record = self.first;
+        record.reference.truncate(1);
+        record.info = vec![("END".to_string(), Some(self.end.to_string()))];
+        record.info.extend(self.info);
+        record.format = BLOCK_FORMAT.map(str::to_string).to_vec();
+        record
+            .format
+            .extend(self.format.into_iter().map(|(key, _)| key));
+        record.samples = samples;
+        record
+    }
+}
+
+impl<I> Iterator for Collapse<'_, I>
+where
+    I: Iterator<Item = Result<Record, VCFError>>,
+{
+    type Item = Result<Record, VCFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self
+            .pending
+            .take()
+            .map(Ok)
+            .or_else(|| self.records.next())?
+        {
+            Ok(record) => record,
+            Err(error) => return Some(Err(error)),
+        };
+        if !is_reference_block(&first) {
+            return Some(Ok(first));
+        }
+        let bands = self.bands;
+        let mut run = Run::new(first, bands);
+        for record in self.records.by_ref() {
+            let record = match record {
+                Ok(record) => record,
+                Err(error) => return Some(Err(error)),
+            };
+            let joins = is_reference_block(&record)
+                && record.chrom == run.first.chrom
+                && record.pos == run.end + 1
+                && keys(&record, bands) == run.keys;
+            if joins {
+                run.add_values(&record);
+            } else {
+                self.pending = Some(record);
+                break;
+            }
+        }
+        Some(Ok(run.into_record()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn records(lines: &[&str]) -> Vec<Result<Record, VCFError>> {
+        lines.iter().map(|line| Ok(Record::parse(line)?)).collect()
+    }
+
+    #[test]
+    fn recognises_reference_blocks() {
+        let block =
+            Record::parse("1\t100\t.\tA\t<NON_REF>\t.\t.\tEND=200\tGT:MIN_DP\t0/0:12").unwrap();
+        assert!(is_reference_block(&block));
+        assert!(!is_reference_block(
+            &Record::parse("1\t100\t.\tA\tG,<NON_REF>\t.\t.\t.").unwrap()
+        ));
+        assert!(!is_reference_block(
+            &Record::parse("1\t100\t.\tA\t.\t.\t.\t.").unwrap()
+        ));
+
+        let block = ReferenceBlock::new(&block).unwrap();
+        assert_eq!(block.interval(), Interval::new(100, 200));
+        assert_eq!(block.genotype_at(150, 0), Some("0/0".parse().unwrap()));
+        assert_eq!(block.genotype_at(201, 0), None);
+        assert_eq!(block.min_dp(0), Some(12));
+    }
+
+    #[test]
+    fn expands_blocks_into_positions() {
+        let mut reference = HashMap::from([("1".to_string(), "ACGTACGT".to_string())]);
+        let block = Record::parse("1\t3\t.\tG\t<*>\t.\t.\tEND=5\tGT:GQ\t0/0:30").unwrap();
+        let expanded = expand(&block, &mut reference).unwrap();
+        let lines: Vec<String> = expanded.iter().map(Record::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "1\t3\t.\tG\t<*>\t.\t.\t.\tGT:GQ\t0/0:30",
+                "1\t4\t.\tT\t<*>\t.\t.\t.\tGT:GQ\t0/0:30",
+                "1\t5\t.\tA\t<*>\t.\t.\t.\tGT:GQ\t0/0:30",
+            ]
+        );
+    }
+
+    #[test]
+    fn collapses_runs_by_gq_band() {
+        let input = records(&[
+            "1\t1\t.\tA\t<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/0:10:25",
+            "1\t2\t.\tC\t<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/0:12:21",
+            "1\t3\t.\tG\t<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/0:8:30",
+            "1\t4\t.\tT\t<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/0:9:10",
+            "1\t5\t.\tA\tG,<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/1:9:10",
+            "1\t6\t.\tA\t<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/0:9:10",
+        ]);
+        let collapsed = collapse(input.into_iter(), &[20, 60])
+            .map(|r| r.unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            collapsed,
+            vec![
+                "1\t1\t.\tA\t<NON_REF>\t.\t.\tEND=3\tGT:DP:GQ:MIN_DP\t0/0:10:21:8",
+                "1\t4\t.\tT\t<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/0:9:10",
+                "1\t5\t.\tA\tG,<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/1:9:10",
+                "1\t6\t.\tA\t<NON_REF>\t.\t.\t.\tGT:DP:GQ\t0/0:9:10",
+            ]
+        );
+    }
+
+    #[test]
+    fn collapse_extends_existing_blocks() {
+        let input = records(&[
+            "1\t1\t.\tA\t<*>\t.\t.\tEND=10\tGT:DP:GQ:MIN_DP\t0/0:10:25:4",
+            "1\t11\t.\tC\t<*>\t.\t.\t.\tGT:DP:GQ\t0/0:12:21",
+            "2\t12\t.\tG\t<*>\t.\t.\t.\tGT:DP:GQ\t0/0:12:21",
+        ]);
+        let collapsed = collapse(input.into_iter(), &[20])
+            .map(|r| r.unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            collapsed[0],
+            "1\t1\t.\tA\t<*>\t.\t.\tEND=11\tGT:DP:GQ:MIN_DP\t0/0:12:21:4"
+        );
+        assert_eq!(collapsed.len(), 2);
+    }
+
+    #[test]
+    fn collapse_keeps_values_shared_by_the_whole_run() {
+        let input = records(&[
+            "1\t1\t.\tA\t<*>\t.\t.\tMQ=60;X=1\tGT:DP:GQ:PL:AD\t0/0:10:25:0,30,300:10,0",
+            "1\t2\t.\tC\t<*>\t.\t.\tMQ=60;X=2\tGT:AD:DP:GQ:PL\t0/0:10,0:12:21:0,21,200",
+            "1\t3\t.\tG\t<*>\t.\t.\tMQ=60\tGT:DP:GQ:PL:AD\t0/0:11:30:0,30,300:10,0",
+        ]);
+        let collapsed = collapse(input.into_iter(), &[20])
+            .map(|r| r.unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            collapsed,
+            vec!["1\t1\t.\tA\t<*>\t.\t.\tEND=3;MQ=60\tGT:DP:GQ:MIN_DP:AD\t0/0:11:21:10:10,0"]
+        );
+    }
+
+    #[test]
+    fn adds_missing_definitions() {
+        let mut vcf = VCF::default();
+        add_definitions(&mut vcf);
+        add_definitions(&mut vcf);
+        let definitions = vcf.definitions();
+        assert!(definitions.info.contains_key("END"));
+        assert!(definitions.format.contains_key("MIN_DP"));
+        assert_eq!(vcf.meta.len(), 2);
+    }
+}
@@ -1,5 +1,13 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
 
+/// A meta-information line, borrowed from the line it was parsed from.
+///
+/// Values are kept as written between their quotes, so the `\\` and `\"` escapes of quoted
+/// values are still in them: use [`Header::get`] for their text, and
+/// [`escape_header_value`] for text that may contain quotes or backslashes before putting it
+/// in a value.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Header<'src> {
     pub key: &'src str,
@@ -12,10 +20,161 @@ pub enum HeaderValue<'src> {
     Nested(HashMap<&'src str, &'src str>),
 }
 
+/// Keys written first in structured lines, in this order; the others follow alphabetically.
+const LEADING_KEYS: [&str; 4] = ["ID", "Number", "Type", "Description"];
+
+/// Keys whose values the specification requires to be quoted.
+const QUOTED_KEYS: [&str; 3] = ["Description", "Source", "Version"];
+
+impl<'src> Header<'src> {
+    /// The `ID` of a structured line.
+    pub fn id(&self) -> Option<&'src str> {
+        match &self.value {
+            HeaderValue::Nested(fields) => fields.get("ID").copied(),
+            HeaderValue::Flat(_) => None,
+        }
+    }
+
+    /// The value of the field `key` of a structured line, with its escapes undone.
+    pub fn get(&self, key: &str) -> Option<Cow<'src, str>> {
+        match &self.value {
+            HeaderValue::Nested(fields) => fields.get(key).map(|v| unescape_header_value(v)),
+            HeaderValue::Flat(_) => None,
+        }
+    }
+}
+
+/// `value` with quotes and backslashes escaped, to be written in a quoted header value.
+pub fn escape_header_value(value: &str) -> Cow<'_, str> {
+    match value.contains(['\\', '"']) {
+        true => Cow::Owned(value.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => Cow::Borrowed(value),
+    }
+}
+
+/// `value` with the `\\` and `\"` escapes of a quoted header value undone.
+pub fn unescape_header_value(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(next @ ('\\' | '"'))) => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+impl fmt::Display for Header<'_> {
+    /// Write the line as it appears in a file. Descriptions, sources and versions, and values
+    /// that would otherwise be misread, are quoted.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = match &self.value {
+            HeaderValue::Flat(value) => return write!(f, "##{}={}", self.key, value),
+            HeaderValue::Nested(fields) => fields,
+        };
+        let mut keys: Vec<&str> = fields.keys().copied().collect();
+        keys.sort_by_key(|key| {
+            let position = LEADING_KEYS.iter().position(|k| k == key);
+            (position.unwrap_or(LEADING_KEYS.len()), *key)
+        });
+        write!(f, "##{}=<", self.key)?;
+        for (i, key) in keys.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            let value = fields[key];
+            if QUOTED_KEYS.contains(key) || value.contains([',', '"', '<', '>', '=', ' ']) {
+                write!(f, "{}=\"{}\"", key, value)?;
+            } else {
+                write!(f, "{}={}", key, value)?;
+            }
+        }
+        write!(f, ">")
+    }
+}
+
+/// Headers are serialized with their values unescaped, as a string for flat lines and as a map
+/// for structured ones. As unescaped values cannot be borrowed, they are deserialized into an
+/// owned [`HeaderLine`] instead.
+#[cfg(feature = "serde")]
+mod serialization {
+    use std::collections::HashMap;
+
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Serialize, Serializer};
+
+    use super::{escape_header_value, unescape_header_value, Header, HeaderValue};
+
+    impl Serialize for Header<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut header = serializer.serialize_struct("Header", 2)?;
+            header.serialize_field("key", self.key)?;
+            header.serialize_field("value", &self.value)?;
+            header.end()
+        }
+    }
+
+    impl Serialize for HeaderValue<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                HeaderValue::Flat(value) => serializer.serialize_str(value),
+                HeaderValue::Nested(fields) => serializer.collect_map(
+                    fields.iter().map(|(key, value)| (key, unescape_header_value(value))),
+                ),
+            }
+        }
+    }
+
+    /// A serialized [`Header`], owning its unescaped values.
+    #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+    pub struct HeaderLine {
+        pub key: String,
+        pub value: LineValue,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+    #[serde(untagged)]
+    pub enum LineValue {
+        Flat(String),
+        Nested(HashMap<String, String>),
+    }
+
+    impl std::fmt::Display for HeaderLine {
+        /// Write the line as [`Header`] does, escaping its values.
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let escaped: HashMap<&str, _>;
+            let value = match &self.value {
+                LineValue::Flat(value) => HeaderValue::Flat(value),
+                LineValue::Nested(fields) => {
+                    escaped = fields
+                        .iter()
+                        .map(|(k, v)| (k.as_str(), escape_header_value(v)))
+                        .collect();
+                    HeaderValue::Nested(escaped.iter().map(|(k, v)| (*k, v.as_ref())).collect())
+                }
+            };
+            write!(f, "{}", Header { key: &self.key, value })
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+pub use serialization::{HeaderLine, LineValue};
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
+    use super::escape_header_value;
+    #[cfg(feature = "serde")]
+    use super::HeaderLine;
     use crate::{Header, HeaderValue};
 
     #[test]
@@ -88,4 +247,66 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn writes_lines_that_parse_back() {
+        let input = "##FILTER=<Description=\"Quality below 30, or missing\",ID=q30>";
+        let header = Header::parse(input).unwrap();
+        let written = header.to_string();
+        assert_eq!(
+            written,
+            "##FILTER=<ID=q30,Description=\"Quality below 30, or missing\">"
+        );
+        assert_eq!(Header::parse(&written), Ok(header));
+
+        let input = "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Frequency\",Source=\"gnomad\",Version=\"4.1\">";
+        assert_eq!(Header::parse(input).unwrap().to_string(), input);
+
+        let flat = Header::parse("##source=vcf-parser").unwrap();
+        assert_eq!(flat.to_string(), "##source=vcf-parser");
+        assert_eq!(flat.id(), None);
+    }
+
+    #[test]
+    fn keeps_escapes_in_values_and_undoes_them_on_request() {
+        let input = r#"##INFO=<ID=CSQ,Number=.,Type=String,Description="Format: \"Allele|Gene\", C:\\vep">"#;
+        let header = Header::parse(input).unwrap();
+        let HeaderValue::Nested(fields) = &header.value else {
+            panic!("not a structured line");
+        };
+        assert_eq!(fields["Description"], r#"Format: \"Allele|Gene\", C:\\vep"#);
+        assert_eq!(
+            header.get("Description").unwrap(),
+            r#"Format: "Allele|Gene", C:\vep"#
+        );
+        let written = header.to_string();
+        assert_eq!(written, input);
+        assert_eq!(Header::parse(&written), Ok(header));
+        assert_eq!(escape_header_value(r#"C:\vep "x""#), r#"C:\\vep \"x\""#);
+
+        let unquoted = Header::parse(r"##INFO=<ID=X,Path=C:\tmp>").unwrap();
+        assert_eq!(unquoted.to_string(), r"##INFO=<ID=X,Path=C:\tmp>");
+        assert_eq!(unquoted.get("Path").unwrap(), r"C:\tmp");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_to_json() {
+        let header = Header::parse("##INFO=<ID=DP,Number=1,Description=\"Depth\">").unwrap();
+        let json = serde_json::to_string(&header).unwrap();
+        let parsed: HeaderLine = serde_json::from_str(&json).unwrap();
+        assert_eq!(Header::parse(&parsed.to_string()), Ok(header));
+
+        let line = r#"##FILTER=<ID=q30,Description="Below \"30\", or \\missing">"#;
+        let json = serde_json::to_string(&Header::parse(line).unwrap()).unwrap();
+        assert!(json.contains(r#""Below \"30\", or \\missing""#));
+        let parsed: HeaderLine = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.to_string(), line);
+
+        let flat = Header::parse("##source=vcf-parser").unwrap();
+        assert_eq!(
+            serde_json::to_string(&flat).unwrap(),
+            r#"{"key":"source","value":"vcf-parser"}"#
+        );
+    }
 }
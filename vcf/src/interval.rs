@@ -0,0 +1,338 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::parse::ParseError;
+use crate::{Allele, Record};
+
+/// Symbolic allele types whose SVLEN extends the reference span of a record.
+const SPANNING_SYMBOLIC: [&str; 4] = ["DEL", "DUP", "INV", "CNV"];
+
+/// A 1-based closed interval of reference positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Interval {
+    pub fn new(start: u64, end: u64) -> Self {
+        Self { start, end }
+    }
+
+    pub fn contains(&self, position: u64) -> bool {
+        self.start <= position && position <= self.end
+    }
+
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end + 1 - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end < self.start
+    }
+}
+
+/// A contig and, optionally, an interval on it, written `chr`, `chr:start` or `chr:start-end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub chrom: String,
+    pub interval: Interval,
+}
+
+impl Region {
+    /// Whether any of the reference interval covered by `record` lies in the region.
+    pub fn overlaps(&self, record: &Record) -> bool {
+        record.chrom == self.chrom && record.interval().overlaps(&self.interval)
+    }
+}
+
+impl FromStr for Region {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let number = |s: &str| s.replace(',', "").parse::<u64>().map_err(|_| ParseError);
+        let (chrom, interval) = match input.rsplit_once(':') {
+            Some((chrom, range)) => {
+                let interval = match range.split_once('-') {
+                    Some((start, "")) => Interval::new(number(start)?, u64::MAX),
+                    Some((start, end)) => Interval::new(number(start)?, number(end)?),
+                    None => Interval::new(number(range)?, number(range)?),
+                };
+                (chrom, interval)
+            }
+            None => (input, Interval::new(1, u64::MAX)),
+        };
+        if chrom.is_empty() || interval.start == 0 || interval.is_empty() {
+            return Err(ParseError);
+        }
+        Ok(Self {
+            chrom: chrom.to_string(),
+            interval,
+        })
+    }
+}
+
+/// Orders contigs as listed in a header or added with [`ContigOrder::push`], with other contigs
+/// after them in natural order, so that `chr2` comes before `chr10`.
+#[derive(Debug, Clone, Default)]
+pub struct ContigOrder {
+    index: HashMap<String, usize>,
+}
+
+impl ContigOrder {
+    pub fn new(contigs: impl IntoIterator<Item = String>) -> Self {
+        let mut index = HashMap::new();
+        for contig in contigs {
+            let next = index.len();
+            index.entry(contig).or_insert(next);
+        }
+        Self { index }
+    }
+
+    /// Place `contig` after the contigs already ordered, unless it already has a place.
+    pub fn push(&mut self, contig: &str) {
+        if !self.index.contains_key(contig) {
+            let next = self.index.len();
+            self.index.insert(contig.to_string(), next);
+        }
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match (self.index.get(a), self.index.get(b)) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => natural_cmp(a, b),
+        }
+    }
+
+    /// Compare records by contig, then by the start and end of their reference intervals.
+    pub fn compare_records(&self, a: &Record, b: &Record) -> Ordering {
+        self.compare(&a.chrom, &b.chrom)
+            .then_with(|| a.interval().cmp(&b.interval()))
+    }
+}
+
+/// Compare strings treating runs of digits as numbers.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn chunks(s: &str) -> impl Iterator<Item = &str> {
+        let mut rest = s;
+        std::iter::from_fn(move || {
+            let first = rest.chars().next()?;
+            let end = rest
+                .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let (chunk, tail) = rest.split_at(end);
+            rest = tail;
+            Some(chunk)
+        })
+    }
+    let mut a_chunks = chunks(a);
+    let mut b_chunks = chunks(b);
+    loop {
+        let ordering = match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y))
+                if x.starts_with(|c: char| c.is_ascii_digit())
+                    && y.starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn integers(value: Option<Option<&str>>) -> Vec<i64> {
+    value
+        .flatten()
+        .map(|v| v.split(',').filter_map(|n| n.parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+impl Record {
+    /// The reference positions the record covers, including the padding base at POS.
+    ///
+    /// The end is taken from INFO END when present. Otherwise, for `<DEL>`, `<DUP>`, `<INV>` and
+    /// `<CNV>` alleles (and their subtypes) it is POS plus the longest SVLEN; VCF 4.4 writes
+    /// SVLEN as a positive length, earlier versions as negative for deletions, so its absolute
+    /// value is used. In all other cases the record covers the bases of REF. SVCLAIM only
+    /// qualifies the evidence for a variant and does not change its span.
+    ///
+    /// ```
+    /// use vcf::{Interval, Record};
+    ///# use vcf::vcf::VCFError;
+    /// let record = Record::parse("1\t100\t.\tA\t<DEL>\t.\t.\tSVLEN=50")?;
+    /// assert_eq!(record.interval(), Interval::new(100, 150));
+    ///# Ok::<(), VCFError>(())
+    /// ```
+    pub fn interval(&self) -> Interval {
+        let start = self.pos;
+        let reference_end = start + (self.reference.len() as u64).max(1) - 1;
+        let end = match self.info("END").flatten().map(str::parse::<u64>) {
+            Some(Ok(end)) => end,
+            _ => {
+                let spans_reference = self.alt.iter().any(|alt| {
+                    matches!(alt.parse(), Ok(Allele::Symbolic(id))
+                        if SPANNING_SYMBOLIC.contains(&id.split(':').next().unwrap_or_default()))
+                });
+                let longest = integers(self.info("SVLEN"))
+                    .into_iter()
+                    .map(i64::unsigned_abs)
+                    .max();
+                match longest {
+                    Some(length) if spans_reference => start + length,
+                    _ => reference_end,
+                }
+            }
+        };
+        Interval::new(start, end.max(reference_end))
+    }
+
+    /// The interval widened by the CIPOS and CIEND confidence intervals.
+    ///
+    /// When the fields have a pair of values per ALT allele, the widest bounds are used.
+    pub fn confidence_interval(&self) -> Interval {
+        let interval = self.interval();
+        let bounds = |key: &str| {
+            let values = integers(self.info(key));
+            let lower = values.iter().step_by(2).copied().min().unwrap_or(0).min(0);
+            let upper = values
+                .iter()
+                .skip(1)
+                .step_by(2)
+                .copied()
+                .max()
+                .unwrap_or(0)
+                .max(0);
+            (lower, upper)
+        };
+        let (start_lower, _) = bounds("CIPOS");
+        let (_, end_upper) = bounds("CIEND");
+        Interval::new(
+            interval.start.saturating_add_signed(start_lower).max(1),
+            interval.end.saturating_add_signed(end_upper),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(line: &str) -> Interval {
+        Record::parse(line).unwrap().interval()
+    }
+
+    #[test]
+    fn small_variants_cover_reference_bases() {
+        assert_eq!(
+            interval("1\t100\t.\tA\tG\t.\t.\t."),
+            Interval::new(100, 100)
+        );
+        assert_eq!(
+            interval("1\t100\t.\tACGT\tA\t.\t.\t."),
+            Interval::new(100, 103)
+        );
+        assert_eq!(
+            interval("1\t100\t.\tA\tACGT\t.\t.\t."),
+            Interval::new(100, 100)
+        );
+    }
+
+    #[test]
+    fn end_takes_precedence() {
+        assert_eq!(
+            interval("1\t100\t.\tA\t<*>\t.\t.\tEND=200"),
+            Interval::new(100, 200)
+        );
+        assert_eq!(
+            interval("1\t100\t.\tA\t<DEL>\t.\t.\tEND=120;SVLEN=50"),
+            Interval::new(100, 120)
+        );
+    }
+
+    #[test]
+    fn svlen_extends_deletions_and_duplications() {
+        assert_eq!(
+            interval("1\t100\t.\tA\t<DEL>\t.\t.\tSVLEN=-50"),
+            Interval::new(100, 150)
+        );
+        assert_eq!(
+            interval("1\t100\t.\tA\t<DUP:TANDEM>\t.\t.\tSVLEN=30"),
+            Interval::new(100, 130)
+        );
+        assert_eq!(
+            interval("1\t100\t.\tA\t<DEL>,<DUP>\t.\t.\tSVLEN=10,20"),
+            Interval::new(100, 120)
+        );
+        assert_eq!(
+            interval("1\t100\t.\tA\t<INS>\t.\t.\tSVLEN=500"),
+            Interval::new(100, 100)
+        );
+        assert_eq!(
+            interval("1\t100\t.\tA\tA[2:300[\t.\t.\t."),
+            Interval::new(100, 100)
+        );
+    }
+
+    #[test]
+    fn confidence_interval_widens_interval() {
+        let record =
+            Record::parse("1\t100\t.\tA\t<DEL>\t.\t.\tSVLEN=50;CIPOS=-10,5;CIEND=-3,20").unwrap();
+        assert_eq!(record.confidence_interval(), Interval::new(90, 170));
+        let record = Record::parse("1\t5\t.\tA\t<DEL>\t.\t.\tEND=10;CIPOS=-10,5").unwrap();
+        assert_eq!(record.confidence_interval(), Interval::new(1, 10));
+    }
+
+    #[test]
+    fn parses_regions() {
+        let region: Region = "chr1:1,000-2000".parse().unwrap();
+        assert_eq!(region.interval, Interval::new(1000, 2000));
+        let region: Region = "chr1".parse().unwrap();
+        assert_eq!(region.interval, Interval::new(1, u64::MAX));
+        let region: Region = "HLA-A*01:01:10".parse().unwrap();
+        assert_eq!(
+            (region.chrom.as_str(), region.interval),
+            ("HLA-A*01:01", Interval::new(10, 10))
+        );
+        assert!("chr1:200-100".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn orders_contigs_by_header_then_naturally() {
+        let order = ContigOrder::new(["chrY".to_string(), "chr1".to_string()]);
+        let mut contigs = vec!["chr10", "chr1", "chrUn", "chr2", "chrY"];
+        contigs.sort_by(|a, b| order.compare(a, b));
+        assert_eq!(contigs, vec!["chrY", "chr1", "chr2", "chr10", "chrUn"]);
+    }
+
+    #[test]
+    fn orders_records_by_contig_and_interval() {
+        let order = ContigOrder::default();
+        let a = Record::parse("2\t100\t.\tA\tG\t.\t.\t.").unwrap();
+        let b = Record::parse("10\t50\t.\tA\tG\t.\t.\t.").unwrap();
+        let c = Record::parse("2\t100\t.\tAT\tA\t.\t.\t.").unwrap();
+        assert_eq!(order.compare_records(&a, &b), Ordering::Less);
+        assert_eq!(order.compare_records(&a, &c), Ordering::Less);
+    }
+
+    #[test]
+    fn regions_overlap_structural_variants() {
+        let region: Region = "1:140-160".parse().unwrap();
+        assert!(region.overlaps(&Record::parse("1\t100\t.\tA\t<DEL>\t.\t.\tSVLEN=50").unwrap()));
+        assert!(!region.overlaps(&Record::parse("1\t100\t.\tA\tG\t.\t.\t.").unwrap()));
+        assert!(!region.overlaps(&Record::parse("2\t150\t.\tA\tG\t.\t.\t.").unwrap()));
+    }
+}
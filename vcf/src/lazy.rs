@@ -0,0 +1,233 @@
+use crate::vcf::VCFError;
+use crate::Record;
+
+fn list(column: &str, separator: char) -> impl Iterator<Item = &str> {
+    let column = if column == "." { "" } else { column };
+    column.split(separator).filter(|item| !item.is_empty())
+}
+
+/// A data line that is decoded only as far as its fields are read.
+///
+/// The fixed columns are found when the record is created, and POS is parsed, but the INFO
+/// column and the sample columns are only split when a value is looked up in them. All values
+/// are borrowed from the line. Use [`Reader::read_lazy`](crate::vcf::Reader::read_lazy) to read
+/// records without allocating for each line.
+///
+/// ```
+/// use vcf::LazyRecord;
+///
+/// let line = "1\t100\trs1\tA\tG,T\t50\tPASS\tDP=12;DB\tGT:GQ\t0/1:30\t1/1:10";
+/// let record = LazyRecord::parse(line, None)?;
+/// assert_eq!((record.chrom(), record.pos()), ("1", 100));
+/// assert_eq!(record.alt().collect::<Vec<_>>(), ["G", "T"]);
+/// assert_eq!(record.info("DP"), Some(Some("12")));
+/// assert_eq!(record.info("DB"), Some(None));
+/// assert_eq!(record.sample_value(1, "GQ"), Some("10"));
+///# Ok::<(), vcf::vcf::VCFError>(())
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct LazyRecord<'l> {
+    fixed: [&'l str; 8],
+    pos: u64,
+    format: Option<&'l str>,
+    samples: Option<&'l str>,
+    /// The sample columns to read, when only some of them are kept.
+    selection: Option<&'l [usize]>,
+}
+
+impl<'l> LazyRecord<'l> {
+    /// Find the columns of `line`. When `selection` is given, sample `i` of the record is the
+    /// sample column at `selection[i]`.
+    pub fn parse(line: &'l str, selection: Option<&'l [usize]>) -> Result<Self, VCFError> {
+        let mut columns = line.trim_end_matches(['\n', '\r']).splitn(10, '\t');
+        let mut fixed = [""; 8];
+        for column in &mut fixed {
+            *column = columns.next().ok_or(VCFError::ParseError)?;
+        }
+        if fixed[0].is_empty() || fixed[3].is_empty() {
+            return Err(VCFError::ParseError);
+        }
+        let pos = fixed[1].parse().map_err(|_| VCFError::ParseError)?;
+        Ok(Self {
+            fixed,
+            pos,
+            format: columns.next(),
+            samples: columns.next(),
+            selection,
+        })
+    }
+
+    pub fn chrom(&self) -> &'l str {
+        self.fixed[0]
+    }
+
+    pub fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn id(&self) -> impl Iterator<Item = &'l str> {
+        list(self.fixed[2], ';')
+    }
+
+    pub fn reference(&self) -> &'l str {
+        self.fixed[3]
+    }
+
+    pub fn alt(&self) -> impl Iterator<Item = &'l str> {
+        list(self.fixed[4], ',')
+    }
+
+    pub fn qual(&self) -> Result<Option<f64>, VCFError> {
+        match self.fixed[5] {
+            "." => Ok(None),
+            qual => qual.parse().map(Some).map_err(|_| VCFError::ParseError),
+        }
+    }
+
+    pub fn filter(&self) -> impl Iterator<Item = &'l str> {
+        list(self.fixed[6], ';')
+    }
+
+    /// The INFO keys and values, in order. Flags have a `None` value.
+    pub fn info_fields(&self) -> impl Iterator<Item = (&'l str, Option<&'l str>)> {
+        list(self.fixed[7], ';').map(|entry| match entry.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (entry, None),
+        })
+    }
+
+    /// Look up an INFO key. Flags are present with a `None` value.
+    pub fn info(&self, key: &str) -> Option<Option<&'l str>> {
+        self.info_fields()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn format(&self) -> impl Iterator<Item = &'l str> {
+        self.format.into_iter().flat_map(|format| format.split(':'))
+    }
+
+    pub fn sample_count(&self) -> usize {
+        match (self.selection, self.samples) {
+            (Some(selection), _) => selection.len(),
+            (None, Some(samples)) => samples.split('\t').count(),
+            (None, None) => 0,
+        }
+    }
+
+    /// The sample column at `index` (0-based), undecoded.
+    pub fn sample(&self, index: usize) -> Option<&'l str> {
+        let column = match self.selection {
+            Some(selection) => *selection.get(index)?,
+            None => index,
+        };
+        self.samples?.split('\t').nth(column)
+    }
+
+    /// All sample columns, undecoded. Splitting them once is faster than calling
+    /// [`sample`](Self::sample) for each of them.
+    pub fn samples(&self) -> Vec<&'l str> {
+        let columns = self
+            .samples
+            .into_iter()
+            .flat_map(|samples| samples.split('\t'));
+        match self.selection {
+            Some(selection) => {
+                let columns: Vec<&str> = columns.collect();
+                selection
+                    .iter()
+                    .filter_map(|&i| columns.get(i).copied())
+                    .collect()
+            }
+            None => columns.collect(),
+        }
+    }
+
+    /// Look up the value of a FORMAT key for the sample at `index` (0-based).
+    pub fn sample_value(&self, index: usize, key: &str) -> Option<&'l str> {
+        let position = self.format().position(|k| k == key)?;
+        self.sample(index)?.split(':').nth(position)
+    }
+
+    /// Decode the whole record.
+    pub fn to_record(&self) -> Result<Record, VCFError> {
+        let owned = |items: &mut dyn Iterator<Item = &str>| items.map(str::to_string).collect();
+        let samples = self.samples();
+        if samples.len() != self.sample_count() {
+            return Err(VCFError::ParseError);
+        }
+        let samples = samples
+            .iter()
+            .map(|sample| sample.split(':').map(str::to_string).collect())
+            .collect();
+        Ok(Record {
+            chrom: self.chrom().to_string(),
+            pos: self.pos,
+            id: owned(&mut self.id()),
+            reference: self.reference().to_string(),
+            alt: owned(&mut self.alt()),
+            qual: self.qual()?,
+            qual_text: Some(self.fixed[5])
+                .filter(|qual| *qual != ".")
+                .map(str::to_string),
+            filter: owned(&mut self.filter()),
+            info: self
+                .info_fields()
+                .map(|(key, value)| (key.to_string(), value.map(str::to_string)))
+                .collect(),
+            format: owned(&mut self.format()),
+            samples,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "1\t100\trs1;rs2\tA\tG,T\t.\tq10;s50\tDP=12;AF=0.5,0.1;DB\tGT:GQ:AD\t0/1:30:5,5\t1/1\t./.:.:.";
+
+    #[test]
+    fn decodes_fields_on_access() {
+        let record = LazyRecord::parse(LINE, None).unwrap();
+        assert_eq!(record.id().collect::<Vec<_>>(), ["rs1", "rs2"]);
+        assert_eq!(record.qual().unwrap(), None);
+        assert_eq!(record.filter().collect::<Vec<_>>(), ["q10", "s50"]);
+        assert_eq!(record.info("AF"), Some(Some("0.5,0.1")));
+        assert_eq!(record.info("XX"), None);
+        assert_eq!(record.sample_count(), 3);
+        assert_eq!(record.sample(2), Some("./.:.:."));
+        assert_eq!(record.sample_value(0, "AD"), Some("5,5"));
+        assert_eq!(record.sample_value(1, "GQ"), None);
+        assert_eq!(record.sample(3), None);
+        assert_eq!(record.samples(), ["0/1:30:5,5", "1/1", "./.:.:."]);
+    }
+
+    #[test]
+    fn matches_eager_decoding() {
+        let record = LazyRecord::parse(LINE, None).unwrap();
+        assert_eq!(record.to_record().unwrap(), Record::parse(LINE).unwrap());
+
+        let selection = [2, 0];
+        let record = LazyRecord::parse(LINE, Some(&selection)).unwrap();
+        assert_eq!(record.sample_count(), 2);
+        assert_eq!(record.sample_value(1, "GQ"), Some("30"));
+        assert_eq!(record.samples(), ["./.:.:.", "0/1:30:5,5"]);
+        assert_eq!(
+            record.to_record().unwrap(),
+            Record::parse_samples(LINE, Some(&selection)).unwrap()
+        );
+
+        let sites = "1\t5\t.\tA\t.\t.\t.\t.";
+        let record = LazyRecord::parse(sites, None).unwrap();
+        assert_eq!(record.sample_count(), 0);
+        assert_eq!(record.alt().count(), 0);
+        assert_eq!(record.to_record().unwrap(), Record::parse(sites).unwrap());
+    }
+
+    #[test]
+    fn rejects_short_lines() {
+        assert!(LazyRecord::parse("1\t100\t.\tA\tG\t.\t.", None).is_err());
+        assert!(LazyRecord::parse("1\tx\t.\tA\tG\t.\t.\t.", None).is_err());
+    }
+}
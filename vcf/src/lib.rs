@@ -1,7 +1,45 @@
+mod allele;
+mod genotype;
 mod headers;
+mod interval;
+mod lazy;
 mod parse;
+mod record;
 mod validate_format;
 mod validate_fileformat;
+mod value;
+pub mod annotate;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod bgzf;
+pub mod combine;
+pub mod concat;
+pub mod edit;
+pub mod fasta;
+pub mod fill_tags;
+pub mod filter;
+pub mod gvcf;
+pub mod liftover;
+pub mod merge;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod multiallelic;
+pub mod normalize;
+pub mod parallel;
+pub mod query;
+pub mod samples;
+pub mod sort;
+pub mod stats;
+pub mod tabix;
+#[cfg(feature = "tokio")]
+pub mod tokio;
 pub mod vcf;
 
+pub use allele::*;
+pub use genotype::*;
 pub use headers::*;
+pub use interval::*;
+pub use lazy::*;
+pub use record::*;
+pub use validate_format::*;
+pub use value::*;
@@ -0,0 +1,656 @@
+//! Moving records between assemblies with a UCSC chain file.
+//!
+//! A [`Chain`] maps positions of the old assembly (the chains' target) to the new one (their
+//! query). [`Liftover`] uses it to move whole records, reverse-complementing the alleles of
+//! records that land on the reverse strand and, when it has the new reference sequence,
+//! checking REF and swapping it with an ALT allele that matches the reference instead.
+//! Records that cannot be moved are left unchanged, with a [`Reason`].
+//!
+//! Lifted records are not necessarily in order, and may need sorting.
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
+
+use crate::fasta::ReferenceSequence;
+use crate::vcf::{VCFError, VCF};
+use crate::{Allele, Genotype, Header, HeaderValue, Interval, Record};
+
+/// An ungapped block of an alignment: `length` bases from `target_start` on the old assembly,
+/// aligned to bases from `query_start` on the new one, both 0-based. Query positions of chains
+/// on the reverse strand count from the end of the contig.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Block {
+    target_start: u64,
+    query_start: u64,
+    length: u64,
+    chain: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Alignment {
+    target: String,
+    query: usize,
+    reverse: bool,
+}
+
+/// A position on the new assembly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapped<'c> {
+    pub chrom: &'c str,
+    /// The 1-based position on the forward strand.
+    pub pos: u64,
+    /// Whether the alignment is to the reverse strand.
+    pub reverse: bool,
+}
+
+/// The alignments of a UCSC chain file.
+#[derive(Debug, Clone, Default)]
+pub struct Chain {
+    /// Blocks of each contig of the old assembly, by target start.
+    blocks: HashMap<String, Vec<Block>>,
+    alignments: Vec<Alignment>,
+    /// Contigs of the new assembly and their lengths, in the order they first appear.
+    contigs: Vec<(String, u64)>,
+}
+
+impl Chain {
+    /// Parse a chain file: for each chain, a header line
+    /// `chain score tName tSize tStrand tStart tEnd qName qSize qStrand qStart qEnd id`, then
+    /// lines of `size dt dq` ending with a line holding only `size`.
+    pub fn parse(source: impl BufRead) -> Result<Self, VCFError> {
+        let mut chain = Chain::default();
+        // The alignment being read, and where its next block starts.
+        let mut current: Option<(usize, u64, u64)> = None;
+        for (index, line) in source.lines().enumerate() {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let invalid = || {
+                VCFError::InvalidRecord(format!("line {} of the chain file: {:?}", index + 1, line))
+            };
+            let number = |i: usize| -> Result<u64, VCFError> {
+                fields
+                    .get(i)
+                    .and_then(|f| f.parse().ok())
+                    .ok_or_else(invalid)
+            };
+            match (fields.first(), &mut current) {
+                (None, _) => {}
+                (Some(&"chain"), None) => {
+                    if fields.len() < 12 || fields[4] != "+" || !matches!(fields[9], "+" | "-") {
+                        return Err(invalid());
+                    }
+                    let query_size = number(8)?;
+                    let query = match chain.contigs.iter().position(|(c, _)| c == fields[7]) {
+                        Some(index) => index,
+                        None => {
+                            chain.contigs.push((fields[7].to_string(), query_size));
+                            chain.contigs.len() - 1
+                        }
+                    };
+                    chain.alignments.push(Alignment {
+                        target: fields[2].to_string(),
+                        query,
+                        reverse: fields[9] == "-",
+                    });
+                    current = Some((chain.alignments.len() - 1, number(5)?, number(10)?));
+                }
+                (Some(_), Some((index, target, query))) => {
+                    let length = number(0)?;
+                    let alignment = &chain.alignments[*index];
+                    chain
+                        .blocks
+                        .entry(alignment.target.clone())
+                        .or_default()
+                        .push(Block {
+                            target_start: *target,
+                            query_start: *query,
+                            length,
+                            chain: *index,
+                        });
+                    match fields.len() {
+                        1 => current = None,
+                        3 => {
+                            *target += length + number(1)?;
+                            *query += length + number(2)?;
+                        }
+                        _ => return Err(invalid()),
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        for blocks in chain.blocks.values_mut() {
+            blocks.sort_by_key(|block| block.target_start);
+        }
+        Ok(chain)
+    }
+
+    /// The contigs of the new assembly, with their lengths.
+    pub fn contigs(&self) -> &[(String, u64)] {
+        &self.contigs
+    }
+
+    /// Map a 1-based position of the old assembly.
+    pub fn map(&self, chrom: &str, pos: u64) -> Option<Mapped<'_>> {
+        self.block(chrom, pos).map(|block| self.map_in(block, pos))
+    }
+
+    /// The block covering a 1-based position.
+    fn block(&self, chrom: &str, pos: u64) -> Option<&Block> {
+        let blocks = self.blocks.get(chrom)?;
+        let offset = pos.checked_sub(1)?;
+        let index = blocks.partition_point(|block| block.target_start <= offset);
+        let block = &blocks[index.checked_sub(1)?];
+        (offset < block.target_start + block.length).then_some(block)
+    }
+
+    fn map_in(&self, block: &Block, pos: u64) -> Mapped<'_> {
+        let alignment = &self.alignments[block.chain];
+        let (chrom, size) = &self.contigs[alignment.query];
+        let offset = block.query_start + (pos - 1 - block.target_start);
+        Mapped {
+            chrom,
+            pos: match alignment.reverse {
+                true => size - offset,
+                false => offset + 1,
+            },
+            reverse: alignment.reverse,
+        }
+    }
+}
+
+/// Why a record could not be lifted over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// POS, or the end of the record, is not in any alignment.
+    Unmapped,
+    /// The bases of REF are not aligned together, without a gap.
+    GapInRef,
+    /// The start and end of the record map to different contigs or strands.
+    SplitInterval,
+    /// Neither REF nor any ALT allele matches the new reference.
+    RefMismatch,
+    /// An indel lands on the reverse strand and there is no reference to find its new padding
+    /// base.
+    ReverseIndel,
+    /// The record has breakend alleles, whose mates cannot be moved with it.
+    Breakend,
+    /// The new reference lacks the contig the record maps to, or is shorter than its REF.
+    MissingReference,
+}
+
+impl Reason {
+    pub const ALL: [Reason; 7] = [
+        Reason::Unmapped,
+        Reason::GapInRef,
+        Reason::SplitInterval,
+        Reason::RefMismatch,
+        Reason::ReverseIndel,
+        Reason::Breakend,
+        Reason::MissingReference,
+    ];
+
+    /// A name for the reason, usable as a FILTER ID.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Reason::Unmapped => "Unmapped",
+            Reason::GapInRef => "GapInRef",
+            Reason::SplitInterval => "SplitInterval",
+            Reason::RefMismatch => "RefMismatch",
+            Reason::ReverseIndel => "ReverseIndel",
+            Reason::Breakend => "Breakend",
+            Reason::MissingReference => "MissingReference",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Reason::Unmapped => "Not in any alignment of the chain file",
+            Reason::GapInRef => "The bases of REF are not aligned together",
+            Reason::SplitInterval => "The start and end map to different contigs or strands",
+            Reason::RefMismatch => "Neither REF nor any ALT allele matches the new reference",
+            Reason::ReverseIndel => "Indel on the reverse strand, with no reference to pad it",
+            Reason::Breakend => "Breakend alleles cannot be lifted over",
+            Reason::MissingReference => "The new reference has no sequence where the record maps",
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// The result of lifting over a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lift {
+    Mapped,
+    /// Mapped, with REF swapped with the ALT allele that matches the new reference.
+    Swapped,
+    Rejected(Reason),
+}
+
+/// Lifts records over with a chain and, optionally, the sequence of the new assembly.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use vcf::liftover::{Chain, Lift, Liftover};
+/// use vcf::Record;
+///# use vcf::vcf::VCFError;
+///
+/// // 100 bases of chr1 are aligned, in reverse, to the end of a 1000-base contig.
+/// let chain = "chain 100 chr1 500 + 0 100 1 1000 - 0 100 1\n100\n";
+/// let mut liftover = Liftover::<HashMap<String, String>>::new(Chain::parse(chain.as_bytes())?);
+/// let mut record = Record::parse("chr1\t10\t.\tA\tG\t.\t.\t.")?;
+/// assert_eq!(liftover.lift(&mut record)?, Lift::Mapped);
+/// assert_eq!((record.chrom.as_str(), record.pos), ("1", 991));
+/// assert_eq!((record.reference.as_str(), record.alt[0].as_str()), ("T", "C"));
+///# Ok::<(), VCFError>(())
+/// ```
+pub struct Liftover<F> {
+    chain: Chain,
+    reference: Option<F>,
+}
+
+impl<F: ReferenceSequence> Liftover<F> {
+    pub fn new(chain: Chain) -> Self {
+        Self {
+            chain,
+            reference: None,
+        }
+    }
+
+    /// Check REF against the sequence of the new assembly, and use it to pad indels that land
+    /// on the reverse strand.
+    pub fn with_reference(chain: Chain, reference: F) -> Self {
+        Self {
+            chain,
+            reference: Some(reference),
+        }
+    }
+
+    /// Replace the `##contig` lines of a header with the contigs of the new assembly that the
+    /// old contigs are aligned to, or all of them if the header lists no contigs.
+    pub fn update_header(&self, header: &mut VCF) {
+        let old = header.contigs();
+        let mut used = vec![old.is_empty(); self.chain.contigs.len()];
+        for contig in &old {
+            for block in self.chain.blocks.get(contig).into_iter().flatten() {
+                used[self.chain.alignments[block.chain].query] = true;
+            }
+        }
+        for contig in &old {
+            header.remove_header("contig", contig);
+        }
+        for ((name, length), _) in self.chain.contigs.iter().zip(used).filter(|(_, u)| *u) {
+            let length = length.to_string();
+            header.set_header(&Header {
+                key: "contig",
+                value: HeaderValue::Nested(HashMap::from([
+                    ("ID", name.as_str()),
+                    ("length", length.as_str()),
+                ])),
+            });
+        }
+    }
+
+    /// Move a record to the new assembly, updating CHROM, POS, REF, ALT and INFO END. A
+    /// rejected record is left unchanged.
+    ///
+    /// When REF is swapped with an ALT allele, GT is updated to match; other values that
+    /// depend on the order of the alleles, such as AF or AD, are not. Records the new reference
+    /// has no sequence for are rejected rather than failing.
+    pub fn lift(&mut self, record: &mut Record) -> Result<Lift, VCFError> {
+        match self.lift_record(record) {
+            Err(VCFError::ReferenceError(_)) => Ok(Lift::Rejected(Reason::MissingReference)),
+            result => result,
+        }
+    }
+
+    /// Lift a record over, changing it only once nothing else can fail.
+    fn lift_record(&mut self, record: &mut Record) -> Result<Lift, VCFError> {
+        let alleles: Vec<Allele> = record.alt_alleles()?;
+        if alleles
+            .iter()
+            .any(|allele| matches!(allele, Allele::Breakend(_)))
+        {
+            return Ok(Lift::Rejected(Reason::Breakend));
+        }
+        let reference_end = record.pos + (record.reference.len() as u64).max(1) - 1;
+        let (Some(first), Some(last)) = (
+            self.chain.block(&record.chrom, record.pos),
+            self.chain.block(&record.chrom, reference_end),
+        ) else {
+            return Ok(Lift::Rejected(Reason::Unmapped));
+        };
+        if first != last {
+            return Ok(Lift::Rejected(Reason::GapInRef));
+        }
+        let start = self.chain.map_in(first, record.pos);
+        let mapped_end = self.chain.map_in(first, reference_end).pos;
+        let chrom = start.chrom.to_string();
+        let reverse = start.reverse;
+        let mut pos = start.pos.min(mapped_end);
+
+        // The end of a record that spans more than its REF, such as a structural variant.
+        let interval = record.interval();
+        let mut end = None;
+        if interval.end > reference_end || record.info("END").is_some() {
+            let Some(mapped) = self.chain.map(&record.chrom, interval.end) else {
+                return Ok(Lift::Rejected(Reason::Unmapped));
+            };
+            if mapped.chrom != chrom || mapped.reverse != reverse {
+                return Ok(Lift::Rejected(Reason::SplitInterval));
+            }
+            let lifted = Interval::new(start.pos.min(mapped.pos), start.pos.max(mapped.pos));
+            pos = pos.min(lifted.start);
+            end = Some(lifted.end.max(mapped_end.max(start.pos)));
+        }
+
+        let is_sequence = |allele: &str| allele.bytes().all(|b| b.is_ascii_alphabetic());
+        let mut reference = record.reference.clone();
+        let mut alt = record.alt.clone();
+        if reverse {
+            reference = reverse_complement(&reference);
+            for (allele, parsed) in alt.iter_mut().zip(&alleles) {
+                if let Allele::Bases(bases) = parsed {
+                    *allele = reverse_complement(bases);
+                }
+            }
+            // A padding base shared by all alleles of an indel ends up last, and must move to
+            // the front. Alleles that do not share their first base are not padded, and keep
+            // their position.
+            let first_base = |allele: &str| allele.bytes().next().map(|b| b.to_ascii_uppercase());
+            let mut bases = alleles.iter().filter_map(|allele| match allele {
+                Allele::Bases(bases) => Some(bases.as_str()),
+                _ => None,
+            });
+            let indel = bases
+                .clone()
+                .any(|bases| bases.len() != record.reference.len());
+            let padded = indel
+                && first_base(&record.reference).is_some()
+                && bases.all(|bases| first_base(bases) == first_base(&record.reference));
+            if padded {
+                let Some(sequence) = self.reference.as_mut() else {
+                    return Ok(Lift::Rejected(Reason::ReverseIndel));
+                };
+                if pos < 2 {
+                    return Ok(Lift::Rejected(Reason::Unmapped));
+                }
+                pos -= 1;
+                let base = sequence.fetch(&chrom, pos, pos)?;
+                let pad = |allele: &str| format!("{}{}", base, &allele[..allele.len() - 1]);
+                reference = pad(&reference);
+                for (allele, parsed) in alt.iter_mut().zip(&alleles) {
+                    if matches!(parsed, Allele::Bases(_)) {
+                        *allele = pad(allele);
+                    }
+                }
+            }
+        }
+
+        let mut swapped = None;
+        if let Some(sequence) = self.reference.as_mut() {
+            if !is_sequence(&reference) {
+                return Ok(Lift::Rejected(Reason::RefMismatch));
+            }
+            let mut fetch = |length: usize| sequence.fetch(&chrom, pos, pos + length as u64 - 1);
+            let symbolic = alleles
+                .iter()
+                .all(|allele| !matches!(allele, Allele::Bases(_)));
+            if symbolic && reverse && reference.len() == 1 {
+                reference = fetch(1)?;
+            } else if !fetch(reference.len())?.eq_ignore_ascii_case(&reference) {
+                let mut matching = None;
+                for (index, allele) in alt.iter().enumerate() {
+                    if is_sequence(allele) && fetch(allele.len())?.eq_ignore_ascii_case(allele) {
+                        matching = Some(index);
+                        break;
+                    }
+                }
+                let Some(index) = matching else {
+                    return Ok(Lift::Rejected(Reason::RefMismatch));
+                };
+                std::mem::swap(&mut reference, &mut alt[index]);
+                swapped = Some(index + 1);
+            }
+        }
+
+        record.chrom = chrom;
+        record.pos = pos;
+        record.reference = reference;
+        record.alt = alt;
+        if let Some(end) = end {
+            if record.info("END").is_some() {
+                record.set_info("END", Some(end.to_string()));
+            }
+        }
+        match swapped {
+            Some(index) => {
+                swap_genotypes(record, index);
+                Ok(Lift::Swapped)
+            }
+            None => Ok(Lift::Mapped),
+        }
+    }
+}
+
+/// Declare the reasons for rejecting records as FILTERs in a header.
+pub fn add_reason_definitions(header: &mut VCF) {
+    for reason in Reason::ALL {
+        header.set_header(&Header {
+            key: "FILTER",
+            value: HeaderValue::Nested(HashMap::from([
+                ("ID", reason.id()),
+                ("Description", reason.description()),
+            ])),
+        });
+    }
+}
+
+fn reverse_complement(bases: &str) -> String {
+    bases
+        .chars()
+        .rev()
+        .map(|base| match base {
+            'A' => 'T',
+            'C' => 'G',
+            'G' => 'C',
+            'T' => 'A',
+            'a' => 't',
+            'c' => 'g',
+            'g' => 'c',
+            't' => 'a',
+            other => other,
+        })
+        .collect()
+}
+
+/// Exchange allele 0 and allele `index` in the genotypes of a record.
+fn swap_genotypes(record: &mut Record, index: usize) {
+    let Some(gt) = record.format.iter().position(|key| key == "GT") else {
+        return;
+    };
+    for sample in &mut record.samples {
+        let Some(value) = sample.get_mut(gt) else {
+            continue;
+        };
+        let Ok(mut genotype) = value.parse::<Genotype>() else {
+            continue;
+        };
+        for allele in genotype.alleles.iter_mut().flatten() {
+            if *allele == 0 {
+                *allele = index;
+            } else if *allele == index {
+                *allele = 0;
+            }
+        }
+        *value = genotype.to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // chr1:1-100 is aligned to 1:11-110, then chr1:111-160 to 1:161-210 after gaps of 10 and
+    // 40 bases. chr2:1-50 is aligned in reverse to the last 50 bases of the 200-base contig 2.
+    const CHAIN: &str = "chain 1000 chr1 500 + 0 160 1 300 + 10 210 1
+100 10 50
+50
+
+chain 500 chr2 100 + 0 50 2 200 - 0 50 2
+50
+";
+
+    fn reference() -> HashMap<String, String> {
+        let mut contig = "ACGT".repeat(75);
+        contig.replace_range(10..13, "GGC");
+        HashMap::from([
+            ("1".to_string(), contig),
+            (
+                "2".to_string(),
+                format!("{}AGC{}", "T".repeat(188), "T".repeat(9)),
+            ),
+        ])
+    }
+
+    fn lift(line: &str, reference: bool) -> (Lift, Record) {
+        let chain = Chain::parse(CHAIN.as_bytes()).unwrap();
+        let mut liftover = match reference {
+            true => Liftover::with_reference(chain, self::reference()),
+            false => Liftover::new(chain),
+        };
+        let mut record = Record::parse(line).unwrap();
+        (liftover.lift(&mut record).unwrap(), record)
+    }
+
+    #[test]
+    fn maps_positions() {
+        let chain = Chain::parse(CHAIN.as_bytes()).unwrap();
+        let mapped = |chrom, pos| chain.map(chrom, pos).map(|m| (m.chrom, m.pos, m.reverse));
+        assert_eq!(mapped("chr1", 1), Some(("1", 11, false)));
+        assert_eq!(mapped("chr1", 100), Some(("1", 110, false)));
+        assert_eq!(mapped("chr1", 105), None);
+        assert_eq!(mapped("chr1", 111), Some(("1", 161, false)));
+        assert_eq!(mapped("chr1", 161), None);
+        assert_eq!(mapped("chr2", 1), Some(("2", 200, true)));
+        assert_eq!(mapped("chr2", 50), Some(("2", 151, true)));
+        assert_eq!(mapped("chr3", 1), None);
+        assert_eq!(
+            chain.contigs(),
+            [("1".to_string(), 300), ("2".to_string(), 200)]
+        );
+        assert!(Chain::parse("chain 1 chr1 10 + 0 10\n".as_bytes()).is_err());
+        assert!(Chain::parse("10 0 0\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn lifts_records() {
+        let (lift_result, record) = lift("chr1\t5\trs1\tG\tT\t.\t.\t.\tGT\t0/1", false);
+        assert_eq!(lift_result, Lift::Mapped);
+        assert_eq!(record.to_string(), "1\t15\trs1\tG\tT\t.\t.\t.\tGT\t0/1");
+
+        // On the reverse strand, alleles are reverse-complemented and indels padded again.
+        let (_, record) = lift("chr2\t10\t.\tA\tG\t.\t.\t.", false);
+        assert_eq!((record.pos, record.reference.as_str()), (191, "T"));
+        assert_eq!(
+            lift("chr2\t10\t.\tAC\tA\t.\t.\t.", false).0,
+            Lift::Rejected(Reason::ReverseIndel)
+        );
+        let (lift_result, record) = lift("chr2\t10\t.\tGC\tG\t.\t.\t.", true);
+        assert_eq!(lift_result, Lift::Mapped);
+        // 2:189-191 reads AGC on the new reference: the deleted G follows the padding A.
+        assert_eq!(
+            (
+                record.pos,
+                record.reference.as_str(),
+                record.alt[0].as_str()
+            ),
+            (189, "AG", "A")
+        );
+
+        // A complex indel has no padding base to move, and keeps its position.
+        let (lift_result, record) = lift("chr2\t10\t.\tAC\tG\t.\t.\t.", false);
+        assert_eq!(lift_result, Lift::Mapped);
+        assert_eq!(record.to_string(), "2\t190\t.\tGT\tC\t.\t.\t.");
+        assert_eq!(
+            lift("chr2\t10\t.\tAC\tG\t.\t.\t.", true).0,
+            Lift::Rejected(Reason::RefMismatch)
+        );
+
+        let (lift_result, record) = lift("chr1\t10\t.\tT\t<DEL>\t.\t.\tEND=20", true);
+        assert_eq!(lift_result, Lift::Mapped);
+        assert_eq!(record.to_string(), "1\t20\t.\tT\t<DEL>\t.\t.\tEND=30");
+    }
+
+    #[test]
+    fn rejects_and_swaps() {
+        let rejected = |line, reference| lift(line, reference).0;
+        assert_eq!(
+            rejected("chr1\t105\t.\tA\tG\t.\t.\t.", false),
+            Lift::Rejected(Reason::Unmapped)
+        );
+        assert_eq!(
+            rejected("chr1\t100\t.\tAAAAAAAAAAAA\tA\t.\t.\t.", false),
+            Lift::Rejected(Reason::GapInRef)
+        );
+        assert_eq!(
+            rejected("chr1\t50\t.\tA\t<DEL>\t.\t.\tEND=105", false),
+            Lift::Rejected(Reason::Unmapped)
+        );
+        assert_eq!(
+            rejected("chr1\t50\t.\tA\t<DEL>\t.\t.\tEND=115", false),
+            Lift::Mapped
+        );
+        assert_eq!(
+            rejected("chr1\t1\t.\tA\tG]chr2:10]\t.\t.\t.", false),
+            Lift::Rejected(Reason::Breakend)
+        );
+        assert_eq!(
+            rejected("chr1\t2\t.\tC\tT\t.\t.\t.", true),
+            Lift::Rejected(Reason::RefMismatch)
+        );
+
+        // 1:11-13 reads GGC on the new reference.
+        let (lift_result, record) = lift("chr1\t1\t.\tA\tT,GGC\t.\t.\t.\tGT\t0/2\t1|0\t./.", true);
+        assert_eq!(lift_result, Lift::Swapped);
+        assert_eq!(
+            record.to_string(),
+            "1\t11\t.\tGGC\tT,A\t.\t.\t.\tGT\t2/0\t1|2\t./."
+        );
+        let (lift_result, record) = lift("chr1\t1\t.\tGGC\tA\t.\t.\t.", true);
+        assert_eq!(lift_result, Lift::Mapped);
+        assert_eq!(record.pos, 11);
+
+        // Records on contigs the new reference lacks are rejected rather than failing.
+        let chain = Chain::parse(CHAIN.as_bytes()).unwrap();
+        let mut reference = reference();
+        reference.remove("2");
+        let mut liftover = Liftover::with_reference(chain, reference);
+        let line = "chr2\t10\t.\tA\tG\t.\t.\t.";
+        let mut record = Record::parse(line).unwrap();
+        assert_eq!(
+            liftover.lift(&mut record).unwrap(),
+            Lift::Rejected(Reason::MissingReference)
+        );
+        assert_eq!(record.to_string(), line);
+    }
+
+    #[test]
+    fn updates_contig_lines() {
+        let mut header = VCF::default();
+        for line in ["##contig=<ID=chr2,length=100>", "##source=test"] {
+            header.meta.push(line.to_string());
+        }
+        let chain = Chain::parse(CHAIN.as_bytes()).unwrap();
+        let liftover = Liftover::<HashMap<String, String>>::new(chain);
+        liftover.update_header(&mut header);
+        assert_eq!(header.meta, ["##source=test", "##contig=<ID=2,length=200>"]);
+
+        add_reason_definitions(&mut header);
+        assert_eq!(header.meta.len(), 2 + Reason::ALL.len());
+    }
+}
@@ -0,0 +1,585 @@
+//! Merging sorted VCFs with different samples into one multi-sample VCF.
+//!
+//! The inputs are read side by side, one position at a time. Records from different inputs that
+//! describe the same variant become one output record carrying the samples of every input, and
+//! samples of inputs without a matching record are written as no-calls.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use crate::multiallelic::{format_keys, merge_info, no_call, remap_sample, MergedAlleles};
+use crate::parse::ParseError;
+use crate::vcf::{Reader, VCFError, VCF};
+use crate::{Allele, ContigOrder, Definitions, Header, HeaderValue, Record};
+
+/// One of several inputs read side by side, with its next record.
+pub(crate) struct Input<R> {
+    pub(crate) reader: Reader<R>,
+    pub(crate) current: Option<Record>,
+    pub(crate) samples: usize,
+}
+
+impl<R: BufRead> Input<R> {
+    pub(crate) fn new(reader: Reader<R>, order: &mut ContigOrder) -> Result<Self, VCFError> {
+        let samples = reader.header().samples.len();
+        let mut input = Self {
+            reader,
+            current: None,
+            samples,
+        };
+        input.advance(order)?;
+        Ok(input)
+    }
+
+    /// Move on to the next record, checking that the input stays sorted, and return the record
+    /// moved past. Contigs `order` does not have yet are added to it as they are reached, so
+    /// inputs without `##contig` lines may order their contigs in any way, as long as each is
+    /// one block and all inputs agree.
+    pub(crate) fn advance(&mut self, order: &mut ContigOrder) -> Result<Option<Record>, VCFError> {
+        let next = self.reader.read_record()?;
+        if let Some(next) = &next {
+            order.push(&next.chrom);
+        }
+        if let (Some(current), Some(next)) = (&self.current, &next) {
+            let ordering = order
+                .compare(&current.chrom, &next.chrom)
+                .then(current.pos.cmp(&next.pos));
+            if ordering == Ordering::Greater {
+                return Err(VCFError::InvalidRecord(format!(
+                    "{}:{} comes after {}:{}; inputs must be sorted, with contigs in the same order",
+                    next.chrom, next.pos, current.chrom, current.pos
+                )));
+            }
+        }
+        Ok(std::mem::replace(&mut self.current, next))
+    }
+
+    /// Take the next record if it is at `chrom`:`pos`.
+    pub(crate) fn take_at(
+        &mut self,
+        chrom: &str,
+        pos: u64,
+        order: &mut ContigOrder,
+    ) -> Result<Option<Record>, VCFError> {
+        match &self.current {
+            Some(record) if record.chrom == chrom && record.pos == pos => self.advance(order),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// The position of the first record still to be read from any of `inputs`.
+pub(crate) fn next_position<R>(inputs: &[Input<R>], order: &ContigOrder) -> Option<(String, u64)> {
+    inputs
+        .iter()
+        .filter_map(|input| input.current.as_ref())
+        .min_by(|a, b| order.compare(&a.chrom, &b.chrom).then(a.pos.cmp(&b.pos)))
+        .map(|record| (record.chrom.clone(), record.pos))
+}
+
+/// The header of the merged file: the meta lines of all inputs, without repeating lines with
+/// the same key and ID, and the samples of all inputs in order.
+///
+/// INFO and FORMAT lines with the same ID must agree on Number and Type; all disagreements are
+/// reported together. Samples must not appear in more than one input.
+pub fn merge_headers(headers: &[&VCF]) -> Result<VCF, VCFError> {
+    let mut conflicts = Vec::new();
+    let mut merged = merge_meta(headers, &mut conflicts);
+    for header in headers {
+        for sample in &header.samples {
+            if merged.samples.contains(sample) {
+                conflicts.push(format!("sample {} appears twice", sample));
+            }
+            merged.samples.push(sample.clone());
+        }
+    }
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(VCFError::InvalidHeader(conflicts.join("; ")))
+    }
+}
+
+/// A header without samples holding the meta lines of all `headers`, each key and ID once,
+/// with INFO and FORMAT definitions that disagree on Number or Type added to `conflicts`.
+pub(crate) fn merge_meta(headers: &[&VCF], conflicts: &mut Vec<String>) -> VCF {
+    let mut merged = VCF {
+        file_format: headers
+            .first()
+            .map(|h| h.file_format.clone())
+            .unwrap_or_default(),
+        ..VCF::default()
+    };
+    let mut seen: HashMap<(String, String), (String, String)> = HashMap::new();
+    for header in headers {
+        for line in &header.meta {
+            let identified = Header::parse(line)
+                .ok()
+                .and_then(|parsed| match parsed.value {
+                    HeaderValue::Nested(fields) => {
+                        Some((parsed.key, fields.get("ID").copied()?, fields))
+                    }
+                    HeaderValue::Flat(_) => None,
+                });
+            let Some((key, id, fields)) = identified else {
+                if !merged.meta.contains(line) {
+                    merged.meta.push(line.clone());
+                }
+                continue;
+            };
+            let field = |name: &str| fields.get(name).copied().unwrap_or_default().to_string();
+            let definition = (field("Number"), field("Type"));
+            let key = (key.to_string(), id.to_string());
+            match seen.get(&key) {
+                Some(first) => {
+                    if matches!(key.0.as_str(), "INFO" | "FORMAT") && *first != definition {
+                        conflicts.push(format!(
+                            "{} {} is Number={},Type={} and Number={},Type={}",
+                            key.0, key.1, first.0, first.1, definition.0, definition.1
+                        ));
+                    }
+                }
+                None => {
+                    seen.insert(key, definition);
+                    merged.meta.push(line.clone());
+                }
+            }
+        }
+    }
+    merged
+}
+
+/// Which records at the same position may be merged into one multiallelic record.
+///
+/// Records with the same REF and ALT alleles are always merged, as are records without ALT
+/// alleles, which are merged with the first record they can join.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Multiallelic {
+    /// Only records with the same alleles.
+    None,
+    /// SNPs with SNPs.
+    Snps,
+    /// Indels with indels.
+    Indels,
+    /// SNPs with SNPs and indels with indels.
+    #[default]
+    Both,
+    /// Any records.
+    All,
+}
+
+impl FromStr for Multiallelic {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "none" => Ok(Multiallelic::None),
+            "snps" => Ok(Multiallelic::Snps),
+            "indels" => Ok(Multiallelic::Indels),
+            "both" => Ok(Multiallelic::Both),
+            "all" => Ok(Multiallelic::All),
+            _ => Err(ParseError),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Class {
+    Snp,
+    Indel,
+    Other,
+}
+
+fn class(record: &Record) -> Class {
+    let alleles: Vec<Allele> = record
+        .alt
+        .iter()
+        .filter_map(|alt| alt.parse().ok())
+        .filter(|allele: &Allele| !allele.is_unspecified())
+        .collect();
+    let bases = |allele: &Allele| match allele {
+        Allele::Bases(bases) => Some(bases.len()),
+        _ => None,
+    };
+    let lengths: Option<Vec<usize>> = alleles.iter().map(bases).collect();
+    match lengths {
+        Some(lengths) if !lengths.is_empty() => {
+            if record.reference.len() == 1 && lengths.iter().all(|&n| n == 1) {
+                Class::Snp
+            } else if lengths.iter().any(|&n| n != record.reference.len()) {
+                Class::Indel
+            } else {
+                Class::Other
+            }
+        }
+        _ => Class::Other,
+    }
+}
+
+impl Multiallelic {
+    fn allows(self, a: &Record, b: &Record) -> bool {
+        if a.alt.is_empty() || b.alt.is_empty() {
+            return true;
+        }
+        let alleles = |record: &Record| {
+            let mut alt = record.alt.clone();
+            alt.sort();
+            (record.reference.clone(), alt)
+        };
+        if alleles(a) == alleles(b) {
+            return true;
+        }
+        matches!(
+            (self, class(a), class(b)),
+            (Multiallelic::All, _, _)
+                | (
+                    Multiallelic::Snps | Multiallelic::Both,
+                    Class::Snp,
+                    Class::Snp
+                )
+                | (
+                    Multiallelic::Indels | Multiallelic::Both,
+                    Class::Indel,
+                    Class::Indel
+                )
+        )
+    }
+}
+
+/// Merge position-sorted VCFs with distinct samples into one VCF written to `output`.
+///
+/// Contigs come in the order of the `##contig` lines, then in the order the inputs reach them,
+/// so each input must have every contig in one block, in the same order as the other inputs.
+///
+/// Records at the same position are grouped into output records, at most one per input, as
+/// allowed by `multiallelic`. In each output record the REF is the longest REF of the group and
+/// the ALT alleles are the union of theirs; GT and Number=A/R/G values are renumbered to match.
+/// IDs and FILTERs are combined, with PASS dropped when another filter applies, QUAL is the
+/// highest QUAL and other INFO values are taken from the first input that has them. Samples of
+/// inputs without a record in the group are written as `./.`.
+///
+/// ```
+/// use std::io::Cursor;
+/// use vcf::merge::{merge, Multiallelic};
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let input = |sample: &str, genotype: &str| {
+///     let text = format!(
+///         "##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}\n\
+///          1\t100\t.\tA\tG\t.\t.\t.\tGT\t{}\n",
+///         sample, genotype
+///     );
+///     Reader::new(Cursor::new(text.into_bytes()))
+/// };
+/// let mut output = Vec::new();
+/// merge(vec![input("A", "0/1")?, input("B", "1/1")?], Multiallelic::Both, &mut output)?;
+/// assert!(String::from_utf8(output).unwrap().ends_with("1\t100\t.\tA\tG\t.\t.\t.\tGT\t0/1\t1/1\n"));
+///# Ok::<(), VCFError>(())
+/// ```
+pub fn merge<R: BufRead>(
+    readers: Vec<Reader<R>>,
+    multiallelic: Multiallelic,
+    mut output: impl Write,
+) -> Result<(), VCFError> {
+    let header = merge_headers(&readers.iter().map(Reader::header).collect::<Vec<_>>())?;
+    let definitions = header.definitions();
+    let mut order = ContigOrder::new(header.contigs());
+    write!(output, "{}", header)?;
+
+    let mut inputs = readers
+        .into_iter()
+        .map(|reader| Input::new(reader, &mut order))
+        .collect::<Result<Vec<_>, VCFError>>()?;
+    let samples: Vec<usize> = inputs.iter().map(|input| input.samples).collect();
+
+    while let Some((chrom, pos)) = next_position(&inputs, &order) {
+        // Each group holds at most one record per input, indexed by input.
+        let mut groups: Vec<Vec<Option<Record>>> = Vec::new();
+        for (index, input) in inputs.iter_mut().enumerate() {
+            while let Some(record) = input.take_at(&chrom, pos, &mut order)? {
+                let group = groups.iter_mut().find(|group| {
+                    group[index].is_none()
+                        && group
+                            .iter()
+                            .flatten()
+                            .all(|other| multiallelic.allows(other, &record))
+                });
+                match group {
+                    Some(group) => group[index] = Some(record),
+                    None => {
+                        let mut group = vec![None; samples.len()];
+                        group[index] = Some(record);
+                        groups.push(group);
+                    }
+                }
+            }
+        }
+        for group in groups {
+            let record = merge_records(&group, &samples, &definitions);
+            writeln!(output, "{}", record)?;
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Merge records at the same position, one per input, given the number of samples per input.
+fn merge_records(
+    records: &[Option<Record>],
+    samples: &[usize],
+    definitions: &Definitions,
+) -> Record {
+    let present: Vec<&Record> = records.iter().flatten().collect();
+    let first = present[0];
+    let merged = MergedAlleles::new(&present);
+    let allele_count = merged.allele_count();
+
+    let mut id: Vec<String> = Vec::new();
+    let mut filter: Vec<String> = Vec::new();
+    let mut info: Vec<(String, Option<String>)> = Vec::new();
+    for (record, map) in present.iter().zip(&merged.maps) {
+        for value in &record.id {
+            if !id.contains(value) {
+                id.push(value.clone());
+            }
+        }
+        for value in &record.filter {
+            if !filter.contains(value) {
+                filter.push(value.clone());
+            }
+        }
+        merge_info(&mut info, record, map, allele_count, definitions);
+    }
+    if filter.len() > 1 {
+        filter.retain(|value| value != "PASS");
+    }
+    let (qual, qual_text) = present
+        .iter()
+        .filter_map(|record| Some((record.qual?, &record.qual_text)))
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map_or((None, None), |(qual, text)| (Some(qual), text.clone()));
+
+    let format = if samples.iter().sum::<usize>() == 0 {
+        Vec::new()
+    } else {
+        format_keys(present.iter().copied())
+    };
+    let mut maps = merged.maps.iter();
+    let columns = records
+        .iter()
+        .zip(samples)
+        .flat_map(|(record, &count)| {
+            let map = record.as_ref().and_then(|_| maps.next());
+            let format = &format;
+            (0..count).map(move |sample| match (record, map) {
+                (Some(record), Some(map)) => {
+                    remap_sample(record, sample, format, map, allele_count, definitions)
+                }
+                _ => no_call(format),
+            })
+        })
+        .collect();
+
+    Record {
+        chrom: first.chrom.clone(),
+        pos: first.pos,
+        id,
+        reference: merged.reference,
+        alt: merged.alt,
+        qual,
+        qual_text,
+        filter,
+        info,
+        format,
+        samples: columns,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const HEADER: &str = "##fileformat=VCFv4.3
+##contig=<ID=2>
+##contig=<ID=1>
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Allele depths\">
+";
+
+    fn reader(samples: &[&str], records: &[&str]) -> Reader<Cursor<Vec<u8>>> {
+        let text = format!(
+            "{}#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}\n{}\n",
+            HEADER,
+            samples.join("\t"),
+            records.join("\n")
+        );
+        Reader::new(Cursor::new(text.into_bytes())).unwrap()
+    }
+
+    fn merged(readers: Vec<Reader<Cursor<Vec<u8>>>>, multiallelic: Multiallelic) -> Vec<String> {
+        let mut output = Vec::new();
+        merge(readers, multiallelic, &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn fills_missing_samples_with_no_calls() {
+        let a = reader(
+            &["A1", "A2"],
+            &[
+                "2\t10\trs1\tC\tT\t20\tPASS\tAF=0.5\tGT:AD\t0/1:5,5\t0/0:9,0",
+                "1\t5\t.\tG\tA\t.\t.\t.\tGT\t1/1\t0/1",
+            ],
+        );
+        let b = reader(
+            &["B"],
+            &[
+                "2\t10\trs1;rs2\tC\tT\t50\tq10\tAF=0.4\tGT\t1/1",
+                "2\t12\t.\tA\tC\t.\t.\t.\tGT:AD\t0/1:3,3",
+            ],
+        );
+        assert_eq!(
+            merged(vec![a, b], Multiallelic::Both),
+            vec![
+                "2\t10\trs1;rs2\tC\tT\t50\tq10\tAF=0.5\tGT:AD\t0/1:5,5\t0/0:9,0\t1/1:.",
+                "2\t12\t.\tA\tC\t.\t.\t.\tGT:AD\t./.:.\t./.:.\t0/1:3,3",
+                "1\t5\t.\tG\tA\t.\t.\t.\tGT\t1/1\t0/1\t./.",
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_records_by_multiallelic_mode() {
+        let records = || {
+            (
+                reader(
+                    &["A"],
+                    &[
+                        "1\t5\t.\tG\tA\t.\t.\tAF=0.1\tGT:AD\t0/1:4,4",
+                        "1\t5\t.\tGT\tG\t.\t.\t.\tGT\t0/1",
+                    ],
+                ),
+                reader(
+                    &["B"],
+                    &[
+                        "1\t5\t.\tG\tC\t.\t.\tAF=0.2\tGT:AD\t1/1:0,8",
+                        "1\t5\t.\tGTT\tG\t.\t.\t.\tGT\t0/1",
+                    ],
+                ),
+            )
+        };
+        let (a, b) = records();
+        assert_eq!(
+            merged(vec![a, b], Multiallelic::None),
+            vec![
+                "1\t5\t.\tG\tA\t.\t.\tAF=0.1\tGT:AD\t0/1:4,4\t./.:.",
+                "1\t5\t.\tGT\tG\t.\t.\t.\tGT\t0/1\t./.",
+                "1\t5\t.\tG\tC\t.\t.\tAF=0.2\tGT:AD\t./.:.\t1/1:0,8",
+                "1\t5\t.\tGTT\tG\t.\t.\t.\tGT\t./.\t0/1",
+            ]
+        );
+        let (a, b) = records();
+        assert_eq!(
+            merged(vec![a, b], Multiallelic::Both),
+            vec![
+                "1\t5\t.\tG\tA,C\t.\t.\tAF=0.1,0.2\tGT:AD\t0/1:4,4,.\t2/2:0,.,8",
+                "1\t5\t.\tGTT\tGT,G\t.\t.\t.\tGT\t0/1\t0/2",
+            ]
+        );
+
+        let a = reader(&["A"], &["1\t5\t.\tG\tA\t.\t.\t.\tGT\t0/1"]);
+        let b = reader(&["B"], &["1\t5\t.\tGTT\tG\t.\t.\t.\tGT\t1/1"]);
+        assert_eq!(
+            merged(vec![a, b], Multiallelic::All),
+            vec!["1\t5\t.\tGTT\tATT,G\t.\t.\t.\tGT\t0/1\t2/2"]
+        );
+    }
+
+    #[test]
+    fn reports_header_conflicts() {
+        let a = VCF {
+            meta: vec!["##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">".to_string()],
+            samples: vec!["A".to_string()],
+            ..VCF::default()
+        };
+        let b = VCF {
+            meta: vec!["##INFO=<ID=DP,Number=A,Type=Float,Description=\"Depth\">".to_string()],
+            samples: vec!["A".to_string()],
+            ..VCF::default()
+        };
+        let Err(VCFError::InvalidHeader(message)) = merge_headers(&[&a, &b]) else {
+            panic!()
+        };
+        assert!(message.contains("INFO DP is Number=1,Type=Integer and Number=A,Type=Float"));
+        assert!(message.contains("sample A appears twice"));
+        assert_eq!(
+            merge_headers(&[&a, &a.clone()]).unwrap_err().to_string(),
+            "invalid header: sample A appears twice"
+        );
+    }
+
+    #[test]
+    fn rejects_unsorted_input() {
+        let a = reader(
+            &["A"],
+            &[
+                "1\t5\t.\tG\tA\t.\t.\t.\tGT\t0/1",
+                "1\t2\t.\tG\tA\t.\t.\t.\tGT\t0/1",
+            ],
+        );
+        assert!(merge(vec![a], Multiallelic::Both, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn merges_contigs_without_contig_lines_in_the_order_they_come() {
+        let reader = |sample: &str, records: &[&str]| {
+            let text = format!(
+                "##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}\n{}\n",
+                sample,
+                records.join("\n")
+            );
+            Reader::new(Cursor::new(text.into_bytes())).unwrap()
+        };
+        let a = reader(
+            "A",
+            &[
+                "chr1\t5\t.\tG\tA\t.\t.\t.\tGT\t0/1",
+                "chr10\t5\t.\tG\tA\t.\t.\t.\tGT\t0/1",
+                "chr2\t5\t.\tG\tA\t.\t.\t.\tGT\t0/1",
+            ],
+        );
+        let b = reader(
+            "B",
+            &[
+                "chr1\t5\t.\tG\tA\t.\t.\t.\tGT\t1/1",
+                "chr2\t5\t.\tG\tA\t.\t.\t.\tGT\t1/1",
+            ],
+        );
+        let lines = merged(vec![a, b], Multiallelic::Both);
+        let contigs: Vec<&str> = lines
+            .iter()
+            .map(|line| &line[..line.find('\t').unwrap()])
+            .collect();
+        assert_eq!(contigs, ["chr1", "chr10", "chr2"]);
+        assert!(lines[2].ends_with("GT\t0/1\t1/1"));
+
+        // A contig that comes back after another is still out of order.
+        let a = reader(
+            "A",
+            &[
+                "chr1\t5\t.\tG\tA\t.\t.\t.\tGT\t0/1",
+                "chr2\t5\t.\tG\tA\t.\t.\t.\tGT\t0/1",
+                "chr1\t9\t.\tG\tA\t.\t.\t.\tGT\t0/1",
+            ],
+        );
+        assert!(merge(vec![a], Multiallelic::Both, Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,493 @@
+//! Random access to uncompressed VCF files through a memory map.
+//!
+//! A [`MappedVcf`] maps the whole file, so the header lines and records read from it borrow
+//! the mapped text instead of copying it. Only the header is checked to be UTF-8 when the file
+//! is opened; data lines are checked as they are read. Data lines are found by byte offset, or
+//! by number with a [`LineIndex`], which can be saved next to the file and loaded again.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use memmap2::Mmap;
+
+use crate::vcf::{Reader, VCFError, VCF};
+use crate::{Header, LazyRecord};
+
+/// The number of lines between the offsets kept by a [`LineIndex`], unless told otherwise.
+pub const DEFAULT_STRIDE: usize = 256;
+
+enum Source {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Source {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Source::Mapped(map) => map,
+            Source::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// A VCF file held in memory, mapped from disk or given as bytes.
+///
+/// Data lines are numbered from 0, skipping blank lines as [`Reader`] does.
+///
+/// ```
+/// use vcf::mmap::{LineIndex, MappedVcf};
+///
+/// let source = "##fileformat=VCFv4.3\n##contig=<ID=1>\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+/// 1\t100\t.\tA\tG\t.\t.\t.\n1\t200\t.\tC\tT\t.\t.\t.\n1\t300\t.\tG\tA\t.\t.\t.\n";
+/// let vcf = MappedVcf::from_bytes(source.as_bytes().to_vec())?;
+/// assert_eq!(vcf.headers().nth(1).unwrap().id(), Some("1"));
+///
+/// // Jumping into the middle of a line goes on to the next one.
+/// let record = vcf.records_from(vcf.data_start() + 3).next().unwrap()?;
+/// assert_eq!(record.pos(), 200);
+///
+/// let index = LineIndex::build(&vcf, 2);
+/// assert_eq!(index.lines(), 3);
+/// assert_eq!(vcf.line(&index, 2).unwrap()?, "1\t300\t.\tG\tA\t.\t.\t.");
+///# Ok::<(), vcf::vcf::VCFError>(())
+/// ```
+pub struct MappedVcf {
+    source: Source,
+    header: VCF,
+    /// The offset of the first data line.
+    data_start: usize,
+    /// When the mapped file was last modified, if it is known.
+    modified: Option<SystemTime>,
+    /// The 64-bit FNV-1a hash of the header lines.
+    header_checksum: u64,
+}
+
+impl MappedVcf {
+    /// Map the file at `path`, which must not be changed while it is mapped.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, VCFError> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only read, and like any reader of the file we rely on it not being
+        // truncated or rewritten underneath us.
+        let map = unsafe { Mmap::map(&file)? };
+        let modified = file.metadata()?.modified().ok();
+        Self::new(Source::Mapped(map), modified)
+    }
+
+    /// Use the text of a VCF file that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, VCFError> {
+        Self::new(Source::Owned(bytes), None)
+    }
+
+    fn new(source: Source, modified: Option<SystemTime>) -> Result<Self, VCFError> {
+        let mut data_start = None;
+        let mut offset = 0;
+        for line in source.bytes().split_inclusive(|&byte| byte == b'\n') {
+            offset += line.len();
+            if line.starts_with(b"#CHROM") {
+                data_start = Some(offset);
+                break;
+            }
+        }
+        let data_start = data_start.ok_or(VCFError::ParseError)?;
+        std::str::from_utf8(&source.bytes()[..data_start])
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let header = Reader::new(&source.bytes()[..data_start])?.into_header();
+        let header_checksum = source.bytes()[..data_start]
+            .iter()
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+        Ok(Self {
+            source,
+            header,
+            data_start,
+            modified,
+            header_checksum,
+        })
+    }
+
+    /// The whole file, whose data lines may not be UTF-8.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.source.bytes()
+    }
+
+    /// The header lines, up to and including the `#CHROM` line.
+    fn header_text(&self) -> &str {
+        // SAFETY: the header was checked to be UTF-8 when the file was opened.
+        unsafe { std::str::from_utf8_unchecked(&self.source.bytes()[..self.data_start]) }
+    }
+
+    pub fn header(&self) -> &VCF {
+        &self.header
+    }
+
+    /// The `##` header lines, parsed in place.
+    pub fn headers(&self) -> impl Iterator<Item = Header<'_>> {
+        self.header_text()
+            .lines()
+            .filter(|line| line.starts_with("##"))
+            .filter_map(|line| Header::parse(line).ok())
+    }
+
+    /// The offset of the first data line.
+    pub fn data_start(&self) -> usize {
+        self.data_start
+    }
+
+    /// The offset of the first data line starting at or after `offset`, or the end of the file.
+    pub fn line_start(&self, offset: usize) -> usize {
+        let text = self.as_bytes();
+        if offset <= self.data_start {
+            self.data_start
+        } else if offset >= text.len() || text[offset - 1] == b'\n' {
+            offset.min(text.len())
+        } else {
+            text[offset..]
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(text.len(), |i| offset + i + 1)
+        }
+    }
+
+    /// The data lines from the first one starting at or after `offset`, with their offsets.
+    pub fn lines_from(&self, offset: usize) -> Lines<'_> {
+        Lines {
+            text: self.as_bytes(),
+            position: self.line_start(offset),
+        }
+    }
+
+    /// The records from the first line starting at or after `offset`.
+    pub fn records_from(
+        &self,
+        offset: usize,
+    ) -> impl Iterator<Item = Result<LazyRecord<'_>, VCFError>> {
+        self.lines_from(offset)
+            .map(|line| LazyRecord::parse(line?.1, None))
+    }
+
+    /// The data line numbered `line`, found through `index`.
+    pub fn line(&self, index: &LineIndex, line: u64) -> Option<Result<&str, VCFError>> {
+        let offset = index.offset(self, line)?;
+        self.lines_from(offset).next().map(|line| Ok(line?.1))
+    }
+}
+
+/// The data lines of a [`MappedVcf`] and their offsets, without line endings. A line that is not
+/// UTF-8 is an error.
+pub struct Lines<'m> {
+    text: &'m [u8],
+    position: usize,
+}
+
+impl<'m> Lines<'m> {
+    /// The next data line and its offset, as bytes.
+    fn next_bytes(&mut self) -> Option<(usize, &'m [u8])> {
+        while self.position < self.text.len() {
+            let rest = &self.text[self.position..];
+            let length = rest
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(rest.len(), |i| i + 1);
+            let start = self.position;
+            self.position += length;
+            let line = &rest[..length];
+            if !line.trim_ascii().is_empty() {
+                let end = line.iter().rposition(|byte| !b"\r\n".contains(byte));
+                return Some((start, &line[..end.map_or(0, |i| i + 1)]));
+            }
+        }
+        None
+    }
+}
+
+impl<'m> Iterator for Lines<'m> {
+    type Item = Result<(usize, &'m str), VCFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, line) = self.next_bytes()?;
+        Some(match std::str::from_utf8(line) {
+            Ok(line) => Ok((start, line)),
+            Err(_) => Err(VCFError::InvalidRecord(format!(
+                "the line at byte {} is not UTF-8",
+                start
+            ))),
+        })
+    }
+}
+
+/// The offsets of every `stride`-th data line of a file.
+///
+/// A line between two indexed ones is found by scanning forward from the one before it, so a
+/// larger stride makes a smaller index and slower lookups. The index is saved as text: a line
+/// with the stride, the size, modification time and header checksum of the file and the number
+/// of data lines, then one offset per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    stride: usize,
+    /// The size, modification time in nanoseconds since the epoch (0 if unknown) and header
+    /// checksum of the indexed file, to tell when it has changed.
+    file_size: u64,
+    modified: u128,
+    header_checksum: u64,
+    lines: u64,
+    offsets: Vec<u64>,
+}
+
+/// When `vcf` was last modified, in nanoseconds since the epoch, or 0 if that is not known.
+fn modified(vcf: &MappedVcf) -> u128 {
+    vcf.modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos())
+}
+
+impl LineIndex {
+    pub fn build(vcf: &MappedVcf, stride: usize) -> Self {
+        let stride = stride.max(1);
+        let mut lines = 0;
+        let mut offsets = Vec::new();
+        let mut data = vcf.lines_from(0);
+        for (i, (offset, _)) in std::iter::from_fn(|| data.next_bytes()).enumerate() {
+            if i % stride == 0 {
+                offsets.push(offset as u64);
+            }
+            lines += 1;
+        }
+        Self {
+            stride,
+            file_size: vcf.as_bytes().len() as u64,
+            modified: modified(vcf),
+            header_checksum: vcf.header_checksum,
+            lines,
+            offsets,
+        }
+    }
+
+    /// Read the index saved next to `path` if it was made for `vcf`, or build one with the
+    /// default stride.
+    pub fn open(path: impl AsRef<Path>, vcf: &MappedVcf) -> Result<Self, VCFError> {
+        if let Ok(file) = File::open(index_path(path.as_ref())) {
+            let index = Self::read(BufReader::new(file))?;
+            if index.matches(vcf) {
+                return Ok(index);
+            }
+        }
+        Ok(Self::build(vcf, DEFAULT_STRIDE))
+    }
+
+    /// Save the index next to the file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), VCFError> {
+        let mut output = BufWriter::new(File::create(index_path(path.as_ref()))?);
+        self.write(&mut output)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    pub fn read(source: impl BufRead) -> Result<Self, VCFError> {
+        let mut lines = source.lines();
+        let first = lines.next().ok_or(VCFError::ParseError)??;
+        let numbers: Vec<u128> = first
+            .split('\t')
+            .map(|number| number.parse().map_err(|_| VCFError::ParseError))
+            .collect::<Result<_, _>>()?;
+        let [stride, file_size, modified, header_checksum, count] = numbers[..] else {
+            return Err(VCFError::ParseError);
+        };
+        let small = |number: u128| u64::try_from(number).map_err(|_| VCFError::ParseError);
+        let (stride, file_size, header_checksum, count) = (
+            small(stride)?,
+            small(file_size)?,
+            small(header_checksum)?,
+            small(count)?,
+        );
+        let offsets: Vec<u64> = lines
+            .map(|line| line?.parse().map_err(|_| VCFError::ParseError))
+            .collect::<Result<_, _>>()?;
+        if stride == 0
+            || offsets.len() as u64 != count.div_ceil(stride)
+            || offsets.iter().any(|&offset| offset >= file_size)
+        {
+            return Err(VCFError::ParseError);
+        }
+        Ok(Self {
+            stride: stride as usize,
+            file_size,
+            modified,
+            header_checksum,
+            lines: count,
+            offsets,
+        })
+    }
+
+    pub fn write(&self, mut output: impl Write) -> io::Result<()> {
+        writeln!(
+            output,
+            "{}\t{}\t{}\t{}\t{}",
+            self.stride, self.file_size, self.modified, self.header_checksum, self.lines
+        )?;
+        for offset in &self.offsets {
+            writeln!(output, "{}", offset)?;
+        }
+        Ok(())
+    }
+
+    /// The number of data lines in the file.
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// Whether the index was built for a file of the size, modification time and header of
+    /// `vcf`.
+    pub fn matches(&self, vcf: &MappedVcf) -> bool {
+        self.file_size == vcf.as_bytes().len() as u64
+            && self.modified == modified(vcf)
+            && self.header_checksum == vcf.header_checksum
+    }
+
+    /// The offset of the data line numbered `line` in `vcf`.
+    pub fn offset(&self, vcf: &MappedVcf, line: u64) -> Option<usize> {
+        if line >= self.lines || !self.matches(vcf) {
+            return None;
+        }
+        let stride = self.stride as u64;
+        let start = self.offsets[(line / stride) as usize] as usize;
+        let mut data = vcf.lines_from(start);
+        let (offset, _) = std::iter::from_fn(|| data.next_bytes()).nth((line % stride) as usize)?;
+        Some(offset)
+    }
+}
+
+/// The path of the index saved next to `path`.
+fn index_path(path: &Path) -> PathBuf {
+    let mut index = path.as_os_str().to_owned();
+    index.push(".lidx");
+    PathBuf::from(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Record;
+
+    fn source(records: u64) -> String {
+        let mut source = String::from(
+            "##fileformat=VCFv4.3\n##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+        );
+        for pos in 1..=records {
+            source.push_str(&format!("1\t{}\t.\tA\tG\t.\t.\tDP={}\r\n", pos, pos % 7));
+            if pos % 10 == 0 {
+                source.push('\n');
+            }
+        }
+        source
+    }
+
+    #[test]
+    fn reads_lines_in_place() {
+        let text = source(25);
+        let vcf = MappedVcf::from_bytes(text.clone().into_bytes()).unwrap();
+        assert_eq!(vcf.header(), Reader::new(text.as_bytes()).unwrap().header());
+        let header = vcf.headers().nth(1).unwrap();
+        assert!(vcf.as_bytes().as_ptr_range().contains(&header.key.as_ptr()));
+
+        let sequential: Vec<Record> = Reader::new(text.as_bytes())
+            .unwrap()
+            .records()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let mapped: Vec<Record> = vcf
+            .records_from(0)
+            .map(|record| record.unwrap().to_record().unwrap())
+            .collect();
+        assert_eq!(mapped, sequential);
+
+        let (second, _) = vcf.lines_from(0).nth(1).unwrap().unwrap();
+        for offset in vcf.data_start() + 1..=second {
+            assert_eq!(vcf.line_start(offset), second);
+        }
+        assert!(vcf.lines_from(text.len() + 5).next().is_none());
+    }
+
+    #[test]
+    fn checks_data_lines_are_utf8_when_read() {
+        let mut bytes = source(3).into_bytes();
+        let second = bytes.windows(3).position(|w| w == b"\t2\t").unwrap();
+        bytes[second + 3] = 0xff;
+        let vcf = MappedVcf::from_bytes(bytes).unwrap();
+        let lines: Vec<_> = vcf.lines_from(0).collect();
+        assert!(lines[0].is_ok() && lines[2].is_ok());
+        assert!(lines[1].is_err());
+        let index = LineIndex::build(&vcf, 1);
+        assert_eq!(index.lines(), 3);
+        assert!(vcf.line(&index, 1).unwrap().is_err());
+
+        let mut header = source(1).into_bytes();
+        header[3] = 0xff;
+        assert!(MappedVcf::from_bytes(header).is_err());
+    }
+
+    #[test]
+    fn finds_lines_by_number() {
+        let vcf = MappedVcf::from_bytes(source(25).into_bytes()).unwrap();
+        let lines: Vec<&str> = vcf.lines_from(0).map(|line| line.unwrap().1).collect();
+        for stride in [1, 3, 100] {
+            let index = LineIndex::build(&vcf, stride);
+            assert_eq!(index.lines(), 25);
+            for (number, line) in lines.iter().enumerate() {
+                let found = vcf.line(&index, number as u64).unwrap().unwrap();
+                assert_eq!(found, *line);
+            }
+            assert!(vcf.line(&index, 25).is_none());
+        }
+    }
+
+    #[test]
+    fn saves_the_index_next_to_the_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("calls.vcf");
+        std::fs::write(&path, source(1000)).unwrap();
+        let vcf = MappedVcf::open(&path).unwrap();
+
+        let index = LineIndex::build(&vcf, 16);
+        index.save(&path).unwrap();
+        assert!(directory.path().join("calls.vcf.lidx").exists());
+        assert_eq!(LineIndex::open(&path, &vcf).unwrap(), index);
+
+        let mut text = Vec::new();
+        index.write(&mut text).unwrap();
+        assert_eq!(LineIndex::read(&text[..]).unwrap(), index);
+        let text = String::from_utf8(text).unwrap();
+        let truncated: Vec<&str> = text.lines().take(index.offsets.len()).collect();
+        assert!(LineIndex::read(truncated.join("\n").as_bytes()).is_err());
+
+        // An index made for another version of the file is rebuilt.
+        drop(vcf);
+        std::fs::write(&path, source(999)).unwrap();
+        let vcf = MappedVcf::open(&path).unwrap();
+        let index = LineIndex::open(&path, &vcf).unwrap();
+        assert_eq!(index.lines(), 999);
+        assert_eq!(index.stride, DEFAULT_STRIDE);
+
+        // So is one made for a file of the same size with another header or modification time.
+        index.save(&path).unwrap();
+        drop(vcf);
+        std::fs::write(&path, source(999).replace("Depth", "Reads")).unwrap();
+        let file = File::options().write(true).open(&path).unwrap();
+        let indexed = UNIX_EPOCH + std::time::Duration::from_nanos(index.modified as u64);
+        file.set_modified(indexed).unwrap();
+        let vcf = MappedVcf::open(&path).unwrap();
+        assert_eq!(modified(&vcf), index.modified);
+        assert!(!index.matches(&vcf));
+        drop(vcf);
+        std::fs::write(&path, source(999)).unwrap();
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(UNIX_EPOCH).unwrap();
+        let vcf = MappedVcf::open(&path).unwrap();
+        assert!(!index.matches(&vcf));
+        let rebuilt = LineIndex::open(&path, &vcf).unwrap();
+        assert_eq!(rebuilt.modified, 0);
+        assert!(rebuilt.matches(&vcf));
+    }
+}
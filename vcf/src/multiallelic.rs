@@ -0,0 +1,773 @@
+//! Splitting multiallelic records into biallelic ones, and joining them back.
+//!
+//! INFO and FORMAT values are subset or combined according to their `Number` in the header:
+//! Number=A fields have one value per ALT allele, Number=R fields one per allele including REF,
+//! and Number=G fields one per genotype. Fields with any other `Number` are copied unchanged.
+use crate::vcf::VCFError;
+use crate::{genotype_count, genotype_index, Allele, Definitions, Genotype, NumberField, Record};
+
+/// Split a record with N ALT alleles into N biallelic records.
+///
+/// Genotype alleles referring to the other ALT alleles are set to the reference allele.
+///
+/// ```
+/// use vcf::{Definitions, Record};
+/// use vcf::multiallelic::split;
+///# use vcf::vcf::VCFError;
+/// let record = Record::parse("1\t100\t.\tA\tG,T\t.\t.\t.\tGT\t1/2")?;
+/// let split = split(&record, &Definitions::default())?;
+/// assert_eq!(split[0].to_string(), "1\t100\t.\tA\tG\t.\t.\t.\tGT\t1/0");
+/// assert_eq!(split[1].to_string(), "1\t100\t.\tA\tT\t.\t.\t.\tGT\t0/1");
+///# Ok::<(), VCFError>(())
+/// ```
+pub fn split(record: &Record, definitions: &Definitions) -> Result<Vec<Record>, VCFError> {
+    let alt_count = record.alt.len();
+    if alt_count <= 1 {
+        return Ok(vec![record.clone()]);
+    }
+    let gt_index = record.format.iter().position(|key| key == "GT");
+    (1..=alt_count)
+        .map(|alt| {
+            let keep = |allele: usize| if allele == alt { 1 } else { 0 };
+            let info = record
+                .info
+                .iter()
+                .map(|(key, value)| {
+                    let value = match (value, definitions.info.get(key)) {
+                        (Some(value), Some(definition)) => {
+                            let number = definition.fieldtype.number();
+                            Some(subset(key, value, number, alt_count, alt, 2)?)
+                        }
+                        (value, _) => value.clone(),
+                    };
+                    Ok((key.clone(), value))
+                })
+                .collect::<Result<_, VCFError>>()?;
+            let samples = record
+                .samples
+                .iter()
+                .map(|sample| {
+                    let genotype = gt_index
+                        .and_then(|i| sample.get(i))
+                        .and_then(|gt| gt.parse::<Genotype>().ok());
+                    let ploidy = genotype.as_ref().map_or(2, Genotype::ploidy);
+                    sample
+                        .iter()
+                        .zip(&record.format)
+                        .enumerate()
+                        .map(
+                            |(i, (value, key))| match (&genotype, definitions.format.get(key)) {
+                                (Some(genotype), _) if Some(i) == gt_index => {
+                                    let mut genotype = genotype.clone();
+                                    for allele in genotype.alleles.iter_mut().flatten() {
+                                        *allele = keep(*allele);
+                                    }
+                                    Ok(genotype.to_string())
+                                }
+                                (_, Some(definition)) => {
+                                    let number = definition.fieldtype.number();
+                                    subset(key, value, number, alt_count, alt, ploidy)
+                                }
+                                _ => Ok(value.clone()),
+                            },
+                        )
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(Record {
+                alt: vec![record.alt[alt - 1].clone()],
+                info,
+                samples,
+                ..record.clone()
+            })
+        })
+        .collect()
+}
+
+fn subset(
+    key: &str,
+    value: &str,
+    number: NumberField,
+    alt_count: usize,
+    alt: usize,
+    ploidy: usize,
+) -> Result<String, VCFError> {
+    if value == "." {
+        return Ok(value.to_string());
+    }
+    let values: Vec<&str> = value.split(',').collect();
+    let indices: Vec<usize> = match number {
+        NumberField::A => vec![alt - 1],
+        NumberField::R => vec![0, alt],
+        NumberField::G => (0..=ploidy)
+            .map(|copies| {
+                let mut alleles = vec![0; ploidy - copies];
+                alleles.extend(std::iter::repeat_n(alt, copies));
+                genotype_index(&alleles)
+            })
+            .collect(),
+        _ => return Ok(value.to_string()),
+    };
+    let expected = match number {
+        NumberField::A => alt_count,
+        NumberField::R => alt_count + 1,
+        _ => genotype_count(alt_count + 1, ploidy),
+    };
+    if values.len() != expected {
+        return Err(VCFError::InvalidRecord(format!(
+            "{} has {} values, expected {}",
+            key,
+            values.len(),
+            expected
+        )));
+    }
+    Ok(indices
+        .iter()
+        .map(|&i| values[i])
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+/// Join records at the same CHROM, POS and REF into one record with all of their ALT alleles.
+///
+/// Number=A/R/G values are combined allele by allele. Genotype likelihoods that none of the
+/// inputs has a value for (for example 1/2 when joining two biallelic records) are set to
+/// missing. Other INFO and FORMAT values, QUAL and the column values of the first record take
+/// precedence. A genotype that refers to an allele its record does not have is an error.
+pub fn join(records: &[Record], definitions: &Definitions) -> Result<Record, VCFError> {
+    let first = records
+        .first()
+        .ok_or_else(|| VCFError::InvalidRecord("no records to join".to_string()))?;
+    if records
+        .iter()
+        .any(|r| r.chrom != first.chrom || r.pos != first.pos || r.reference != first.reference)
+    {
+        return Err(VCFError::InvalidRecord(format!(
+            "records at {}:{} do not share a position and REF",
+            first.chrom, first.pos
+        )));
+    }
+
+    // For each input record, the index of each of its alleles in the joined record.
+    let mut alt: Vec<String> = Vec::new();
+    let allele_maps: Vec<Vec<usize>> = records
+        .iter()
+        .map(|record| {
+            std::iter::once(0)
+                .chain(
+                    record
+                        .alt
+                        .iter()
+                        .map(|a| match alt.iter().position(|b| a == b) {
+                            Some(i) => i + 1,
+                            None => {
+                                alt.push(a.clone());
+                                alt.len()
+                            }
+                        }),
+                )
+                .collect()
+        })
+        .collect();
+    let allele_count = alt.len() + 1;
+    let sources: Vec<Source> = records
+        .iter()
+        .zip(&allele_maps)
+        .map(|(record, map)| Source { record, map })
+        .collect();
+
+    let mut id: Vec<String> = Vec::new();
+    let mut filter: Vec<String> = Vec::new();
+    for record in records {
+        for value in &record.id {
+            if !id.contains(value) {
+                id.push(value.clone());
+            }
+        }
+        for value in &record.filter {
+            if !filter.contains(value) {
+                filter.push(value.clone());
+            }
+        }
+    }
+    if filter.len() > 1 {
+        filter.retain(|f| f != "PASS");
+    }
+
+    let mut info: Vec<(String, Option<String>)> = Vec::new();
+    for record in records {
+        for (key, _) in &record.info {
+            if info.iter().any(|(k, _)| k == key) {
+                continue;
+            }
+            let number = definitions.info.get(key).map(|d| d.fieldtype.number());
+            let value = combine(&sources, number, allele_count, 2, |record| {
+                record.info(key).map(|value| value.map(str::to_string))
+            });
+            info.push((key.clone(), value.flatten()));
+        }
+    }
+
+    let mut format: Vec<String> = Vec::new();
+    for record in records {
+        for key in &record.format {
+            if !format.contains(key) {
+                format.push(key.clone());
+            }
+        }
+    }
+    let samples = (0..first.samples.len())
+        .map(|sample| {
+            let genotype = join_genotypes(&sources, sample)?;
+            let ploidy = genotype.as_ref().map_or(2, Genotype::ploidy);
+            Ok(format
+                .iter()
+                .map(|key| match (key.as_str(), &genotype) {
+                    ("GT", Some(genotype)) => genotype.to_string(),
+                    _ => {
+                        let number = definitions.format.get(key).map(|d| d.fieldtype.number());
+                        combine(&sources, number, allele_count, ploidy, |record| {
+                            record
+                                .sample_value(sample, key)
+                                .map(|v| Some(v.to_string()))
+                        })
+                        .flatten()
+                        .unwrap_or_else(|| ".".to_string())
+                    }
+                })
+                .collect())
+        })
+        .collect::<Result<_, VCFError>>()?;
+
+    Ok(Record {
+        id,
+        alt,
+        filter,
+        info,
+        format,
+        samples,
+        ..first.clone()
+    })
+}
+
+struct Source<'r> {
+    record: &'r Record,
+    map: &'r [usize],
+}
+
+/// Combine the values of a field across records. `get` returns `None` when the record lacks
+/// the field, and `Some(None)` for a flag.
+fn combine(
+    sources: &[Source],
+    number: Option<NumberField>,
+    allele_count: usize,
+    ploidy: usize,
+    get: impl Fn(&Record) -> Option<Option<String>>,
+) -> Option<Option<String>> {
+    let values: Vec<Option<Vec<String>>> = sources
+        .iter()
+        .map(|source| match get(source.record) {
+            Some(Some(value)) if value != "." => {
+                Some(value.split(',').map(str::to_string).collect())
+            }
+            _ => None,
+        })
+        .collect();
+    // The value for a combination of joined alleles, from the first record that has them all.
+    let value_for = |alleles: &[usize], index: &dyn Fn(&[usize]) -> usize| {
+        sources.iter().zip(&values).find_map(|(source, values)| {
+            let values = values.as_ref()?;
+            let mut local: Vec<usize> = alleles
+                .iter()
+                .map(|a| source.map.iter().position(|m| m == a))
+                .collect::<Option<_>>()?;
+            local.sort_unstable();
+            values.get(index(&local)).cloned()
+        })
+    };
+    let missing = || ".".to_string();
+    let combined: Vec<String> = match number {
+        _ if values.iter().all(Option::is_none) => {
+            return sources.iter().find_map(|source| get(source.record));
+        }
+        Some(NumberField::A) => (1..allele_count)
+            .map(|a| value_for(&[a], &|local: &[usize]| local[0] - 1).unwrap_or_else(missing))
+            .collect(),
+        Some(NumberField::R) => (0..allele_count)
+            .map(|a| value_for(&[a], &|local: &[usize]| local[0]).unwrap_or_else(missing))
+            .collect(),
+        Some(NumberField::G) => genotypes(allele_count, ploidy)
+            .iter()
+            .map(|alleles| value_for(alleles, &genotype_index).unwrap_or_else(missing))
+            .collect(),
+        _ => return sources.iter().find_map(|source| get(source.record)),
+    };
+    Some(Some(combined.join(",")))
+}
+
+/// All genotypes of the given ploidy, in Number=G order.
+fn genotypes(allele_count: usize, ploidy: usize) -> Vec<Vec<usize>> {
+    let mut all = vec![Vec::new()];
+    for _ in 0..ploidy {
+        all = all
+            .into_iter()
+            .flat_map(|alleles: Vec<usize>| {
+                let start = alleles.last().copied().unwrap_or(0);
+                (start..allele_count).map(move |a| {
+                    let mut next = alleles.clone();
+                    next.push(a);
+                    next
+                })
+            })
+            .collect();
+    }
+    all.sort_by_key(|alleles| genotype_index(alleles));
+    all
+}
+
+/// Rearrange the values of a field for a record with a different set of alleles.
+///
+/// `local(allele)` gives the index in the original record of each allele of the new record,
+/// or `None` if the original record has no value for it. Values that cannot be mapped are
+/// missing. Fields whose `Number` is not A, R or G are returned unchanged.
+pub(crate) fn remap_values(
+    value: &str,
+    number: NumberField,
+    allele_count: usize,
+    ploidy: usize,
+    local: impl Fn(usize) -> Option<usize>,
+) -> String {
+    if value == "." {
+        return value.to_string();
+    }
+    let values: Vec<&str> = value.split(',').collect();
+    let get = |index: Option<usize>| index.and_then(|i| values.get(i).copied()).unwrap_or(".");
+    let remapped: Vec<&str> = match number {
+        NumberField::A => (1..allele_count)
+            .map(|a| get(local(a).filter(|&l| l > 0).map(|l| l - 1)))
+            .collect(),
+        NumberField::R => (0..allele_count).map(|a| get(local(a))).collect(),
+        NumberField::G => genotypes(allele_count, ploidy)
+            .iter()
+            .map(|alleles| {
+                let local: Option<Vec<usize>> = alleles.iter().map(|&a| local(a)).collect();
+                get(local.map(|mut local| {
+                    local.sort_unstable();
+                    genotype_index(&local)
+                }))
+            })
+            .collect(),
+        _ => return value.to_string(),
+    };
+    remapped.join(",")
+}
+
+/// Where the alleles of a record go in a record merged from several.
+pub(crate) struct AlleleMap {
+    /// The merged index of each of the record's alleles.
+    pub(crate) forward: Vec<usize>,
+    /// The record's `<NON_REF>` or `<*>` allele, if it has one.
+    pub(crate) unspecified: Option<usize>,
+}
+
+impl AlleleMap {
+    /// For each merged allele, the record's allele, falling back to its unspecified allele
+    /// for ALT alleles it does not have.
+    pub(crate) fn local(&self) -> impl Fn(usize) -> Option<usize> + '_ {
+        move |allele| {
+            self.forward
+                .iter()
+                .position(|&a| a == allele)
+                .or(self.unspecified.filter(|_| allele > 0))
+        }
+    }
+
+    pub(crate) fn remap_genotype(&self, mut genotype: Genotype) -> Genotype {
+        for allele in genotype.alleles.iter_mut().flatten() {
+            *allele = self.forward.get(*allele).copied().unwrap_or(0);
+        }
+        genotype
+    }
+}
+
+/// The alleles of records at the same position, merged.
+pub(crate) struct MergedAlleles {
+    pub(crate) reference: String,
+    pub(crate) alt: Vec<String>,
+    pub(crate) maps: Vec<AlleleMap>,
+}
+
+impl MergedAlleles {
+    /// REF is the longest REF of the records, and the ALT alleles of records with a shorter REF
+    /// are padded with its extra bases. A `<NON_REF>` or `<*>` allele is kept last.
+    pub(crate) fn new(records: &[&Record]) -> Self {
+        let reference = records
+            .iter()
+            .map(|record| &record.reference)
+            .max_by_key(|reference| reference.len())
+            .cloned()
+            .unwrap_or_default();
+        let padded = |record: &Record, allele: &str| match allele.parse::<Allele>() {
+            Ok(parsed) if parsed.is_unspecified() => None,
+            Ok(Allele::Bases(bases)) => {
+                let suffix = reference.get(record.reference.len()..).unwrap_or_default();
+                Some(format!("{}{}", bases, suffix))
+            }
+            _ => Some(allele.to_string()),
+        };
+
+        let mut alt: Vec<String> = Vec::new();
+        let mut unspecified: Option<String> = None;
+        for record in records {
+            for allele in &record.alt {
+                match padded(record, allele) {
+                    Some(padded) if !alt.contains(&padded) => alt.push(padded),
+                    Some(_) => {}
+                    None => {
+                        unspecified.get_or_insert_with(|| allele.clone());
+                    }
+                }
+            }
+        }
+        alt.extend(unspecified.clone());
+
+        let maps = records
+            .iter()
+            .map(|record| {
+                let merged_index = |allele: Option<String>| {
+                    let allele = allele.or_else(|| unspecified.clone());
+                    alt.iter()
+                        .position(|a| Some(a) == allele.as_ref())
+                        .map_or(0, |i| i + 1)
+                };
+                let forward = std::iter::once(0)
+                    .chain(record.alt.iter().map(|a| merged_index(padded(record, a))))
+                    .collect();
+                let unspecified = record
+                    .alt
+                    .iter()
+                    .position(|a| padded(record, a).is_none())
+                    .map(|i| i + 1);
+                AlleleMap {
+                    forward,
+                    unspecified,
+                }
+            })
+            .collect();
+        Self {
+            reference,
+            alt,
+            maps,
+        }
+    }
+
+    pub(crate) fn allele_count(&self) -> usize {
+        self.alt.len() + 1
+    }
+}
+
+/// Add the INFO values of `record` to `info`, remapping Number=A/R/G values.
+///
+/// Values already in `info` are kept, except that missing entries of a Number=A/R/G value are
+/// filled in from `record`.
+pub(crate) fn merge_info(
+    info: &mut Vec<(String, Option<String>)>,
+    record: &Record,
+    map: &AlleleMap,
+    allele_count: usize,
+    definitions: &Definitions,
+) {
+    for (key, value) in &record.info {
+        let number = definitions.info.get(key).map(|d| d.fieldtype.number());
+        let value = match (value, number) {
+            (Some(value), Some(number)) => {
+                Some(remap_values(value, number, allele_count, 2, map.local()))
+            }
+            (value, _) => value.clone(),
+        };
+        let per_allele = matches!(
+            number,
+            Some(NumberField::A | NumberField::R | NumberField::G)
+        );
+        match info.iter_mut().find(|(k, _)| k == key) {
+            Some((_, Some(existing))) if per_allele => {
+                if let Some(value) = value {
+                    *existing = fill_missing(existing, &value);
+                }
+            }
+            Some(_) => {}
+            None => info.push((key.clone(), value)),
+        }
+    }
+}
+
+/// Replace the missing entries of a list of values with those of another list.
+fn fill_missing(values: &str, other: &str) -> String {
+    if values == "." {
+        return other.to_string();
+    }
+    let mut other = other.split(',');
+    values
+        .split(',')
+        .map(|value| match (value, other.next()) {
+            (".", Some(other)) => other,
+            (value, _) => value,
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The FORMAT values of a sample in the order of `keys`, with alleles renumbered.
+pub(crate) fn remap_sample(
+    record: &Record,
+    sample: usize,
+    keys: &[String],
+    map: &AlleleMap,
+    allele_count: usize,
+    definitions: &Definitions,
+) -> Vec<String> {
+    let genotype = record
+        .sample_value(sample, "GT")
+        .and_then(|gt| gt.parse::<Genotype>().ok())
+        .map(|gt| map.remap_genotype(gt));
+    let ploidy = genotype.as_ref().map_or(2, Genotype::ploidy);
+    keys.iter()
+        .map(|key| match (key.as_str(), &genotype) {
+            ("GT", Some(genotype)) => genotype.to_string(),
+            _ => {
+                let value = record.sample_value(sample, key).unwrap_or(".");
+                match definitions.format.get(key) {
+                    Some(definition) => remap_values(
+                        value,
+                        definition.fieldtype.number(),
+                        allele_count,
+                        ploidy,
+                        map.local(),
+                    ),
+                    None => value.to_string(),
+                }
+            }
+        })
+        .collect()
+}
+
+/// The FORMAT keys of several records, with GT first.
+pub(crate) fn format_keys<'r>(records: impl IntoIterator<Item = &'r Record>) -> Vec<String> {
+    let mut keys = vec!["GT".to_string()];
+    for record in records {
+        for key in &record.format {
+            if !keys.contains(key) {
+                keys.push(key.clone());
+            }
+        }
+    }
+    keys
+}
+
+/// The values of a sample without data: a no-call genotype and missing values.
+pub(crate) fn no_call(keys: &[String]) -> Vec<String> {
+    keys.iter()
+        .map(|key| if key == "GT" { "./." } else { "." }.to_string())
+        .collect()
+}
+
+/// Combine a sample's genotypes: each allele comes from the first record that calls it as
+/// something other than the reference.
+fn join_genotypes(sources: &[Source], sample: usize) -> Result<Option<Genotype>, VCFError> {
+    let genotypes: Vec<(Genotype, &[usize], &Record)> = sources
+        .iter()
+        .filter_map(|source| {
+            let gt = source
+                .record
+                .sample_value(sample, "GT")?
+                .parse::<Genotype>()
+                .ok()?;
+            Some((gt, source.map, source.record))
+        })
+        .collect();
+    for (gt, map, record) in &genotypes {
+        if let Some(allele) = gt.alleles.iter().flatten().find(|&&a| a >= map.len()) {
+            return Err(VCFError::InvalidRecord(format!(
+                "genotype {} at {}:{} refers to allele {}, but the record has {} ALT alleles",
+                gt,
+                record.chrom,
+                record.pos,
+                allele,
+                map.len() - 1
+            )));
+        }
+    }
+    let Some((first, _, _)) = genotypes.first() else {
+        return Ok(None);
+    };
+    let mut joined = first.clone();
+    for (i, allele) in joined.alleles.iter_mut().enumerate() {
+        *allele = genotypes
+            .iter()
+            .filter_map(|(gt, map, _)| gt.alleles.get(i).copied().flatten().map(|a| map[a]))
+            .find(|&a| a != 0)
+            .or_else(|| allele.map(|_| 0));
+    }
+    Ok(Some(joined))
+}
+
+/// Join runs of adjacent records that share CHROM, POS and REF.
+pub fn join_adjacent<I>(records: I, definitions: &Definitions) -> JoinAdjacent<'_, I>
+where
+    I: Iterator<Item = Result<Record, VCFError>>,
+{
+    JoinAdjacent {
+        records,
+        definitions,
+        pending: None,
+    }
+}
+
+pub struct JoinAdjacent<'d, I> {
+    records: I,
+    definitions: &'d Definitions,
+    pending: Option<Record>,
+}
+
+impl<I> Iterator for JoinAdjacent<'_, I>
+where
+    I: Iterator<Item = Result<Record, VCFError>>,
+{
+    type Item = Result<Record, VCFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut group = vec![match self.pending.take() {
+            Some(record) => record,
+            None => match self.records.next()? {
+                Ok(record) => record,
+                Err(error) => return Some(Err(error)),
+            },
+        }];
+        for record in self.records.by_ref() {
+            let record = match record {
+                Ok(record) => record,
+                Err(error) => return Some(Err(error)),
+            };
+            let first = &group[0];
+            if record.chrom == first.chrom
+                && record.pos == first.pos
+                && record.reference == first.reference
+            {
+                group.push(record);
+            } else {
+                self.pending = Some(record);
+                break;
+            }
+        }
+        if group.len() == 1 {
+            return group.pop().map(Ok);
+        }
+        Some(join(&group, self.definitions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::Reader;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=AC,Number=A,Type=Integer,Description=\"Allele count\">
+##INFO=<ID=AD,Number=R,Type=Integer,Description=\"Allele depth\">
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=PL,Number=G,Type=Integer,Description=\"Likelihoods\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2
+1\t100\trs1\tA\tG,T\t50\tPASS\tAC=1,2;AD=10,5,6;DP=21\tGT:PL\t1/2:90,80,70,60,50,40\t2|2:0,1,2,3,4,5
+";
+
+    fn read() -> (Definitions, Record) {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        let record = reader.read_record().unwrap().unwrap();
+        (reader.header().definitions(), record)
+    }
+
+    #[test]
+    fn splits_by_number() {
+        let (definitions, record) = read();
+        let split = split(&record, &definitions).unwrap();
+        assert_eq!(split.len(), 2);
+        assert_eq!(
+            split[0].to_string(),
+            "1\t100\trs1\tA\tG\t50\tPASS\tAC=1;AD=10,5;DP=21\tGT:PL\t1/0:90,80,70\t0|0:0,1,2"
+        );
+        assert_eq!(
+            split[1].to_string(),
+            "1\t100\trs1\tA\tT\t50\tPASS\tAC=2;AD=10,6;DP=21\tGT:PL\t0/1:90,60,40\t1|1:0,3,5"
+        );
+    }
+
+    #[test]
+    fn join_reverses_split() {
+        let (definitions, record) = read();
+        let split = split(&record, &definitions).unwrap();
+        let joined = join(&split, &definitions).unwrap();
+        assert_eq!(
+            joined.to_string(),
+            "1\t100\trs1\tA\tG,T\t50\tPASS\tAC=1,2;AD=10,5,6;DP=21\tGT:PL\t1/2:90,80,70,60,.,40\t2|2:0,1,2,3,.,5"
+        );
+    }
+
+    #[test]
+    fn split_rejects_wrong_value_counts() {
+        let (definitions, mut record) = read();
+        record.set_info("AC", Some("1".to_string()));
+        assert!(split(&record, &definitions).is_err());
+    }
+
+    #[test]
+    fn join_adjacent_groups_matching_records() {
+        let (definitions, record) = read();
+        let mut records = split(&record, &definitions).unwrap();
+        records.push(Record {
+            pos: 200,
+            ..record.clone()
+        });
+        let joined = join_adjacent(records.into_iter().map(Ok), &definitions)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(joined.len(), 2);
+        assert_eq!(joined[0].alt, vec!["G", "T"]);
+        assert_eq!(joined[1].pos, 200);
+    }
+
+    #[test]
+    fn remaps_values_to_new_alleles() {
+        // The original record has alleles A,T; the new one A,G,T.
+        let local = |allele: usize| match allele {
+            0 => Some(0),
+            2 => Some(1),
+            _ => None,
+        };
+        assert_eq!(remap_values("5", NumberField::A, 3, 2, local), ".,5");
+        assert_eq!(remap_values("9,5", NumberField::R, 3, 2, local), "9,.,5");
+        assert_eq!(
+            remap_values("0,10,20", NumberField::G, 3, 2, local),
+            "0,.,.,10,.,20"
+        );
+        assert_eq!(remap_values("7", NumberField::Number(1), 3, 2, local), "7");
+    }
+
+    #[test]
+    fn join_rejects_genotypes_of_unknown_alleles() {
+        let (definitions, record) = read();
+        let mut split = split(&record, &definitions).unwrap();
+        split[0].samples[0][0] = "0/3".to_string();
+        assert!(join(&split, &definitions).is_err());
+    }
+
+    #[test]
+    fn join_rejects_different_positions() {
+        let (definitions, record) = read();
+        let other = Record {
+            pos: 101,
+            ..record.clone()
+        };
+        assert!(join(&[record, other], &definitions).is_err());
+    }
+}
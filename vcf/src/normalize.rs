@@ -0,0 +1,181 @@
+use crate::fasta::ReferenceSequence;
+use crate::vcf::VCFError;
+use crate::Record;
+
+fn is_sequence(allele: &str) -> bool {
+    !allele.is_empty()
+        && allele
+            .bytes()
+            .all(|b| matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N'))
+}
+
+/// Normalize a record against the reference sequence.
+///
+/// REF is checked against the reference, bases shared by all alleles are trimmed from the end
+/// and the start, and indels are shifted left through repeats, updating POS, REF and ALT (and
+/// INFO END, if present). Records with symbolic, breakend or `*` alleles, or without ALT, are
+/// left alone.
+///
+/// Returns whether the record changed.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use vcf::Record;
+/// use vcf::normalize::normalize;
+///# use vcf::vcf::VCFError;
+/// let mut reference = HashMap::from([("1".to_string(), "GCACACAT".to_string())]);
+/// let mut record = Record::parse("1\t4\t.\tCAC\tC\t.\t.\t.")?;
+/// assert!(normalize(&mut record, &mut reference)?);
+/// assert_eq!((record.pos, record.reference.as_str(), record.alt[0].as_str()), (1, "GCA", "G"));
+///# Ok::<(), VCFError>(())
+/// ```
+pub fn normalize(
+    record: &mut Record,
+    reference: &mut impl ReferenceSequence,
+) -> Result<bool, VCFError> {
+    if record.alt.is_empty()
+        || !is_sequence(&record.reference)
+        || !record.alt.iter().all(|alt| is_sequence(alt))
+    {
+        return Ok(false);
+    }
+    let end = record.pos + record.reference.len() as u64 - 1;
+    let expected = reference.fetch(&record.chrom, record.pos, end)?;
+    if !expected.eq_ignore_ascii_case(&record.reference) {
+        return Err(VCFError::ReferenceError(format!(
+            "REF {} does not match the reference {} at {}:{}",
+            record.reference, expected, record.chrom, record.pos
+        )));
+    }
+
+    let mut alleles: Vec<Vec<u8>> = std::iter::once(&record.reference)
+        .chain(&record.alt)
+        .map(|allele| allele.to_ascii_uppercase().into_bytes())
+        .collect();
+    if alleles[1..].iter().any(|alt| *alt == alleles[0]) {
+        return Ok(false);
+    }
+    let mut pos = record.pos;
+    loop {
+        let mut changed = false;
+        let last = alleles[0].last().copied();
+        if alleles
+            .iter()
+            .all(|a| a.last().is_some() && a.last().copied() == last)
+            && (pos > 1 || alleles.iter().all(|a| a.len() > 1))
+        {
+            alleles.iter_mut().for_each(|a| {
+                a.pop();
+            });
+            changed = true;
+        }
+        if alleles.iter().any(|a| a.is_empty()) {
+            pos -= 1;
+            let base = reference.fetch(&record.chrom, pos, pos)?.into_bytes()[0];
+            alleles.iter_mut().for_each(|a| a.insert(0, base));
+            changed = true;
+        }
+        if !changed {
+            break;
+        }
+    }
+    while alleles
+        .iter()
+        .all(|a| a.len() >= 2 && a[0] == alleles[0][0])
+    {
+        alleles.iter_mut().for_each(|a| {
+            a.remove(0);
+        });
+        pos += 1;
+    }
+
+    let mut alleles = alleles
+        .into_iter()
+        .map(|a| String::from_utf8(a).expect("alleles are ASCII"));
+    let new_reference = alleles.next().expect("REF is always present");
+    let new_alt: Vec<String> = alleles.collect();
+    let changed = pos != record.pos
+        || !new_reference.eq_ignore_ascii_case(&record.reference)
+        || new_alt
+            .iter()
+            .zip(&record.alt)
+            .any(|(new, old)| !new.eq_ignore_ascii_case(old));
+    if changed {
+        record.pos = pos;
+        record.reference = new_reference;
+        record.alt = new_alt;
+        if record.info("END").is_some() {
+            let end = record.pos + record.reference.len() as u64 - 1;
+            record.set_info("END", Some(end.to_string()));
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const SEQUENCE: &str = "TTACACACAGGT";
+
+    fn normalized(line: &str) -> (bool, Record) {
+        let mut reference = HashMap::from([("1".to_string(), SEQUENCE.to_string())]);
+        let mut record = Record::parse(line).unwrap();
+        let changed = normalize(&mut record, &mut reference).unwrap();
+        (changed, record)
+    }
+
+    #[test]
+    fn left_aligns_deletion_in_repeat() {
+        let (changed, record) = normalized("1\t7\t.\tACA\tA\t.\t.\t.");
+        assert!(changed);
+        assert_eq!(record.pos, 2);
+        assert_eq!(record.reference, "TAC");
+        assert_eq!(record.alt, vec!["T"]);
+    }
+
+    #[test]
+    fn left_aligns_insertion_in_repeat() {
+        let (changed, record) = normalized("1\t9\t.\tA\tACA\t.\t.\t.");
+        assert!(changed);
+        assert_eq!((record.pos, record.reference.as_str()), (2, "T"));
+        assert_eq!(record.alt, vec!["TAC"]);
+    }
+
+    #[test]
+    fn trims_shared_prefix_and_suffix() {
+        let (changed, record) = normalized("1\t9\t.\tAGGT\tACGT\t.\t.\t.");
+        assert!(changed);
+        assert_eq!((record.pos, record.reference.as_str()), (10, "G"));
+        assert_eq!(record.alt, vec!["C"]);
+    }
+
+    #[test]
+    fn leaves_normalized_records_unchanged() {
+        let (changed, record) = normalized("1\t2\t.\tTAC\tT,TACAC\t.\t.\t.");
+        assert!(!changed);
+        assert_eq!(record.to_string(), "1\t2\t.\tTAC\tT,TACAC\t.\t.\t.");
+    }
+
+    #[test]
+    fn cannot_extend_past_start_of_contig() {
+        let (changed, record) = normalized("1\t1\t.\tTT\tT\t.\t.\t.");
+        assert!(!changed);
+        assert_eq!(record.pos, 1);
+    }
+
+    #[test]
+    fn skips_symbolic_alleles() {
+        let (changed, _) = normalized("1\t3\t.\tA\t<DEL>\t.\t.\tEND=5");
+        assert!(!changed);
+    }
+
+    #[test]
+    fn reference_mismatch_is_an_error() {
+        let mut reference = HashMap::from([("1".to_string(), SEQUENCE.to_string())]);
+        let mut record = Record::parse("1\t1\t.\tG\tC\t.\t.\t.").unwrap();
+        assert!(normalize(&mut record, &mut reference).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Parsing records on several threads.
+//!
+//! [`ParallelReader`] reads the data lines in large chunks, split at line ends, and parses
+//! the chunks on a pool of worker threads. Records come out in input order, exactly as
+//! [`Reader`] would return them. BGZF input can be decompressed in parallel too, with
+//! [`bgzf::ParallelReader`](crate::bgzf::ParallelReader).
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::vcf::{Reader, VCFError, VCF};
+use crate::Record;
+
+/// The amount of text parsed by a worker at a time.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// The results of applying a function to a sequence of inputs on worker threads, in input
+/// order.
+pub(crate) struct Ordered<U> {
+    results: Receiver<(usize, U)>,
+    pending: BTreeMap<usize, U>,
+    next: usize,
+}
+
+/// Apply `f` to the items of `inputs` on `threads` worker threads, reading the inputs on a
+/// thread of their own.
+///
+/// The threads stop once the inputs run out, or after the results are dropped.
+pub(crate) fn map_ordered<I, F, U>(inputs: I, threads: usize, f: F) -> Ordered<U>
+where
+    I: Iterator + Send + 'static,
+    I::Item: Send + 'static,
+    F: Fn(I::Item) -> U + Send + Sync + 'static,
+    U: Send + 'static,
+{
+    let threads = threads.max(1);
+    let (work_sender, work) = sync_channel(threads * 2);
+    let (result_sender, results) = sync_channel(threads * 2);
+    thread::spawn(move || {
+        for job in inputs.enumerate() {
+            if work_sender.send(job).is_err() {
+                break;
+            }
+        }
+    });
+    let work = Arc::new(Mutex::new(work));
+    let f = Arc::new(f);
+    for _ in 0..threads {
+        let work = Arc::clone(&work);
+        let results = result_sender.clone();
+        let f = Arc::clone(&f);
+        thread::spawn(move || loop {
+            let job = work.lock().map(|work| work.recv());
+            let Ok(Ok((index, item))) = job else {
+                break;
+            };
+            if results.send((index, f(item))).is_err() {
+                break;
+            }
+        });
+    }
+    Ordered {
+        results,
+        pending: BTreeMap::new(),
+        next: 0,
+    }
+}
+
+impl<U> Iterator for Ordered<U> {
+    type Item = U;
+
+    fn next(&mut self) -> Option<U> {
+        loop {
+            if let Some(result) = self.pending.remove(&self.next) {
+                self.next += 1;
+                return Some(result);
+            }
+            let (index, result) = self.results.recv().ok()?;
+            self.pending.insert(index, result);
+        }
+    }
+}
+
+/// Reads text in chunks of about `CHUNK_SIZE` bytes that end at the end of a line, stopping
+/// after an error.
+struct Chunks<R> {
+    inner: R,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for Chunks<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE + 1024);
+        let read = (&mut self.inner)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)
+            .and_then(|_| match chunk.last() {
+                Some(b'\n') | None => Ok(0),
+                Some(_) => self.inner.read_until(b'\n', &mut chunk),
+            })
+            .and_then(|_| {
+                String::from_utf8(chunk)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+            });
+        match read {
+            Ok(chunk) if chunk.is_empty() => None,
+            Ok(chunk) => Some(Ok(chunk)),
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Parse the lines of a chunk as [`Reader::read_record`] does.
+fn parse_chunk(
+    chunk: io::Result<String>,
+    samples: Option<&[usize]>,
+) -> Vec<Result<Record, VCFError>> {
+    match chunk {
+        Ok(chunk) => chunk
+            .split('\n')
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let line = line.trim_end_matches(['\n', '\r']);
+                Ok(Record::parse_samples(line, samples)?)
+            })
+            .collect(),
+        Err(error) => vec![Err(error.into())],
+    }
+}
+
+/// Parses the records of a VCF on a pool of threads.
+///
+/// Like [`Records`](crate::vcf::Records), the iterator goes on after a record that cannot be
+/// parsed, but it ends after an error reading the input.
+///
+/// ```
+/// use vcf::parallel::ParallelReader;
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let mut source = String::from("##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n");
+/// for pos in 1..=1000 {
+///     source.push_str(&format!("1\t{}\t.\tA\tG\t.\t.\t.\n", pos));
+/// }
+/// let reader = ParallelReader::new(Reader::new(std::io::Cursor::new(source))?, 4);
+/// let positions = reader.map(|record| record.map(|r| r.pos)).collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(positions, (1..=1000).collect::<Vec<_>>());
+///# Ok::<(), VCFError>(())
+/// ```
+pub struct ParallelReader {
+    header: VCF,
+    chunks: Ordered<Vec<Result<Record, VCFError>>>,
+    current: std::vec::IntoIter<Result<Record, VCFError>>,
+}
+
+impl ParallelReader {
+    /// Parse the records left in `reader` on `threads` threads. Samples selected in the reader
+    /// stay selected.
+    pub fn new<R: BufRead + Send + 'static>(reader: Reader<R>, threads: usize) -> Self {
+        let (header, inner, samples) = reader.into_parts();
+        let chunks = map_ordered(Chunks { inner, done: false }, threads, move |chunk| {
+            parse_chunk(chunk, samples.as_deref())
+        });
+        Self {
+            header,
+            chunks,
+            current: Vec::new().into_iter(),
+        }
+    }
+
+    pub fn header(&self) -> &VCF {
+        &self.header
+    }
+}
+
+impl Iterator for ParallelReader {
+    type Item = Result<Record, VCFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.current.next() {
+                return Some(record);
+            }
+            self.current = self.chunks.next()?.into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::SampleSelection;
+    use std::io::Cursor;
+
+    fn source(records: usize) -> String {
+        let mut source = String::from(
+            "##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\n",
+        );
+        for i in 0..records {
+            // Long INFO values make records span chunk boundaries; blank and CRLF lines are
+            // skipped like the sequential reader does.
+            source.push_str(&format!(
+                "{}\t{}\tid{}\tA\tG\t{}\t.\tX={}\tGT\t0/1\t1/1\r\n",
+                i % 3,
+                i,
+                i,
+                i % 50,
+                "x".repeat(i % 700)
+            ));
+            if i % 1000 == 0 {
+                source.push('\n');
+            }
+        }
+        source
+    }
+
+    #[test]
+    fn returns_the_records_of_the_sequential_reader() {
+        let source = source(10_000);
+        assert!(source.len() > 3 * CHUNK_SIZE);
+        let sequential: Vec<Record> = Reader::new(source.as_bytes())
+            .unwrap()
+            .records()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        for threads in [1, 3, 8] {
+            let parallel: Vec<Record> =
+                ParallelReader::new(Reader::new(Cursor::new(source.clone())).unwrap(), threads)
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+            assert_eq!(parallel, sequential);
+        }
+    }
+
+    #[test]
+    fn keeps_the_sample_selection() {
+        let mut reader = Reader::new(Cursor::new(source(10))).unwrap();
+        reader
+            .select_samples(&SampleSelection::parse_list("B"))
+            .unwrap();
+        let parallel = ParallelReader::new(reader, 2);
+        assert_eq!(parallel.header().samples, vec!["B"]);
+        for record in parallel {
+            assert_eq!(record.unwrap().samples, vec![vec!["1/1"]]);
+        }
+    }
+
+    #[test]
+    fn reports_errors_where_they_occur() {
+        let mut source = source(5000);
+        source.push_str("1\tnot-a-position\t.\tA\tG\t.\t.\t.\n");
+        source.push_str("1\t1\t.\tA\tG\t.\t.\t.\n");
+        let sequential: Vec<bool> = Reader::new(source.as_bytes())
+            .unwrap()
+            .records()
+            .map(|record| record.is_ok())
+            .collect();
+        let parallel: Vec<bool> = ParallelReader::new(Reader::new(Cursor::new(source)).unwrap(), 4)
+            .map(|record| record.is_ok())
+            .collect();
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel.iter().filter(|ok| !**ok).count(), 1);
+    }
+}
@@ -3,13 +3,14 @@ use std::collections::HashMap;
 use regex::Regex;
 use lazy_static::lazy_static;
 
-use crate::{Header, HeaderValue};
+use crate::{Header, HeaderValue, Record};
 
 lazy_static! {
     // Repeatedly match either non-comma/non-quote characters or blocks of text enclosed in
-    // quotes, until we can't, in which case we're either at a non-quote-enclosed comma or the
-    // end of the string.
-    static ref HEADER_VALUE_REGEX: Regex = Regex::new(r#"(?:[^,"]+|(?:"[^"]*"))+"#).unwrap();
+    // quotes, in which quotes may be escaped with a backslash, until we can't, in which case
+    // we're either at a non-quote-enclosed comma or the end of the string.
+    static ref HEADER_VALUE_REGEX: Regex =
+        Regex::new(r#"(?:[^,"]+|(?:"(?:[^"\\]|\\.)*"))+"#).unwrap();
 }
 
 impl<'src> Header<'src> {
@@ -31,12 +32,7 @@ impl<'src> HeaderValue<'src> {
                 HEADER_VALUE_REGEX.captures_iter(pairs)
                     .map(|c| c.get(0).unwrap().as_str())
                     .map(|pair| pair.split_once('=').ok_or(ParseError))
-                    .map(
-                        |r| match r {
-                            Ok((k, v)) => Ok((k, v.trim_matches('\"'))),
-                            x => x,
-                        }
-                    )
+                    .map(|r| r.map(|(k, v)| (k, unquote(v))))
                     .collect::<Result<HashMap<_, _>, _>>()
                     .map(HeaderValue::Nested)
             }
@@ -44,5 +40,84 @@ impl<'src> HeaderValue<'src> {
     }
 }
 
+/// `line`, a structured meta-information line, with its `ID` replaced by `id` and its other
+/// fields written as they were, or `None` if it is not a structured line.
+pub(crate) fn replace_id(line: &str, id: &str) -> Option<String> {
+    let (key, value) = line.trim().strip_prefix("##")?.split_once('=')?;
+    let pairs = value.strip_prefix('<')?.strip_suffix('>')?;
+    let fields: Vec<String> = HEADER_VALUE_REGEX.find_iter(pairs)
+        .map(|pair| match pair.as_str().split_once('=') {
+            Some(("ID", _)) => format!("ID={}", id),
+            _ => pair.as_str().to_string(),
+        })
+        .collect();
+    Some(format!("##{}=<{}>", key, fields.join(",")))
+}
+
+/// Strip the quotes around a header value, leaving the escapes inside them.
+fn unquote(value: &str) -> &str {
+    value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value)
+}
+
+impl Record {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        Self::parse_samples(input, None)
+    }
+
+    /// Parse a data line, keeping only the sample columns at `samples`, in that order. The
+    /// other sample columns are not split into fields.
+    pub(crate) fn parse_samples(input: &str, samples: Option<&[usize]>) -> Result<Self, ParseError> {
+        let line = input.trim_end_matches(['\n', '\r']);
+        let mut columns = line.split('\t');
+        let mut next = || columns.next().ok_or(ParseError);
+        let chrom = next()?.to_string();
+        let pos = next()?.parse().map_err(|_| ParseError)?;
+        let id = parse_list(next()?, ';');
+        let reference = next()?.to_string();
+        let alt = parse_list(next()?, ',');
+        let (qual, qual_text) = match next()? {
+            "." => (None, None),
+            qual => (Some(qual.parse().map_err(|_| ParseError)?), Some(qual.to_string())),
+        };
+        let filter = parse_list(next()?, ';');
+        let info = match next()? {
+            "." => Vec::new(),
+            info => info
+                .split(';')
+                .map(|entry| match entry.split_once('=') {
+                    Some((k, v)) => (k.to_string(), Some(v.to_string())),
+                    None => (entry.to_string(), None),
+                })
+                .collect(),
+        };
+        let format = match columns.next() {
+            Some(format) => format.split(':').map(str::to_string).collect(),
+            None => Vec::new(),
+        };
+        let decode = |sample: &str| sample.split(':').map(str::to_string).collect();
+        let samples = match samples {
+            None => columns.map(decode).collect(),
+            Some(indices) => {
+                let columns: Vec<&str> = columns.collect();
+                indices
+                    .iter()
+                    .map(|&i| columns.get(i).map(|sample| decode(sample)).ok_or(ParseError))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        if chrom.is_empty() || reference.is_empty() {
+            return Err(ParseError);
+        }
+        Ok(Self { chrom, pos, id, reference, alt, qual, qual_text, filter, info, format, samples })
+    }
+}
+
+fn parse_list(input: &str, separator: char) -> Vec<String> {
+    match input {
+        "." => Vec::new(),
+        list => list.split(separator).map(str::to_string).collect(),
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ParseError;
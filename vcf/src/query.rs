@@ -0,0 +1,403 @@
+//! Extracting fields from records as text, driven by a format string such as
+//! `%CHROM\t%POS\t%REF\t%ALT\t%INFO/AF[\t%SAMPLE=%GT]\n`.
+//!
+//! - `%CHROM`, `%POS`, `%ID`, `%REF`, `%ALT`, `%QUAL` and `%FILTER` are the fixed columns, and
+//!   `%INFO` the whole INFO column.
+//! - `%INFO/KEY`, or `%KEY` for short, is the value of an INFO field. Flags are written as `1`
+//!   when present and `0` when absent.
+//! - The part of the format between `[` and `]` is repeated for each sample. In it, `%SAMPLE`
+//!   is the sample name and `%FORMAT/KEY`, or `%KEY` for short, the sample's value of a FORMAT
+//!   field.
+//! - Missing values are written as `.`.
+//! - `\t`, `\n` and `\\` are a tab, a newline and a backslash, and `\%`, `\[` and `\]` are
+//!   the characters themselves.
+use std::io::{BufRead, Write};
+
+use crate::vcf::{Reader, VCFError, VCF};
+use crate::{DataType, Definitions, Record};
+
+/// A parsed format string.
+///
+/// ```
+/// use vcf::query::Query;
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let source = b"##fileformat=VCFv4.3\
+///     \n##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">\
+///     \n##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\
+///     \n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\
+///     \n1\t100\t.\tA\tG,T\t50\tPASS\tAF=0.25,0.5\tGT\t0/1\t./.\n";
+/// let mut reader = Reader::new(&source[..])?;
+/// let query = Query::parse(r"%CHROM\t%POS\t%ALT\t%INFO/AF[\t%SAMPLE=%GT]\n", reader.header())?;
+/// assert_eq!(query.header_line(), "#CHROM\tPOS\tALT\tINFO/AF\tA:SAMPLE=A:GT\tB:SAMPLE=B:GT\n");
+///
+/// let record = reader.read_record()?.unwrap();
+/// let mut output = Vec::new();
+/// query.write(&record, &mut output)?;
+/// assert_eq!(output, b"1\t100\tG,T\t0.25,0.5\tA=0/1\tB=./.\n");
+///# Ok::<(), VCFError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    items: Vec<Item>,
+    samples: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Text(String),
+    Field(Field),
+    PerSample(Vec<Item>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Chrom,
+    Pos,
+    Id,
+    Ref,
+    Alt,
+    Qual,
+    Filter,
+    Info,
+    InfoValue { key: String, flag: bool },
+    Sample,
+    Format(String),
+}
+
+impl Field {
+    fn parse(name: &str, per_sample: bool, definitions: &Definitions) -> Result<Self, VCFError> {
+        let field = match name {
+            "CHROM" => Field::Chrom,
+            "POS" => Field::Pos,
+            "ID" => Field::Id,
+            "REF" => Field::Ref,
+            "ALT" => Field::Alt,
+            "QUAL" => Field::Qual,
+            "FILTER" => Field::Filter,
+            "INFO" => Field::Info,
+            "SAMPLE" if per_sample => Field::Sample,
+            _ => {
+                let (prefix, key) = match name.split_once('/') {
+                    Some((prefix, key)) => (Some(prefix), key),
+                    None => (None, name),
+                };
+                match (prefix, per_sample) {
+                    (Some("INFO"), _) | (None, false) => {
+                        let definition = definitions.info.get(key).ok_or_else(|| {
+                            error(format!("INFO/{} is not defined in the header", key))
+                        })?;
+                        Field::InfoValue {
+                            key: key.to_string(),
+                            flag: matches!(definition.fieldtype, DataType::Flag),
+                        }
+                    }
+                    (Some("FORMAT"), true) | (None, true) => {
+                        if !definitions.format.contains_key(key) {
+                            return Err(error(format!(
+                                "FORMAT/{} is not defined in the header",
+                                key
+                            )));
+                        }
+                        Field::Format(key.to_string())
+                    }
+                    (Some("FORMAT"), false) => {
+                        return Err(error(format!("%{} can only be used between [ and ]", name)))
+                    }
+                    _ => return Err(error(format!("unknown field %{}", name))),
+                }
+            }
+        };
+        Ok(field)
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Field::Chrom => "CHROM".to_string(),
+            Field::Pos => "POS".to_string(),
+            Field::Id => "ID".to_string(),
+            Field::Ref => "REF".to_string(),
+            Field::Alt => "ALT".to_string(),
+            Field::Qual => "QUAL".to_string(),
+            Field::Filter => "FILTER".to_string(),
+            Field::Info => "INFO".to_string(),
+            Field::InfoValue { key, .. } => format!("INFO/{}", key),
+            Field::Sample => "SAMPLE".to_string(),
+            Field::Format(key) => key.clone(),
+        }
+    }
+
+    fn write(&self, record: &Record, output: &mut String) {
+        let missing_or = |values: &[String], separator: &str| match values.is_empty() {
+            true => ".".to_string(),
+            false => values.join(separator),
+        };
+        let value = match self {
+            Field::Chrom => record.chrom.clone(),
+            Field::Pos => record.pos.to_string(),
+            Field::Id => missing_or(&record.id, ";"),
+            Field::Ref => record.reference.clone(),
+            Field::Alt => missing_or(&record.alt, ","),
+            Field::Qual => match record.qual {
+                Some(qual) => qual.to_string(),
+                None => ".".to_string(),
+            },
+            Field::Filter => missing_or(&record.filter, ";"),
+            Field::Info => {
+                let fields: Vec<String> = record
+                    .info
+                    .iter()
+                    .map(|(key, value)| match value {
+                        Some(value) => format!("{}={}", key, value),
+                        None => key.clone(),
+                    })
+                    .collect();
+                missing_or(&fields, ";")
+            }
+            Field::InfoValue { key, flag: true } => match record.info(key) {
+                Some(_) => "1".to_string(),
+                None => "0".to_string(),
+            },
+            Field::InfoValue { key, flag: false } => {
+                record.info(key).flatten().unwrap_or(".").to_string()
+            }
+            Field::Sample | Field::Format(_) => {
+                unreachable!("sample fields are written per sample")
+            }
+        };
+        output.push_str(&value);
+    }
+}
+
+fn error(message: String) -> VCFError {
+    VCFError::InvalidExpression(message)
+}
+
+impl Query {
+    /// Parse a format string, checking its INFO and FORMAT fields against the header.
+    pub fn parse(format: &str, header: &VCF) -> Result<Self, VCFError> {
+        let definitions = header.definitions();
+        let mut items = Vec::new();
+        let mut sample_items = None;
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            let text = match c {
+                '\\' => match chars.next() {
+                    Some('t') => '\t',
+                    Some('n') => '\n',
+                    Some(c @ ('\\' | '%' | '[' | ']')) => c,
+                    Some(c) => return Err(error(format!("unknown escape \\{}", c))),
+                    None => return Err(error("the format ends with \\".to_string())),
+                },
+                '%' => {
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == '_' || c == '/' || c == '.') {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+                    if name.is_empty() {
+                        return Err(error("% must be followed by a field name".to_string()));
+                    }
+                    let field = Field::parse(&name, sample_items.is_some(), &definitions)?;
+                    sample_items
+                        .as_mut()
+                        .unwrap_or(&mut items)
+                        .push(Item::Field(field));
+                    continue;
+                }
+                '[' if sample_items.is_some() => {
+                    return Err(error("[ cannot be nested".to_string()))
+                }
+                '[' => {
+                    sample_items = Some(Vec::new());
+                    continue;
+                }
+                ']' => match sample_items.take() {
+                    Some(inner) => {
+                        items.push(Item::PerSample(inner));
+                        continue;
+                    }
+                    None => return Err(error("] without [".to_string())),
+                },
+                c => c,
+            };
+            let current = sample_items.as_mut().unwrap_or(&mut items);
+            match current.last_mut() {
+                Some(Item::Text(existing)) => existing.push(text),
+                _ => current.push(Item::Text(text.to_string())),
+            }
+        }
+        if sample_items.is_some() {
+            return Err(error("[ without ]".to_string()));
+        }
+        Ok(Self {
+            items,
+            samples: header.samples.clone(),
+        })
+    }
+
+    /// A line naming the fields the format writes, starting with `#`. Fields between `[` and
+    /// `]` are named after each sample, as in `A:GT`.
+    pub fn header_line(&self) -> String {
+        let mut line = "#".to_string();
+        for item in &self.items {
+            match item {
+                Item::Text(text) => line.push_str(text),
+                Item::Field(field) => line.push_str(&field.name()),
+                Item::PerSample(items) => {
+                    for sample in &self.samples {
+                        for item in items {
+                            match item {
+                                Item::Text(text) => line.push_str(text),
+                                Item::Field(field) => {
+                                    line.push_str(&format!("{}:{}", sample, field.name()))
+                                }
+                                Item::PerSample(_) => unreachable!("[ cannot be nested"),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if !line.ends_with('\n') {
+            line.push('\n');
+        }
+        line
+    }
+
+    /// Write the fields of a record as the format describes.
+    pub fn write(&self, record: &Record, output: &mut impl Write) -> Result<(), VCFError> {
+        let mut text = String::new();
+        for item in &self.items {
+            match item {
+                Item::Text(literal) => text.push_str(literal),
+                Item::Field(field) => field.write(record, &mut text),
+                Item::PerSample(items) => {
+                    for sample in 0..self.samples.len() {
+                        self.write_sample(items, record, sample, &mut text);
+                    }
+                }
+            }
+        }
+        output.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    fn write_sample(&self, items: &[Item], record: &Record, sample: usize, text: &mut String) {
+        for item in items {
+            match item {
+                Item::Text(literal) => text.push_str(literal),
+                Item::Field(Field::Sample) => text.push_str(&self.samples[sample]),
+                Item::Field(Field::Format(key)) => {
+                    text.push_str(record.sample_value(sample, key).unwrap_or("."))
+                }
+                Item::Field(field) => field.write(record, text),
+                Item::PerSample(_) => unreachable!("[ cannot be nested"),
+            }
+        }
+    }
+}
+
+/// Write the fields of every record of `reader` as `format` describes, optionally preceded by
+/// a line naming them.
+pub fn query<R: BufRead>(
+    mut reader: Reader<R>,
+    format: &str,
+    header_line: bool,
+    mut output: impl Write,
+) -> Result<(), VCFError> {
+    let query = Query::parse(format, reader.header())?;
+    if header_line {
+        output.write_all(query.header_line().as_bytes())?;
+    }
+    while let Some(record) = reader.read_record()? {
+        query.write(&record, &mut output)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype quality\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB
+1\t100\trs1\tA\tG\t50\tPASS\tDP=12;DB\tGT:GQ\t0/1:30\t1/1
+2\t200\t.\tC\t.\t.\t.\t.\tGT:GQ\t0/0:40\t./.:.
+";
+
+    fn query_lines(format: &str, header_line: bool) -> String {
+        let mut output = Vec::new();
+        query(
+            Reader::new(SOURCE.as_bytes()).unwrap(),
+            format,
+            header_line,
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_site_fields() {
+        assert_eq!(
+            query_lines(r"%CHROM:%POS %ID %REF>%ALT %QUAL %FILTER %INFO\n", false),
+            "1:100 rs1 A>G 50 PASS DP=12;DB\n2:200 . C>. . . .\n"
+        );
+        assert_eq!(
+            query_lines(r"%INFO/DP\t%DP\t%DB\n", true),
+            "#INFO/DP\tINFO/DP\tINFO/DB\n12\t12\t1\n.\t.\t0\n"
+        );
+    }
+
+    #[test]
+    fn repeats_brackets_per_sample() {
+        assert_eq!(
+            query_lines(r"%POS[\t%SAMPLE:%GT:%FORMAT/GQ:%CHROM]\n", true),
+            "#POS\tA:SAMPLE:A:GT:A:GQ:A:CHROM\tB:SAMPLE:B:GT:B:GQ:B:CHROM\n\
+             100\tA:0/1:30:1\tB:1/1:.:1\n\
+             200\tA:0/0:40:2\tB:./.:.:2\n"
+        );
+        assert_eq!(query_lines(r"[%GT\n]", false), "0/1\n1/1\n0/0\n./.\n");
+        assert_eq!(
+            query_lines(r"\[%POS\]\%\\\n", false),
+            "[100]%\\\n[200]%\\\n"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_formats() {
+        let header = Reader::new(SOURCE.as_bytes()).unwrap().into_header();
+        let message = |format: &str| Query::parse(format, &header).unwrap_err().to_string();
+        assert_eq!(
+            message("%AF"),
+            "invalid expression: INFO/AF is not defined in the header"
+        );
+        assert_eq!(
+            message("%FORMAT/GT"),
+            "invalid expression: %FORMAT/GT can only be used between [ and ]"
+        );
+        assert_eq!(
+            message("[%XX]"),
+            "invalid expression: FORMAT/XX is not defined in the header"
+        );
+        assert_eq!(message("[%GT"), "invalid expression: [ without ]");
+        assert_eq!(message("[[%GT]]"), "invalid expression: [ cannot be nested");
+        assert_eq!(
+            message("%SAMPLE"),
+            "invalid expression: INFO/SAMPLE is not defined in the header"
+        );
+        assert_eq!(
+            message("% "),
+            "invalid expression: % must be followed by a field name"
+        );
+    }
+}
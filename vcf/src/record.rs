@@ -0,0 +1,253 @@
+use std::fmt;
+
+use crate::parse::ParseError;
+use crate::{Allele, VariantType};
+
+/// A single data line of a VCF file.
+///
+/// Fields are kept close to their textual form: missing values (`.`) in the ID, ALT, FILTER and
+/// INFO columns are represented by empty vectors, and INFO and sample values are left as raw
+/// strings to be interpreted against the header definitions.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub chrom: String,
+    pub pos: u64,
+    pub id: Vec<String>,
+    pub reference: String,
+    pub alt: Vec<String>,
+    pub qual: Option<f64>,
+    /// QUAL as written in the file, such as `50.00`. It is written back in place of `qual`
+    /// while it still reads as the same number, and is not compared by `==`.
+    pub qual_text: Option<String>,
+    pub filter: Vec<String>,
+    pub info: Vec<(String, Option<String>)>,
+    pub format: Vec<String>,
+    pub samples: Vec<Vec<String>>,
+}
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        let Record {
+            chrom,
+            pos,
+            id,
+            reference,
+            alt,
+            qual,
+            qual_text: _,
+            filter,
+            info,
+            format,
+            samples,
+        } = self;
+        *chrom == other.chrom
+            && *pos == other.pos
+            && *id == other.id
+            && *reference == other.reference
+            && *alt == other.alt
+            && *qual == other.qual
+            && *filter == other.filter
+            && *info == other.info
+            && *format == other.format
+            && *samples == other.samples
+    }
+}
+
+impl Record {
+    /// Look up an INFO key. Flags are present with a `None` value.
+    pub fn info(&self, key: &str) -> Option<Option<&str>> {
+        self.info
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_deref())
+    }
+
+    /// Set an INFO key, replacing any existing value in place.
+    pub fn set_info(&mut self, key: &str, value: Option<String>) {
+        match self.info.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.info.push((key.to_string(), value)),
+        }
+    }
+
+    /// Remove an INFO key, returning its value if it was present.
+    pub fn remove_info(&mut self, key: &str) -> Option<Option<String>> {
+        let index = self.info.iter().position(|(k, _)| k == key)?;
+        Some(self.info.remove(index).1)
+    }
+
+    /// The ALT alleles, parsed.
+    pub fn alt_alleles(&self) -> Result<Vec<Allele>, ParseError> {
+        self.alt.iter().map(|alt| alt.parse()).collect()
+    }
+
+    /// The kind of each ALT allele.
+    pub fn allele_types(&self) -> Result<Vec<VariantType>, ParseError> {
+        let alleles = self.alt_alleles()?;
+        Ok(alleles
+            .iter()
+            .map(|allele| allele.variant_type(&self.reference))
+            .collect())
+    }
+
+    /// The kind of variant at this site.
+    ///
+    /// `<*>` and `<NON_REF>` alleles are left out, and so are `*` alleles unless they are the
+    /// only ALT alleles. A site left without ALT alleles is [`VariantType::Reference`], and one
+    /// with alleles of several kinds is [`VariantType::Mixed`].
+    pub fn variant_type(&self) -> Result<VariantType, ParseError> {
+        let mut kinds = self.allele_types()?;
+        kinds.retain(|kind| !matches!(kind, VariantType::Reference | VariantType::Unspecified));
+        if kinds
+            .iter()
+            .any(|kind| *kind != VariantType::OverlappingDeletion)
+        {
+            kinds.retain(|kind| *kind != VariantType::OverlappingDeletion);
+        }
+        kinds.sort_unstable();
+        kinds.dedup();
+        Ok(match kinds[..] {
+            [] => VariantType::Reference,
+            [kind] => kind,
+            _ => VariantType::Mixed,
+        })
+    }
+
+    /// Look up the value of a FORMAT key for the sample at `sample` (0-based).
+    ///
+    /// Trailing FORMAT fields may be dropped from a sample column, in which case they are
+    /// reported as missing.
+    pub fn sample_value(&self, sample: usize, key: &str) -> Option<&str> {
+        let index = self.format.iter().position(|k| k == key)?;
+        self.samples.get(sample)?.get(index).map(String::as_str)
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T], sep: &str) -> fmt::Result {
+    if items.is_empty() {
+        return write!(f, ".");
+    }
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", sep)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}\t", self.chrom, self.pos)?;
+        write_list(f, &self.id, ";")?;
+        write!(f, "\t{}\t", self.reference)?;
+        write_list(f, &self.alt, ",")?;
+        match (self.qual, &self.qual_text) {
+            (Some(qual), Some(text)) if text.parse() == Ok(qual) => write!(f, "\t{}\t", text)?,
+            (Some(qual), _) => write!(f, "\t{}\t", qual)?,
+            (None, _) => write!(f, "\t.\t")?,
+        }
+        write_list(f, &self.filter, ";")?;
+        write!(f, "\t")?;
+        if self.info.is_empty() {
+            write!(f, ".")?;
+        }
+        for (i, (key, value)) in self.info.iter().enumerate() {
+            if i > 0 {
+                write!(f, ";")?;
+            }
+            match value {
+                Some(value) => write!(f, "{}={}", key, value)?,
+                None => write!(f, "{}", key)?,
+            }
+        }
+        if !self.format.is_empty() {
+            write!(f, "\t{}", self.format.join(":"))?;
+            for sample in &self.samples {
+                write!(f, "\t{}", sample.join(":"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str =
+        "20\t14370\trs6054257\tG\tA\t29\tPASS\tNS=3;DP=14;AF=0.5;DB;H2\tGT:GQ:DP:HQ\t0|0:48:1:51,51\t1/1:43";
+
+    #[test]
+    fn round_trips_through_display() {
+        let record = Record::parse(LINE).unwrap();
+        assert_eq!(record.to_string(), LINE);
+    }
+
+    #[test]
+    fn keeps_qual_as_written() {
+        let mut record = Record::parse("1\t100\t.\tA\tG\t50.00\t.\t.").unwrap();
+        assert_eq!(record.qual, Some(50.0));
+        assert_eq!(record.to_string(), "1\t100\t.\tA\tG\t50.00\t.\t.");
+        record.qual = Some(20.5);
+        assert_eq!(record.to_string(), "1\t100\t.\tA\tG\t20.5\t.\t.");
+    }
+
+    #[test]
+    fn missing_columns_are_written_as_dots() {
+        let record = Record::parse("1\t100\t.\tA\t.\t.\t.\t.").unwrap();
+        assert!(record.id.is_empty());
+        assert!(record.alt.is_empty());
+        assert_eq!(record.qual, None);
+        assert_eq!(record.to_string(), "1\t100\t.\tA\t.\t.\t.\t.");
+    }
+
+    #[test]
+    fn classifies_sites() {
+        let kind = |alt: &str| {
+            Record::parse(&format!("1\t100\t.\tAC\t{}\t.\t.\t.", alt))
+                .unwrap()
+                .variant_type()
+                .unwrap()
+        };
+        assert_eq!(kind("."), VariantType::Reference);
+        assert_eq!(kind("<NON_REF>"), VariantType::Reference);
+        assert_eq!(kind("GC,TC,<*>"), VariantType::Snv);
+        assert_eq!(kind("*"), VariantType::OverlappingDeletion);
+        assert_eq!(kind("A,*"), VariantType::Deletion);
+        assert_eq!(kind("A,ACC"), VariantType::Mixed);
+        assert!(Record::parse("1\t100\t.\tA\tX\t.\t.\t.")
+            .unwrap()
+            .variant_type()
+            .is_err());
+    }
+
+    #[test]
+    fn can_look_up_info_and_sample_values() {
+        let record = Record::parse(LINE).unwrap();
+        assert_eq!(record.info("DP"), Some(Some("14")));
+        assert_eq!(record.info("DB"), Some(None));
+        assert_eq!(record.info("XX"), None);
+        assert_eq!(record.sample_value(0, "HQ"), Some("51,51"));
+        assert_eq!(record.sample_value(1, "DP"), None);
+    }
+
+    #[test]
+    fn parses_alt_alleles() {
+        let record = Record::parse("2\t321681\tbnd_W\tG\tG]17:198982],<DEL>\t6\tPASS\t.").unwrap();
+        let alleles = record.alt_alleles().unwrap();
+        assert!(matches!(alleles[0], Allele::Breakend(..)));
+        assert_eq!(alleles[1], Allele::Symbolic("DEL".to_string()));
+    }
+
+    #[test]
+    fn set_info_replaces_existing_values() {
+        let mut record = Record::parse(LINE).unwrap();
+        record.set_info("DP", Some("20".to_string()));
+        record.set_info("END", Some("14371".to_string()));
+        assert_eq!(record.info("DP"), Some(Some("20")));
+        assert_eq!(record.info.last().unwrap().0, "END");
+        assert_eq!(record.remove_info("DB"), Some(None));
+        assert_eq!(record.info("DB"), None);
+    }
+}
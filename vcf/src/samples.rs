@@ -0,0 +1,223 @@
+//! Choosing which samples to keep, and recomputing the allele counts of the samples kept.
+//!
+//! A [`SampleSelection`] is applied to a [`Reader`](crate::vcf::Reader) with
+//! [`select_samples`](crate::vcf::Reader::select_samples), which rewrites the sample names of
+//! its header and leaves the other sample columns of each record undecoded.
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead};
+
+use crate::vcf::{VCFError, VCF};
+use crate::{Genotype, Header, HeaderValue, Record};
+
+/// The samples to keep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleSelection {
+    /// Keep these samples, in this order.
+    Include(Vec<String>),
+    /// Keep all samples but these, in header order.
+    Exclude(Vec<String>),
+}
+
+impl SampleSelection {
+    /// Parse a comma-separated list of names, excluding them when the list starts with `^`.
+    pub fn parse_list(list: &str) -> Self {
+        let (names, exclude) = match list.strip_prefix('^') {
+            Some(names) => (names, true),
+            None => (list, false),
+        };
+        let names = names
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        match exclude {
+            true => SampleSelection::Exclude(names),
+            false => SampleSelection::Include(names),
+        }
+    }
+
+    /// The positions in `samples` of the samples to keep, in output order.
+    ///
+    /// Names that are not in `samples`, and included names given more than once, are errors.
+    pub fn indices(&self, samples: &[String]) -> Result<Vec<usize>, VCFError> {
+        let positions: HashMap<&str, usize> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i))
+            .collect();
+        let position = |name: &String| {
+            positions.get(name.as_str()).copied().ok_or_else(|| {
+                VCFError::InvalidHeader(format!("sample {} is not in the header", name))
+            })
+        };
+        match self {
+            SampleSelection::Include(names) => {
+                let mut seen = HashSet::new();
+                names
+                    .iter()
+                    .map(|name| match seen.insert(name) {
+                        true => position(name),
+                        false => Err(VCFError::InvalidHeader(format!(
+                            "sample {} is selected twice",
+                            name
+                        ))),
+                    })
+                    .collect()
+            }
+            SampleSelection::Exclude(names) => {
+                let excluded = names
+                    .iter()
+                    .map(position)
+                    .collect::<Result<HashSet<_>, _>>()?;
+                Ok((0..samples.len())
+                    .filter(|i| !excluded.contains(i))
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Read sample names, one per line, skipping blank lines.
+pub fn read_sample_list(input: impl BufRead) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        let name = line.trim();
+        if !name.is_empty() {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Add the `AC` and `AN` INFO definitions set by [`update_allele_counts`], unless the header
+/// already has them.
+pub fn add_allele_count_definitions(header: &mut VCF) {
+    let definitions = header.definitions();
+    let lines = [
+        ("AC", "A", "Allele count in genotypes, for each ALT allele"),
+        ("AN", "1", "Total number of alleles in called genotypes"),
+    ];
+    for (id, number, description) in lines {
+        if !definitions.info.contains_key(id) {
+            header.set_header(&Header {
+                key: "INFO",
+                value: HeaderValue::Nested(HashMap::from([
+                    ("ID", id),
+                    ("Number", number),
+                    ("Type", "Integer"),
+                    ("Description", description),
+                ])),
+            });
+        }
+    }
+}
+
+/// Set `AC` and `AN` from the genotypes of the record's samples, removing `AC` from records
+/// without ALT alleles. Records without genotypes are left unchanged.
+pub fn update_allele_counts(record: &mut Record) {
+    let Some(index) = record.format.iter().position(|key| key == "GT") else {
+        return;
+    };
+    let mut counts = vec![0usize; record.alt.len()];
+    let mut total = 0;
+    let genotypes = record
+        .samples
+        .iter()
+        .filter_map(|sample| sample.get(index)?.parse::<Genotype>().ok());
+    for genotype in genotypes {
+        for allele in genotype.alleles.into_iter().flatten() {
+            total += 1;
+            if let Some(count) = allele.checked_sub(1).and_then(|i| counts.get_mut(i)) {
+                *count += 1;
+            }
+        }
+    }
+    if counts.is_empty() {
+        record.remove_info("AC");
+    } else {
+        let counts: Vec<String> = counts.iter().map(usize::to_string).collect();
+        record.set_info("AC", Some(counts.join(",")));
+    }
+    record.set_info("AN", Some(total.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::Reader;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=AC,Number=A,Type=Integer,Description=\"Allele count\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tC\tD
+1\t100\t.\tA\tG,T\t.\t.\tAC=9,9;AN=8\tGT:DP\t0/1:5\t1/2:6\t2|2:7\t./.:8
+1\t200\t.\tA\t.\t.\t.\tAC=2\tGT\t0/0\t0\t.\t0/0
+";
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn resolves_selections() {
+        let samples = names(&["A", "B", "C", "D"]);
+        let include = SampleSelection::parse_list("C,A");
+        assert_eq!(include, SampleSelection::Include(names(&["C", "A"])));
+        assert_eq!(include.indices(&samples).unwrap(), vec![2, 0]);
+        let exclude = SampleSelection::parse_list("^C,A");
+        assert_eq!(exclude.indices(&samples).unwrap(), vec![1, 3]);
+        assert_eq!(
+            SampleSelection::parse_list("A,E")
+                .indices(&samples)
+                .unwrap_err()
+                .to_string(),
+            "invalid header: sample E is not in the header"
+        );
+        assert!(SampleSelection::parse_list("A,A")
+            .indices(&samples)
+            .is_err());
+        assert_eq!(
+            read_sample_list("B\n\n C \n".as_bytes()).unwrap(),
+            names(&["B", "C"])
+        );
+    }
+
+    #[test]
+    fn reads_only_selected_samples() {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        reader
+            .select_samples(&SampleSelection::parse_list("D,B,A"))
+            .unwrap();
+        reader
+            .select_samples(&SampleSelection::parse_list("^B"))
+            .unwrap();
+        assert_eq!(reader.header().samples, names(&["D", "A"]));
+        assert!(reader.header().to_string().ends_with("FORMAT\tD\tA\n"));
+        let record = reader.read_record().unwrap().unwrap();
+        assert_eq!(
+            record.samples,
+            vec![names(&["./.", "8"]), names(&["0/1", "5"])]
+        );
+    }
+
+    #[test]
+    fn recomputes_allele_counts() {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        reader
+            .select_samples(&SampleSelection::parse_list("^A"))
+            .unwrap();
+        let mut header = reader.header().clone();
+        add_allele_count_definitions(&mut header);
+        assert!(header.definitions().info.contains_key("AN"));
+        assert_eq!(header.meta.len(), 2);
+
+        let mut record = reader.read_record().unwrap().unwrap();
+        update_allele_counts(&mut record);
+        assert_eq!(record.info("AC"), Some(Some("1,3")));
+        assert_eq!(record.info("AN"), Some(Some("4")));
+        let mut record = reader.read_record().unwrap().unwrap();
+        update_allele_counts(&mut record);
+        assert_eq!(record.info("AC"), None);
+        assert_eq!(record.info("AN"), Some(Some("3")));
+    }
+}
@@ -0,0 +1,351 @@
+//! Sorting records by contig and position in a bounded amount of memory.
+//!
+//! Records are read until a memory budget is reached, then sorted and written to a temporary
+//! file, and the sorted files are merged once the input ends, at most [`MAX_FAN_IN`] at a time.
+//! Inputs that fit in the budget are sorted in memory without touching the disk.
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use tempfile::{NamedTempFile, TempPath};
+
+use crate::vcf::{Reader, VCFError};
+use crate::{ContigOrder, Interval, Record};
+
+/// The default memory budget, in bytes.
+pub const DEFAULT_MAX_MEMORY: usize = 768 * 1024 * 1024;
+
+/// The most sorted runs merged at once. Runs are kept closed until they are merged, and when
+/// there are more, groups of runs are first merged into longer ones, so no more than this many
+/// files are open at the same time.
+pub const MAX_FAN_IN: usize = 64;
+
+/// A data line with its sort key.
+struct Line {
+    text: String,
+    chrom_length: usize,
+    interval: Interval,
+}
+
+impl Line {
+    fn new(text: String) -> Result<Self, VCFError> {
+        let interval = Record::parse(&text)?.interval();
+        let chrom_length = text.find('\t').unwrap_or(text.len());
+        Ok(Self {
+            text,
+            chrom_length,
+            interval,
+        })
+    }
+
+    fn chrom(&self) -> &str {
+        &self.text[..self.chrom_length]
+    }
+
+    /// Roughly the memory the line takes up.
+    fn size(&self) -> usize {
+        self.text.capacity() + std::mem::size_of::<Self>()
+    }
+
+    fn compare(&self, other: &Self, order: &ContigOrder) -> Ordering {
+        order
+            .compare(self.chrom(), other.chrom())
+            .then(self.interval.cmp(&other.interval))
+    }
+}
+
+/// Sort the records of `reader` by the contig order of its header, falling back to natural
+/// order for contigs it does not list, and then by the start and end of their
+/// [reference intervals](Record::interval), writing them to `output`.
+///
+/// Records with the same interval keep their input order. When the records take up more than
+/// `max_memory` bytes, sorted runs are written to temporary files in `temp_dir` (or the system
+/// temporary directory), so inputs much larger than the memory available can be sorted.
+///
+/// ```
+/// use vcf::sort::{sort, DEFAULT_MAX_MEMORY};
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let source = b"##fileformat=VCFv4.3\n##contig=<ID=chrX>\n\
+/// #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+/// chr10\t5\t.\tA\tG\t.\t.\t.\nchr2\t9\t.\tA\tG\t.\t.\t.\nchrX\t7\t.\tA\tG\t.\t.\t.\n";
+/// let mut output = Vec::new();
+/// sort(Reader::new(&source[..])?, &mut output, DEFAULT_MAX_MEMORY, None)?;
+/// let output = String::from_utf8(output).unwrap();
+/// let chroms: Vec<&str> = output.lines().filter(|l| !l.starts_with('#')).map(|l| &l[..l.find('\t').unwrap()]).collect();
+/// assert_eq!(chroms, vec!["chrX", "chr2", "chr10"]);
+///# Ok::<(), VCFError>(())
+/// ```
+pub fn sort<R: BufRead>(
+    mut reader: Reader<R>,
+    mut output: impl Write,
+    max_memory: usize,
+    temp_dir: Option<&Path>,
+) -> Result<(), VCFError> {
+    let order = ContigOrder::new(reader.header().contigs());
+    write!(output, "{}", reader.header())?;
+
+    let mut lines = Vec::new();
+    let mut used = 0;
+    let mut runs = Vec::new();
+    while let Some(text) = reader.read_line()? {
+        let line = Line::new(text.to_string())?;
+        used += line.size();
+        lines.push(line);
+        if used >= max_memory {
+            runs.push(write_run(&mut lines, &order, temp_dir)?);
+            used = 0;
+        }
+    }
+
+    if runs.is_empty() {
+        lines.sort_by(|a, b| a.compare(b, &order));
+        for line in &lines {
+            writeln!(output, "{}", line.text)?;
+        }
+    } else {
+        if !lines.is_empty() {
+            runs.push(write_run(&mut lines, &order, temp_dir)?);
+        }
+        while runs.len() > MAX_FAN_IN {
+            runs = merge_pass(runs, &order, temp_dir)?;
+        }
+        merge_runs(runs, &order, &mut output)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Sort `lines` and move them to a temporary file, which is closed until it is merged and
+/// deleted when the returned path is dropped.
+fn write_run(
+    lines: &mut Vec<Line>,
+    order: &ContigOrder,
+    temp_dir: Option<&Path>,
+) -> Result<TempPath, VCFError> {
+    lines.sort_by(|a, b| a.compare(b, order));
+    let (file, path) = temp_file(temp_dir)?.into_parts();
+    let mut writer = BufWriter::new(file);
+    for line in lines.drain(..) {
+        writeln!(writer, "{}", line.text)?;
+    }
+    writer.flush()?;
+    Ok(path)
+}
+
+/// Merge consecutive groups of up to [`MAX_FAN_IN`] runs into longer runs, keeping them in
+/// order so that equal lines still come out in input order.
+fn merge_pass(
+    runs: Vec<TempPath>,
+    order: &ContigOrder,
+    temp_dir: Option<&Path>,
+) -> Result<Vec<TempPath>, VCFError> {
+    let mut merged = Vec::with_capacity(runs.len().div_ceil(MAX_FAN_IN));
+    let mut runs = runs.into_iter().peekable();
+    while runs.peek().is_some() {
+        let group: Vec<TempPath> = runs.by_ref().take(MAX_FAN_IN).collect();
+        let (file, path) = temp_file(temp_dir)?.into_parts();
+        let mut writer = BufWriter::new(file);
+        merge_runs(group, order, &mut writer)?;
+        writer.flush()?;
+        merged.push(path);
+    }
+    Ok(merged)
+}
+
+fn temp_file(temp_dir: Option<&Path>) -> Result<NamedTempFile, VCFError> {
+    Ok(match temp_dir {
+        Some(dir) => NamedTempFile::new_in(dir)?,
+        None => NamedTempFile::new()?,
+    })
+}
+
+/// The next line of a sorted run, ordered so that the smallest comes out of a max-heap first.
+struct Next<'o> {
+    line: Line,
+    run: usize,
+    order: &'o ContigOrder,
+}
+
+impl Ord for Next<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.line
+            .compare(&other.line, self.order)
+            .then(self.run.cmp(&other.run))
+    }
+}
+
+impl PartialOrd for Next<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Next<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Next<'_> {}
+
+/// Merge sorted runs into `output`; lines that compare equal come out in run order.
+fn merge_runs(
+    runs: Vec<TempPath>,
+    order: &ContigOrder,
+    output: &mut impl Write,
+) -> Result<(), VCFError> {
+    let mut readers = runs
+        .iter()
+        .map(|run| Ok(BufReader::new(File::open(run)?)))
+        .collect::<Result<Vec<_>, VCFError>>()?;
+    let read = |reader: &mut BufReader<File>| -> Result<Option<Line>, VCFError> {
+        let mut text = String::new();
+        if reader.read_line(&mut text)? == 0 {
+            return Ok(None);
+        }
+        text.truncate(text.trim_end_matches('\n').len());
+        Ok(Some(Line::new(text)?))
+    };
+
+    let mut heap = BinaryHeap::new();
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some(line) = read(reader)? {
+            heap.push(Reverse(Next { line, run, order }));
+        }
+    }
+    while let Some(Reverse(next)) = heap.pop() {
+        writeln!(output, "{}", next.line.text)?;
+        if let Some(line) = read(&mut readers[next.run])? {
+            heap.push(Reverse(Next {
+                line,
+                run: next.run,
+                order,
+            }));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "##fileformat=VCFv4.3
+##contig=<ID=chr2>
+##contig=<ID=chr1>
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+";
+
+    fn sorted(records: &[String], max_memory: usize) -> Vec<String> {
+        let text = format!("{}{}\n", HEADER, records.join("\n"));
+        let mut output = Vec::new();
+        let dir = tempfile::tempdir().unwrap();
+        sort(
+            Reader::new(text.as_bytes()).unwrap(),
+            &mut output,
+            max_memory,
+            Some(dir.path()),
+        )
+        .unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn records() -> Vec<String> {
+        // A shuffled mix of contigs, with some records sharing a position.
+        (0..500u64)
+            .map(|i| {
+                let chrom = ["chr1", "chr2", "chr10", "chr3"][(i * 7 % 4) as usize];
+                format!("{}\t{}\t{}\tA\tG\t.\t.\t.", chrom, (i * 37) % 101 + 1, i)
+            })
+            .collect()
+    }
+
+    fn is_sorted(lines: &[String]) -> bool {
+        let rank = |chrom: &str| {
+            ["chr2", "chr1", "chr3", "chr10"]
+                .iter()
+                .position(|c| *c == chrom)
+        };
+        let key = |line: &String| {
+            let columns: Vec<&str> = line.split('\t').collect();
+            (rank(columns[0]), columns[1].parse::<u64>().unwrap())
+        };
+        lines.windows(2).all(|pair| key(&pair[0]) <= key(&pair[1]))
+    }
+
+    #[test]
+    fn sorts_in_memory() {
+        let lines = sorted(&records(), DEFAULT_MAX_MEMORY);
+        assert_eq!(lines.len(), 500);
+        assert!(is_sorted(&lines));
+    }
+
+    #[test]
+    fn spilling_gives_the_same_order() {
+        let in_memory = sorted(&records(), DEFAULT_MAX_MEMORY);
+        assert_eq!(sorted(&records(), 4096), in_memory);
+        assert_eq!(sorted(&records(), 1), in_memory);
+    }
+
+    #[test]
+    fn merges_more_runs_than_the_fan_in() {
+        // One run per record, so that the runs are merged over several passes.
+        let records: Vec<String> = (0..5000u64)
+            .map(|i| format!("chr1\t{}\t{}\tA\tG\t.\t.\t.", (i * 37) % 11 + 1, i))
+            .collect();
+        let lines = sorted(&records, 1);
+        assert_eq!(lines, sorted(&records, DEFAULT_MAX_MEMORY));
+        let keys: Vec<(u64, u64)> = lines
+            .iter()
+            .map(|line| {
+                let columns: Vec<&str> = line.split('\t').collect();
+                (columns[1].parse().unwrap(), columns[2].parse().unwrap())
+            })
+            .collect();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn orders_records_at_the_same_position_by_end() {
+        let records: Vec<String> = [
+            "chr1\t5\ta\tACGT\tA\t.\t.\t.",
+            "chr1\t5\tb\tA\t<DEL>\t.\t.\tSVLEN=2",
+            "chr1\t5\tc\tA\tG\t.\t.\t.",
+        ]
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+        let ids: Vec<String> = sorted(&records, 1)
+            .iter()
+            .map(|l| l.split('\t').nth(2).unwrap().to_string())
+            .collect();
+        assert_eq!(ids, vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn keeps_input_order_at_the_same_position() {
+        let records: Vec<String> = ["chr1\t5\ta", "chr1\t2\tb", "chr1\t5\tc", "chr1\t5\td"]
+            .iter()
+            .map(|r| format!("{}\tA\tG\t.\t.\t.", r))
+            .collect();
+        let ids = |lines: Vec<String>| -> Vec<String> {
+            lines
+                .iter()
+                .map(|l| l.split('\t').nth(2).unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(
+            ids(sorted(&records, DEFAULT_MAX_MEMORY)),
+            vec!["b", "a", "c", "d"]
+        );
+        assert_eq!(ids(sorted(&records, 1)), vec!["b", "a", "c", "d"]);
+    }
+}
@@ -0,0 +1,397 @@
+//! Summary statistics of a VCF, gathered in a single pass over its records.
+//!
+//! Each record counts once for each kind of variant among its ALT alleles, so a record with a
+//! SNV and an indel allele counts as both. Sites without ALT alleles, or with only `*` and
+//! `<*>`/`<NON_REF>` alleles, count as `no_alt`. Genotypes are classified as in
+//! [`fill_tags`](crate::fill_tags): homozygous when all their alleles are the same, and missing
+//! when any allele is missing. A singleton is an ALT allele found exactly once in the genotypes
+//! of a site; the sample carrying it is counted as having a singleton.
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::vcf::VCF;
+use crate::{Allele, Fields, Genotype, Record, Substitution, VariantType};
+
+/// Counts of values in bins of equal width: bin `i` counts values from `i * bin_width` up to
+/// `(i + 1) * bin_width`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Histogram {
+    pub bin_width: f64,
+    pub bins: BTreeMap<u64, u64>,
+    /// Values that are missing, negative or not numbers.
+    pub missing: u64,
+}
+
+impl Histogram {
+    pub fn new(bin_width: f64) -> Self {
+        Self {
+            bin_width,
+            bins: BTreeMap::new(),
+            missing: 0,
+        }
+    }
+
+    pub fn add(&mut self, value: Option<f64>) {
+        match value {
+            Some(value) if value >= 0.0 && value.is_finite() => {
+                *self
+                    .bins
+                    .entry((value / self.bin_width).floor() as u64)
+                    .or_default() += 1
+            }
+            _ => self.missing += 1,
+        }
+    }
+}
+
+/// The genotype counts of one sample.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SampleStats {
+    pub name: String,
+    pub hom_ref: u64,
+    pub het: u64,
+    pub hom_alt: u64,
+    pub missing: u64,
+    pub singletons: u64,
+}
+
+impl SampleStats {
+    /// The number of heterozygous genotypes for each homozygous ALT genotype.
+    pub fn het_hom_ratio(&self) -> Option<f64> {
+        (self.hom_alt > 0).then(|| self.het as f64 / self.hom_alt as f64)
+    }
+
+    /// The fraction of genotypes that are missing.
+    pub fn missingness(&self) -> Option<f64> {
+        let total = self.hom_ref + self.het + self.hom_alt + self.missing;
+        (total > 0).then(|| self.missing as f64 / total as f64)
+    }
+}
+
+/// Statistics of the records of a VCF.
+///
+/// ```
+/// use vcf::stats::Stats;
+/// use vcf::vcf::{Reader, VCFError};
+///
+/// let source = b"##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\n\
+/// 1\t100\t.\tA\tG\t50\t.\tDP=10\tGT\t0/1\t0/0\n1\t200\t.\tC\tCTT\t.\t.\t.\tGT\t1/1\t0/1\n";
+/// let mut reader = Reader::new(&source[..])?;
+/// let mut stats = Stats::new(reader.header());
+/// for record in reader.records() {
+///     stats.add(&record?);
+/// }
+/// assert_eq!(stats.types.get("snv"), Some(&1));
+/// assert_eq!(stats.indel_lengths.get(&2), Some(&1));
+/// assert_eq!(stats.samples[0].het_hom_ratio(), Some(1.0));
+/// assert_eq!(stats.singletons, 1);
+///# Ok::<(), VCFError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stats {
+    pub records: u64,
+    /// Records by kind of variant: `snv`, `mnv`, `indel`, `complex`, `symbolic`, `breakend` and
+    /// `no_alt`.
+    pub types: BTreeMap<String, u64>,
+    pub transitions: u64,
+    pub transversions: u64,
+    /// Indel alleles by length, positive for insertions and negative for deletions.
+    pub indel_lengths: BTreeMap<i64, u64>,
+    pub qual: Histogram,
+    /// `INFO/DP`.
+    pub depth: Histogram,
+    /// Sites with at least one singleton.
+    pub singletons: u64,
+    pub samples: Vec<SampleStats>,
+    /// Records by contig, in the order the contigs first appear.
+    pub contigs: Fields<u64>,
+}
+
+impl Stats {
+    /// Statistics with no records yet, for the samples of `header`.
+    pub fn new(header: &VCF) -> Self {
+        Self {
+            records: 0,
+            types: BTreeMap::new(),
+            transitions: 0,
+            transversions: 0,
+            indel_lengths: BTreeMap::new(),
+            qual: Histogram::new(10.0),
+            depth: Histogram::new(1.0),
+            singletons: 0,
+            samples: header
+                .samples
+                .iter()
+                .map(|name| SampleStats {
+                    name: name.clone(),
+                    ..SampleStats::default()
+                })
+                .collect(),
+            contigs: Fields::default(),
+        }
+    }
+
+    pub fn add(&mut self, record: &Record) {
+        self.records += 1;
+        match self
+            .contigs
+            .0
+            .iter_mut()
+            .rev()
+            .find(|(contig, _)| *contig == record.chrom)
+        {
+            Some((_, count)) => *count += 1,
+            None => self.contigs.0.push((record.chrom.clone(), 1)),
+        }
+        self.qual.add(record.qual);
+        let depth = record.info("DP").flatten().and_then(|dp| dp.parse().ok());
+        self.depth.add(depth);
+
+        let mut types: Vec<&str> = Vec::new();
+        for alt in &record.alt {
+            let Some(kind) = self.add_allele(&record.reference, alt) else {
+                continue;
+            };
+            if !types.contains(&kind) {
+                types.push(kind);
+            }
+        }
+        if types.is_empty() {
+            types.push("no_alt");
+        }
+        for kind in types {
+            *self.types.entry(kind.to_string()).or_default() += 1;
+        }
+        self.add_genotypes(record);
+    }
+
+    /// Count an ALT allele, returning its kind of variant.
+    fn add_allele(&mut self, reference: &str, alt: &str) -> Option<&'static str> {
+        let allele = alt.parse::<Allele>().ok()?;
+        let kind = allele.variant_type(reference);
+        match kind {
+            VariantType::Snv => match allele.substitution(reference) {
+                Some(Substitution::Transition) => self.transitions += 1,
+                Some(Substitution::Transversion) => self.transversions += 1,
+                None => {}
+            },
+            VariantType::Insertion | VariantType::Deletion => {
+                let length = alt.len() as i64 - reference.len() as i64;
+                *self.indel_lengths.entry(length).or_default() += 1;
+            }
+            _ => {}
+        }
+        match kind {
+            VariantType::Snv => Some("snv"),
+            VariantType::Mnv => Some("mnv"),
+            VariantType::Insertion | VariantType::Deletion => Some("indel"),
+            VariantType::Complex => Some("complex"),
+            VariantType::Symbolic => Some("symbolic"),
+            VariantType::Breakend => Some("breakend"),
+            _ => None,
+        }
+    }
+
+    fn add_genotypes(&mut self, record: &Record) {
+        let Some(index) = record.format.iter().position(|key| key == "GT") else {
+            return;
+        };
+        let mut counts = vec![0u64; record.alt.len() + 1];
+        let mut genotypes = Vec::with_capacity(self.samples.len());
+        for (sample, stats) in record.samples.iter().zip(&mut self.samples) {
+            let genotype = sample.get(index).and_then(|gt| gt.parse::<Genotype>().ok());
+            let called: Option<Vec<usize>> =
+                genotype.and_then(|genotype| genotype.alleles.into_iter().collect());
+            match &called {
+                None => stats.missing += 1,
+                Some(alleles) if alleles.iter().all(|allele| *allele == alleles[0]) => {
+                    match alleles[0] {
+                        0 => stats.hom_ref += 1,
+                        _ => stats.hom_alt += 1,
+                    }
+                }
+                Some(_) => stats.het += 1,
+            }
+            for allele in called.iter().flatten() {
+                if let Some(count) = counts.get_mut(*allele) {
+                    *count += 1;
+                }
+            }
+            genotypes.push(called);
+        }
+        let singletons: Vec<usize> = (1..counts.len()).filter(|&i| counts[i] == 1).collect();
+        if singletons.is_empty() {
+            return;
+        }
+        self.singletons += 1;
+        for (called, stats) in genotypes.iter().zip(&mut self.samples) {
+            let alleles = called.iter().flatten();
+            stats.singletons += alleles.filter(|a| singletons.contains(a)).count() as u64;
+        }
+    }
+
+    /// The ratio of transitions to transversions.
+    pub fn ts_tv(&self) -> Option<f64> {
+        (self.transversions > 0).then(|| self.transitions as f64 / self.transversions as f64)
+    }
+
+    /// Write the statistics as tab-separated lines, each starting with the name of its section.
+    pub fn write_tsv(&self, mut output: impl Write) -> io::Result<()> {
+        let ratio = |value: Option<f64>| value.map_or(".".to_string(), |v| format!("{:.4}", v));
+        writeln!(output, "# SN\tkey\tvalue")?;
+        writeln!(output, "SN\trecords\t{}", self.records)?;
+        writeln!(output, "SN\tsingleton sites\t{}", self.singletons)?;
+        writeln!(output, "SN\ttransitions\t{}", self.transitions)?;
+        writeln!(output, "SN\ttransversions\t{}", self.transversions)?;
+        writeln!(output, "SN\tts/tv\t{}", ratio(self.ts_tv()))?;
+        writeln!(output, "# TYPE\ttype\trecords")?;
+        for (kind, count) in &self.types {
+            writeln!(output, "TYPE\t{}\t{}", kind, count)?;
+        }
+        writeln!(output, "# IDL\tlength\tcount")?;
+        for (length, count) in &self.indel_lengths {
+            writeln!(output, "IDL\t{}\t{}", length, count)?;
+        }
+        for (section, histogram) in [("QUAL", &self.qual), ("DP", &self.depth)] {
+            writeln!(output, "# {}\tbin start\tcount", section)?;
+            for (bin, count) in &histogram.bins {
+                let start = *bin as f64 * histogram.bin_width;
+                writeln!(output, "{}\t{}\t{}", section, start, count)?;
+            }
+            writeln!(output, "{}\t.\t{}", section, histogram.missing)?;
+        }
+        writeln!(
+            output,
+            "# PSC\tsample\thom ref\thet\thom alt\tmissing\tsingletons\thet/hom\tmissingness"
+        )?;
+        for sample in &self.samples {
+            writeln!(
+                output,
+                "PSC\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                sample.name,
+                sample.hom_ref,
+                sample.het,
+                sample.hom_alt,
+                sample.missing,
+                sample.singletons,
+                ratio(sample.het_hom_ratio()),
+                ratio(sample.missingness())
+            )?;
+        }
+        writeln!(output, "# CTG\tcontig\trecords")?;
+        for (contig, count) in &self.contigs.0 {
+            writeln!(output, "CTG\t{}\t{}", contig, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use serde::ser::SerializeStruct;
+    use serde::{Serialize, Serializer};
+
+    use super::SampleStats;
+
+    impl Serialize for SampleStats {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut sample = serializer.serialize_struct("SampleStats", 8)?;
+            sample.serialize_field("name", &self.name)?;
+            sample.serialize_field("hom_ref", &self.hom_ref)?;
+            sample.serialize_field("het", &self.het)?;
+            sample.serialize_field("hom_alt", &self.hom_alt)?;
+            sample.serialize_field("missing", &self.missing)?;
+            sample.serialize_field("singletons", &self.singletons)?;
+            sample.serialize_field("het_hom_ratio", &self.het_hom_ratio())?;
+            sample.serialize_field("missingness", &self.missingness())?;
+            sample.end()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::Reader;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tC
+1\t100\t.\tA\tG,T\t55\t.\tDP=12\tGT\t0/1\t1/1\t0/2
+1\t200\t.\tCAT\tC,CATAT,GCA\t.\t.\tDP=.\tGT\t0/0\t0/1\t./.
+2\t300\t.\tAC\tGTT,<DEL>,*\t5\t.\tDP=3\tGT\t0/0\t0/0\t0
+2\t400\t.\tA\tA[2:500[,<NON_REF>\t.\t.\t.\tGT\t0/1\t.\t0|0
+2\t500\t.\tC\t.\t.\t.\t.\tGT\t0/0\t0/0\t0/0
+";
+
+    fn stats() -> Stats {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        let mut stats = Stats::new(reader.header());
+        for record in reader.records() {
+            stats.add(&record.unwrap());
+        }
+        stats
+    }
+
+    #[test]
+    fn counts_variant_types() {
+        let stats = stats();
+        assert_eq!(stats.records, 5);
+        let types: Vec<(&str, u64)> = stats.types.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(
+            types,
+            [
+                ("breakend", 1),
+                ("complex", 1),
+                ("indel", 1),
+                ("mnv", 1),
+                ("no_alt", 1),
+                ("snv", 1),
+                ("symbolic", 1)
+            ]
+        );
+        assert_eq!((stats.transitions, stats.transversions), (1, 1));
+        assert_eq!(stats.ts_tv(), Some(1.0));
+        assert_eq!(stats.indel_lengths, BTreeMap::from([(-2, 1), (2, 1)]));
+        assert_eq!(stats.qual.bins, BTreeMap::from([(0, 1), (5, 1)]));
+        assert_eq!(stats.qual.missing, 3);
+        assert_eq!(stats.depth.bins, BTreeMap::from([(3, 1), (12, 1)]));
+        assert_eq!(
+            stats.contigs.0,
+            [("1".to_string(), 2), ("2".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn counts_genotypes_by_sample() {
+        let stats = stats();
+        let a = &stats.samples[0];
+        assert_eq!((a.hom_ref, a.het, a.hom_alt, a.missing), (3, 2, 0, 0));
+        assert_eq!(a.het_hom_ratio(), None);
+        let b = &stats.samples[1];
+        assert_eq!((b.hom_ref, b.het, b.hom_alt, b.missing), (2, 1, 1, 1));
+        assert_eq!(b.missingness(), Some(0.2));
+        // The T at 1:100, the CATAT at 1:200 and the breakend at 2:400 are singletons.
+        assert_eq!(stats.singletons, 3);
+        let singletons: Vec<u64> = stats.samples.iter().map(|s| s.singletons).collect();
+        assert_eq!(singletons, [1, 1, 1]);
+
+        let mut tsv = Vec::new();
+        stats.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("SN\tts/tv\t1.0000\n"));
+        assert!(tsv.contains("QUAL\t50\t1\n"));
+        assert!(tsv.contains("PSC\tB\t2\t1\t1\t1\t1\t1.0000\t0.2000\n"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_to_json() {
+        let json = serde_json::to_value(stats()).unwrap();
+        assert_eq!(json["types"]["snv"], 1);
+        assert_eq!(json["indel_lengths"]["-2"], 1);
+        assert_eq!(json["samples"][1]["het_hom_ratio"], 1.0);
+        assert_eq!(json["contigs"]["2"], 3);
+    }
+}
@@ -0,0 +1,330 @@
+//! Tabix (`.tbi`) and CSI (`.csi`) indexes of BGZF-compressed files, used to skip to the lines
+//! of a region without reading the lines before it.
+//!
+//! Only what is needed to find where the lines overlapping a position start is read: the linear
+//! index of a `.tbi` and the offsets of the bins of a `.csi`.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Seek};
+
+use crate::bgzf;
+
+/// The bin holding the metadata of a contig in a `.tbi`, rather than lines.
+const TBI_METADATA_BIN: u32 = 37450;
+
+/// The size, as a power of two, of the windows of the linear index of a `.tbi`.
+const TBI_SHIFT: u32 = 14;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("index: {}", message))
+}
+
+/// The index of one contig.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ContigIndex {
+    /// For a `.tbi`, the offset of the first line overlapping each 16 KiB window.
+    linear: Vec<u64>,
+    /// For a `.csi`, the offset of the first line overlapping each bin.
+    bins: HashMap<u32, u64>,
+}
+
+/// An index of the lines of a BGZF-compressed file by contig and position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    contigs: HashMap<String, ContigIndex>,
+    /// For a `.csi`, the size of the smallest bins as a power of two and the number of levels
+    /// below the root bin.
+    csi: Option<(u32, u32)>,
+}
+
+/// Reads little-endian values from decompressed index data.
+struct Fields<R>(R);
+
+impl<R: Read> Fields<R> {
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        self.0.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        self.0.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn count(&mut self) -> io::Result<usize> {
+        usize::try_from(self.u32()? as i32).map_err(|_| invalid("negative count"))
+    }
+
+    fn bytes(&mut self, length: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        (&mut self.0).take(length as u64).read_to_end(&mut bytes)?;
+        match bytes.len() == length {
+            true => Ok(bytes),
+            false => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    /// The contig names of a tabix header, after its format and column fields.
+    fn names(&mut self) -> io::Result<Vec<String>> {
+        for _ in 0..6 {
+            self.u32()?;
+        }
+        let length = self.count()?;
+        let names = self.bytes(length)?;
+        names
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8(name.to_vec()).map_err(|_| invalid("contig name")))
+            .collect()
+    }
+}
+
+impl Index {
+    /// Read a BGZF-compressed `.tbi` or `.csi`. A `.csi` must have the tabix header giving
+    /// the contig names, as those written by `tabix --csi` do.
+    pub fn read(input: impl Read) -> io::Result<Self> {
+        let mut fields = Fields(bgzf::Reader::new(input));
+        let magic = fields.bytes(4)?;
+        let (names, csi) = match &magic[..] {
+            b"TBI\x01" => {
+                let references = fields.count()?;
+                let names = fields.names()?;
+                if names.len() != references {
+                    return Err(invalid("contig names do not match the contigs"));
+                }
+                (names, None)
+            }
+            b"CSI\x01" => {
+                let min_shift = fields.u32()?;
+                let depth = fields.u32()?;
+                let aux = fields.count()?;
+                let aux = fields.bytes(aux)?;
+                let names = match aux.len() >= 28 {
+                    true => Fields(&aux[..]).names()?,
+                    false => return Err(invalid("CSI index without contig names")),
+                };
+                (names, Some((min_shift, depth)))
+            }
+            _ => return Err(invalid("not a tabix or CSI index")),
+        };
+        if csi.is_some() && fields.count()? != names.len() {
+            return Err(invalid("contig names do not match the contigs"));
+        }
+        let metadata_bin = match csi {
+            Some((_, depth)) => ((1 << ((depth + 1) * 3)) - 1) / 7 + 1,
+            None => TBI_METADATA_BIN,
+        };
+        let mut contigs = HashMap::new();
+        for name in names {
+            let mut contig = ContigIndex::default();
+            for _ in 0..fields.count()? {
+                let bin = fields.u32()?;
+                let first = match csi {
+                    Some(_) => Some(fields.u64()?),
+                    None => None,
+                };
+                for _ in 0..fields.count()? {
+                    fields.u64()?;
+                    fields.u64()?;
+                }
+                if let (Some(first), true) = (first, bin != metadata_bin) {
+                    contig.bins.insert(bin, first);
+                }
+            }
+            if csi.is_none() {
+                let windows = fields.count()?;
+                contig.linear = (0..windows)
+                    .map(|_| fields.u64())
+                    .collect::<io::Result<_>>()?;
+            }
+            contigs.insert(name, contig);
+        }
+        Ok(Self { contigs, csi })
+    }
+
+    /// A virtual offset before every line on `chrom` that ends at or after the 1-based
+    /// position `pos`, or `None` if the index has no lines on `chrom`.
+    pub fn offset(&self, chrom: &str, pos: u64) -> Option<u64> {
+        let contig = self.contigs.get(chrom)?;
+        let start = pos.saturating_sub(1);
+        match self.csi {
+            None => {
+                let window = (start >> TBI_SHIFT) as usize;
+                let last = contig.linear.len().checked_sub(1)?;
+                Some(contig.linear[window.min(last)])
+            }
+            // Every line overlapping the position overlaps every bin that contains it, so
+            // none comes before the largest of their offsets.
+            Some((min_shift, depth)) => (0..=depth)
+                .filter_map(|level| {
+                    let shift = min_shift + 3 * (depth - level);
+                    let first = ((1u64 << (3 * level)) - 1) / 7;
+                    let bin = u32::try_from(first + (start >> shift)).ok()?;
+                    contig.bins.get(&bin).copied()
+                })
+                .max(),
+        }
+    }
+}
+
+/// Reads a BGZF-compressed file, skipping ahead with its index when asked.
+pub struct IndexedReader<R> {
+    inner: bgzf::Reader<R>,
+    index: Index,
+}
+
+impl<R: Read + Seek> IndexedReader<R> {
+    /// Read `inner` from its current position, which must be the start of the file.
+    pub fn new(inner: R, index: Index) -> Self {
+        Self {
+            inner: bgzf::Reader::new(inner),
+            index,
+        }
+    }
+
+    /// Skip to the lines that may overlap `pos` on `chrom`, unless they are not ahead of the
+    /// lines read so far, returning whether the reader moved.
+    pub fn seek(&mut self, chrom: &str, pos: u64) -> io::Result<bool> {
+        match self.index.offset(chrom, pos) {
+            Some(offset) if offset > self.inner.virtual_offset() => {
+                self.inner.seek_virtual(offset)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl<R: Read> Read for IndexedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Read> BufRead for IndexedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// BGZF-compress `lines`, one block per line so that they have distinct offsets, and build
+    /// a `.tbi` or `.csi` for them from their CHROM and POS columns.
+    pub(crate) fn indexed(lines: &[&str], csi: bool) -> (Vec<u8>, Vec<u8>) {
+        let mut data = bgzf::Writer::new(Vec::new());
+        let mut offsets: Vec<(&str, u64, u64)> = Vec::new();
+        let mut written = 0;
+        for line in lines {
+            let block = bgzf::Block::compress(format!("{}\n", line).as_bytes(), Default::default())
+                .unwrap();
+            data.write_block(&block).unwrap();
+            let mut columns = line.split('\t');
+            let chrom = columns.next().unwrap();
+            let pos = columns.next().unwrap().parse().unwrap();
+            offsets.push((chrom, pos, written << 16));
+            written += block.as_bytes().len() as u64;
+        }
+        let data = data.finish().unwrap();
+
+        let mut names: Vec<&str> = Vec::new();
+        for (chrom, _, _) in &offsets {
+            if !names.contains(chrom) {
+                names.push(chrom);
+            }
+        }
+        let name_bytes: Vec<u8> = names
+            .iter()
+            .flat_map(|n| [n.as_bytes(), b"\0"].concat())
+            .collect();
+        let mut header = Vec::new();
+        for value in [2u32, 1, 2, 0, b'#' as u32, 0, name_bytes.len() as u32] {
+            header.extend(value.to_le_bytes());
+        }
+        header.extend(&name_bytes);
+
+        let mut index = Vec::new();
+        match csi {
+            true => {
+                index.extend(b"CSI\x01");
+                for value in [TBI_SHIFT, 5, header.len() as u32] {
+                    index.extend(value.to_le_bytes());
+                }
+                index.extend(&header);
+                index.extend((names.len() as u32).to_le_bytes());
+            }
+            false => {
+                index.extend(b"TBI\x01");
+                index.extend((names.len() as u32).to_le_bytes());
+                index.extend(&header);
+            }
+        }
+        for name in &names {
+            let lines: Vec<_> = offsets.iter().filter(|(c, _, _)| c == name).collect();
+            match csi {
+                // A single bin at the root, which holds every line.
+                true => {
+                    index.extend(1u32.to_le_bytes());
+                    index.extend(0u32.to_le_bytes());
+                    index.extend(lines[0].2.to_le_bytes());
+                    index.extend(0u32.to_le_bytes());
+                }
+                false => {
+                    index.extend(0u32.to_le_bytes());
+                    let last = (lines.last().unwrap().1 - 1) >> TBI_SHIFT;
+                    index.extend((last as u32 + 1).to_le_bytes());
+                    for window in 0..=last {
+                        let first = lines
+                            .iter()
+                            .find(|(_, pos, _)| (pos - 1) >> TBI_SHIFT >= window)
+                            .unwrap();
+                        index.extend(first.2.to_le_bytes());
+                    }
+                }
+            }
+        }
+        let mut writer = bgzf::Writer::new(Vec::new());
+        writer.write_all(&index).unwrap();
+        (data, writer.finish().unwrap())
+    }
+
+    const LINES: [&str; 4] = ["1\t100\ta", "1\t40000\tb", "1\t90000\tc", "2\t5\td"];
+
+    fn rest(reader: &mut impl BufRead) -> Vec<String> {
+        reader.lines().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn seeks_forward_with_a_tbi() {
+        let (data, index) = indexed(&LINES, false);
+        let index = Index::read(&index[..]).unwrap();
+        assert_eq!(index.offset("3", 1), None);
+        let mut reader = IndexedReader::new(io::Cursor::new(data), index);
+        assert!(reader.seek("1", 50000).unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "1\t90000\tc\n");
+        // Positions behind the lines read are not sought back to.
+        assert!(!reader.seek("1", 1).unwrap());
+        assert_eq!(rest(&mut reader), ["2\t5\td"]);
+    }
+
+    #[test]
+    fn seeks_forward_with_a_csi() {
+        let (data, index) = indexed(&LINES, true);
+        let index = Index::read(&index[..]).unwrap();
+        let mut reader = IndexedReader::new(io::Cursor::new(data), index);
+        assert!(reader.seek("2", 5).unwrap());
+        assert_eq!(rest(&mut reader), ["2\t5\td"]);
+        assert!(Index::read(&b"not an index"[..]).is_err());
+    }
+}
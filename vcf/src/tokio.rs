@@ -0,0 +1,267 @@
+//! Reading VCF and BGZF from tokio's asynchronous readers.
+//!
+//! [`Reader`] parses the header and records just as [`vcf::Reader`](crate::vcf::Reader) does,
+//! and is a [`Stream`] of records. [`BgzfReader`] decompresses BGZF input one block at a time,
+//! like [`bgzf::Reader`](crate::bgzf::Reader), and can be read by a [`Reader`].
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use ::tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+use futures_core::Stream;
+
+use crate::bgzf::Block;
+use crate::vcf::{data_line, HeaderLines, VCFError, VCF};
+use crate::Record;
+
+/// Parses a VCF from an [`AsyncBufRead`].
+///
+/// ```
+/// use vcf::tokio::Reader;
+///
+/// # ::tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let source = b"##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+/// 1\t100\t.\tA\tG\t.\t.\t.\n1\t200\t.\tC\tT\t.\t.\t.\n";
+/// let mut reader = Reader::new(&source[..]).await?;
+/// let mut positions = Vec::new();
+/// while let Some(record) = reader.read_record().await? {
+///     positions.push(record.pos);
+/// }
+/// assert_eq!(positions, [100, 200]);
+/// # Ok::<(), vcf::vcf::VCFError>(())
+/// # }).unwrap();
+/// ```
+pub struct Reader<R> {
+    inner: R,
+    vcf: VCF,
+    /// The line being read, which may be incomplete.
+    line: Vec<u8>,
+}
+
+impl<R: AsyncBufRead + Unpin> Reader<R> {
+    pub async fn new(mut inner: R) -> Result<Self, VCFError> {
+        let mut line = Vec::new();
+        let mut lines = HeaderLines::default();
+        let vcf = loop {
+            line.clear();
+            if !poll_fn(|cx| poll_line(Pin::new(&mut inner), &mut line, cx)).await? {
+                return Err(VCFError::ParseError);
+            }
+            if let Some(vcf) = lines.push(utf8(&line)?)? {
+                break vcf;
+            }
+        };
+        line.clear();
+        Ok(Self { inner, vcf, line })
+    }
+
+    pub fn header(&self) -> &VCF {
+        &self.vcf
+    }
+
+    pub fn into_header(self) -> VCF {
+        self.vcf
+    }
+
+    /// Read the next record, or `None` at the end of the input.
+    pub async fn read_record(&mut self) -> Result<Option<Record>, VCFError> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .transpose()
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for Reader<R> {
+    type Item = Result<Record, VCFError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match ready!(poll_line(Pin::new(&mut this.inner), &mut this.line, cx)) {
+                Err(error) => return Poll::Ready(Some(Err(error.into()))),
+                Ok(false) => return Poll::Ready(None),
+                Ok(true) => {}
+            }
+            let line = std::mem::take(&mut this.line);
+            let record = match utf8(&line) {
+                Ok(line) => data_line(line).map(|line| Ok(Record::parse(line)?)),
+                Err(error) => Some(Err(error.into())),
+            };
+            this.line = line;
+            this.line.clear();
+            if let Some(record) = record {
+                return Poll::Ready(Some(record));
+            }
+        }
+    }
+}
+
+fn utf8(line: &[u8]) -> io::Result<&str> {
+    std::str::from_utf8(line).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Add bytes to `line` until it holds a whole line, returning whether there was anything left
+/// to read. Bytes already in `line` are kept, so a line read in several calls is not lost.
+fn poll_line<R: AsyncBufRead>(
+    mut inner: Pin<&mut R>,
+    line: &mut Vec<u8>,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<bool>> {
+    loop {
+        let available = ready!(inner.as_mut().poll_fill_buf(cx))?;
+        if available.is_empty() {
+            return Poll::Ready(Ok(!line.is_empty()));
+        }
+        match available.iter().position(|&byte| byte == b'\n') {
+            Some(end) => {
+                line.extend_from_slice(&available[..=end]);
+                inner.as_mut().consume(end + 1);
+                return Poll::Ready(Ok(true));
+            }
+            None => {
+                let length = available.len();
+                line.extend_from_slice(available);
+                inner.as_mut().consume(length);
+            }
+        }
+    }
+}
+
+/// Decompresses a BGZF stream from an [`AsyncRead`] one block at a time.
+pub struct BgzfReader<R> {
+    inner: R,
+    /// The part of the next block read so far.
+    block: Vec<u8>,
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl<R> BgzfReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            block: Vec::new(),
+            data: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BgzfReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let length = available.len().min(buf.remaining());
+        buf.put_slice(&available[..length]);
+        self.consume(length);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for BgzfReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        while this.position == this.data.len() {
+            let length = Block::length(&this.block)?;
+            if length == this.block.len() {
+                let block = Block::from_bytes(std::mem::take(&mut this.block));
+                this.data = block.decompress()?;
+                this.position = 0;
+                continue;
+            }
+            let read = this.block.len();
+            this.block.resize(length, 0);
+            let mut buf = ReadBuf::new(&mut this.block[read..]);
+            let polled = Pin::new(&mut this.inner).poll_read(cx, &mut buf);
+            let filled = buf.filled().len();
+            this.block.truncate(read + filled);
+            ready!(polled)?;
+            if filled == 0 && read == 0 {
+                break;
+            } else if filled == 0 {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+        Poll::Ready(Ok(&this.data[this.position..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amount: usize) {
+        let this = self.get_mut();
+        this.position = (this.position + amount).min(this.data.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bgzf::Writer;
+    use ::tokio::io::{AsyncReadExt, BufReader};
+    use std::io::Write;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3\n\
+        ##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\n\
+        #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\n\
+        1\t100\t.\tA\tG\t.\tPASS\tDP=3\tGT\t0/1\r\n\
+        \n\
+        1\t200\t.\tC\tT,G\t20\t.\tDP=5\tGT\t1/2\n\
+        2\t50\trs1\tG\tA\t.\t.\t.\tGT\t0|1";
+
+    async fn records<R: AsyncBufRead + Unpin>(mut reader: Reader<R>) -> Vec<Record> {
+        let mut records = Vec::new();
+        while let Some(record) = poll_fn(|cx| Pin::new(&mut reader).poll_next(cx)).await {
+            records.push(record.unwrap());
+        }
+        records
+    }
+
+    #[::tokio::test]
+    async fn reads_what_the_sync_reader_reads() {
+        let mut sync = crate::vcf::Reader::new(SOURCE.as_bytes()).unwrap();
+        let expected: Vec<Record> = sync.records().collect::<Result<_, _>>().unwrap();
+
+        // A tiny buffer makes lines arrive in pieces.
+        let reader = Reader::new(BufReader::with_capacity(3, SOURCE.as_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(reader.header(), sync.header());
+        assert_eq!(records(reader).await, expected);
+
+        assert!(Reader::new(&b"##fileformat=VCFv4.3\n"[..]).await.is_err());
+        let source = format!("{}\n1\tx\n", SOURCE);
+        let mut reader = Reader::new(source.as_bytes()).await.unwrap();
+        for _ in 0..expected.len() {
+            reader.read_record().await.unwrap();
+        }
+        assert!(reader.read_record().await.is_err());
+        assert!(reader.read_record().await.unwrap().is_none());
+    }
+
+    #[::tokio::test]
+    async fn decompresses_bgzf() {
+        let text = format!("{}\n", SOURCE).repeat(2000);
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(text.as_bytes()).unwrap();
+        let compressed = writer.finish().unwrap();
+
+        let mut decoded = Vec::new();
+        BgzfReader::new(&compressed[..])
+            .read_to_end(&mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, text.as_bytes());
+        let truncated = BgzfReader::new(&compressed[..compressed.len() / 2])
+            .read_to_end(&mut Vec::new())
+            .await;
+        assert!(truncated.is_err());
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(SOURCE.as_bytes()).unwrap();
+        let compressed = writer.finish().unwrap();
+        let reader = Reader::new(BgzfReader::new(&compressed[..])).await.unwrap();
+        assert_eq!(records(reader).await.len(), 3);
+    }
+}
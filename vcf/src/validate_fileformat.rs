@@ -1,18 +1,13 @@
-use std::collections::HashMap;
-
 use crate::headers::Header;
-use crate::headers::HeaderValue::{Flat, Nested};
+use crate::headers::HeaderValue::Flat;
 
 pub fn is_valid_file_format(input: &Header) -> bool {
-    is_flat(&input)
-    & key_is_fileformat(&input)
+    is_flat(input)
+    & key_is_fileformat(input)
 }
 
 fn is_flat(input: &Header) -> bool {
-    match input.value {
-        Flat(..) => true,
-        _ => false,
-    }
+    matches!(input.value, Flat(..))
 }
 
 fn key_is_fileformat(input: &Header) -> bool {
@@ -21,7 +16,10 @@ fn key_is_fileformat(input: &Header) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::*;
+    use crate::headers::HeaderValue::Nested;
 
     #[test]
     fn is_valid_if_key_is_fileformat() {
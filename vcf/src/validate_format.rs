@@ -2,7 +2,8 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::str::FromStr;
 
-enum NumberField {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberField {
     Number(u32),
     A,   // The field has one value per alternate allele
     R,   // The field has one value for each possible allele
@@ -11,7 +12,8 @@ enum NumberField {
 }
 
 // use static dispatch for Info field parser
-enum DataType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
     Integer(NumberField),
     Float(NumberField),
     Flag,
@@ -19,11 +21,45 @@ enum DataType {
     String(NumberField),
 }
 
-struct InfoFormat {
-    fieldtype: DataType,
-    description: String,
-    source: Option<String>,
-    version: Option<String>,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfoFormat {
+    pub fieldtype: DataType,
+    pub description: String,
+    pub source: Option<String>,
+    pub version: Option<String>,
+}
+
+impl DataType {
+    pub fn number(&self) -> NumberField {
+        match self {
+            DataType::Integer(number)
+            | DataType::Float(number)
+            | DataType::Character(number)
+            | DataType::String(number) => *number,
+            DataType::Flag => NumberField::Number(0),
+        }
+    }
+}
+
+impl InfoFormat {
+    /// Build a definition from the key-value pairs of an `##INFO` or `##FORMAT` header line.
+    pub fn parse(fields: &HashMap<&str, &str>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(InfoFormat {
+            fieldtype: parse_type_value(fields)?,
+            description: crate::unescape_header_value(
+                fields.get("Description").ok_or("Description not found")?,
+            ).into_owned(),
+            source: fields.get("Source").map(|s| crate::unescape_header_value(s).into_owned()),
+            version: fields.get("Version").map(|s| crate::unescape_header_value(s).into_owned()),
+        })
+    }
+}
+
+/// The INFO and FORMAT definitions of a header, keyed by ID.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Definitions {
+    pub info: HashMap<String, InfoFormat>,
+    pub format: HashMap<String, InfoFormat>,
 }
 
 fn parse_number_field(number: Option<&&str>) -> Result<NumberField, Box<dyn std::error::Error>> {
@@ -42,16 +78,16 @@ fn parse_number_field(number: Option<&&str>) -> Result<NumberField, Box<dyn std:
 
 /// - [X] Check has all and only required keys.
 /// - [ ] Check that values are of the required types.
-fn is_valid_format(input: HashMap<&str, &str>) -> bool {
-    return has_required_keys(&input)
+pub fn is_valid_format(input: HashMap<&str, &str>) -> bool {
+    has_required_keys(&input)
         & has_valid_type_value(&input)
-        & has_valid_number_value(&input);
+        & has_valid_number_value(&input)
 }
 
 fn has_required_keys(input: &HashMap<&str, &str>) -> bool {
     let keys: HashSet<&str> = input.keys().copied().collect();
     let required_keys = HashSet::from(["ID", "Number", "Type", "Description"]);
-    return required_keys == keys;
+    required_keys == keys
 }
 
 fn parse_type_value(
@@ -82,6 +118,7 @@ fn has_valid_number_value(input: &HashMap<&str, &str>) -> bool {
 }
 
 #[cfg(test)]
+#[allow(non_snake_case, clippy::bool_assert_comparison)]
 mod tests {
 
     use super::*;
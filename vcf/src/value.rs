@@ -0,0 +1,323 @@
+use std::fmt;
+
+use crate::{DataType, Definitions, NumberField, Record};
+
+/// An INFO or FORMAT value, typed by its header definition.
+///
+/// Fields with a single value (`Number=1`) are scalars and the others are arrays, whose
+/// elements may be missing. Values that do not match their definition, and those of fields
+/// the header does not define, are kept as strings. Characters are strings of one character.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(untagged)
+)]
+pub enum Value {
+    Missing,
+    Flag(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// Type the value of an INFO or FORMAT field. Fields without a value are flags.
+    pub fn parse(value: Option<&str>, data_type: Option<DataType>) -> Self {
+        let (value, data_type) = match (value, data_type) {
+            (None, _) | (Some(_), Some(DataType::Flag)) => return Value::Flag(true),
+            (Some("."), _) => return Value::Missing,
+            (Some(value), None) => return Value::String(value.to_string()),
+            (Some(value), Some(data_type)) => (value, data_type),
+        };
+        match data_type.number() {
+            NumberField::Number(0 | 1) => Self::scalar(value, data_type),
+            _ => Value::Array(
+                value
+                    .split(',')
+                    .map(|value| Self::scalar(value, data_type))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn scalar(value: &str, data_type: DataType) -> Self {
+        let typed = match (value, data_type) {
+            (".", _) => Some(Value::Missing),
+            (_, DataType::Integer(_)) => value.parse().ok().map(Value::Integer),
+            (_, DataType::Float(_)) => value.parse().ok().map(Value::Float),
+            _ => None,
+        };
+        typed.unwrap_or_else(|| Value::String(value.to_string()))
+    }
+}
+
+impl fmt::Display for Value {
+    /// Write the value as it appears in a VCF. Flags have no text of their own.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Missing => write!(f, "."),
+            Value::Flag(_) => Ok(()),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Keys with values, in the order they appear in a record. Serialized as a map.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Fields<V>(pub Vec<(String, V)>);
+
+impl<V> Fields<V> {
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+/// A record with its INFO and FORMAT values typed by the header, and its samples named.
+///
+/// This is the form in which records are serialized:
+///
+/// ```
+/// use vcf::vcf::{Reader, VCFError};
+/// use vcf::{TypedRecord, Value};
+///
+/// let source = b"##fileformat=VCFv4.3\
+///     \n##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\
+///     \n##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">\
+///     \n##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\
+///     \n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\
+///     \n1\t100\t.\tA\tG,T\t50\tPASS\tDP=12;AF=0.25,.\tGT\t0/1\n";
+/// let mut reader = Reader::new(&source[..])?;
+/// let definitions = reader.header().definitions();
+/// let record = reader.read_record()?.unwrap();
+///
+/// let typed = TypedRecord::new(&record, &definitions, &reader.header().samples);
+/// assert_eq!(typed.info.get("DP"), Some(&Value::Integer(12)));
+/// assert_eq!(typed.info.get("AF"), Some(&Value::Array(vec![Value::Float(0.25), Value::Missing])));
+/// assert_eq!(typed.samples.get("A").unwrap().get("GT"), Some(&Value::String("0/1".to_string())));
+/// assert_eq!(typed.to_record(), record);
+///# Ok::<(), VCFError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypedRecord {
+    pub chrom: String,
+    pub pos: u64,
+    pub id: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(rename = "ref"))]
+    pub reference: String,
+    pub alt: Vec<String>,
+    pub qual: Option<f64>,
+    pub filter: Vec<String>,
+    pub info: Fields<Value>,
+    pub samples: Fields<Fields<Value>>,
+}
+
+impl TypedRecord {
+    /// Type the values of `record` with the definitions of its header, naming its samples with
+    /// `samples`.
+    pub fn new(record: &Record, definitions: &Definitions, samples: &[String]) -> Self {
+        let info = record
+            .info
+            .iter()
+            .map(|(key, value)| {
+                let data_type = definitions.info.get(key).map(|d| d.fieldtype);
+                (key.clone(), Value::parse(value.as_deref(), data_type))
+            })
+            .collect();
+        let samples = samples
+            .iter()
+            .zip(&record.samples)
+            .map(|(name, values)| {
+                let fields = record
+                    .format
+                    .iter()
+                    .enumerate()
+                    .map(|(i, key)| {
+                        let data_type = definitions.format.get(key).map(|d| d.fieldtype);
+                        let value = values.get(i).map(String::as_str).unwrap_or(".");
+                        (key.clone(), Value::parse(Some(value), data_type))
+                    })
+                    .collect();
+                (name.clone(), Fields(fields))
+            })
+            .collect();
+        Self {
+            chrom: record.chrom.clone(),
+            pos: record.pos,
+            id: record.id.clone(),
+            reference: record.reference.clone(),
+            alt: record.alt.clone(),
+            qual: record.qual,
+            filter: record.filter.clone(),
+            info: Fields(info),
+            samples: Fields(samples),
+        }
+    }
+
+    /// The record with its values written back as text. FORMAT keys are taken from the first
+    /// sample, and flags that are false are left out.
+    pub fn to_record(&self) -> Record {
+        let info = self
+            .info
+            .0
+            .iter()
+            .filter(|(_, value)| *value != Value::Flag(false))
+            .map(|(key, value)| match value {
+                Value::Flag(_) => (key.clone(), None),
+                value => (key.clone(), Some(value.to_string())),
+            })
+            .collect();
+        let format: Vec<String> = match self.samples.0.first() {
+            Some((_, fields)) => fields.0.iter().map(|(key, _)| key.clone()).collect(),
+            None => Vec::new(),
+        };
+        let samples = self
+            .samples
+            .0
+            .iter()
+            .map(|(_, fields)| {
+                format
+                    .iter()
+                    .map(|key| match fields.get(key) {
+                        Some(value) => value.to_string(),
+                        None => ".".to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Record {
+            chrom: self.chrom.clone(),
+            pos: self.pos,
+            id: self.id.clone(),
+            reference: self.reference.clone(),
+            alt: self.alt.clone(),
+            qual: self.qual,
+            qual_text: None,
+            filter: self.filter.clone(),
+            info,
+            format,
+            samples,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    use serde::de::{MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Fields;
+
+    impl<V: Serialize> Serialize for Fields<V> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.0.len()))?;
+            for (key, value) in &self.0 {
+                map.serialize_entry(key, value)?;
+            }
+            map.end()
+        }
+    }
+
+    struct FieldsVisitor<V>(PhantomData<V>);
+
+    impl<'de, V: Deserialize<'de>> Visitor<'de> for FieldsVisitor<V> {
+        type Value = Fields<V>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a map")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+            let mut fields = Vec::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some(entry) = access.next_entry()? {
+                fields.push(entry);
+            }
+            Ok(Fields(fields))
+        }
+    }
+
+    impl<'de, V: Deserialize<'de>> Deserialize<'de> for Fields<V> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_map(FieldsVisitor(PhantomData))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::Reader;
+
+    const SOURCE: &str = "##fileformat=VCFv4.3
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">
+##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP\">
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">
+##FORMAT=<ID=AD,Number=R,Type=Integer,Description=\"Allele depths\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB
+1\t100\trs1\tA\tG\t50\tPASS\tDP=x;AF=0.5;DB;XX=1\tGT:AD\t0/1:5,.\t./.:.
+";
+
+    fn typed() -> (Record, TypedRecord) {
+        let mut reader = Reader::new(SOURCE.as_bytes()).unwrap();
+        let definitions = reader.header().definitions();
+        let record = reader.read_record().unwrap().unwrap();
+        let typed = TypedRecord::new(&record, &definitions, &reader.header().samples);
+        (record, typed)
+    }
+
+    #[test]
+    fn types_values_by_definition() {
+        let (record, typed) = typed();
+        assert_eq!(
+            typed.info.0,
+            vec![
+                ("DP".to_string(), Value::String("x".to_string())),
+                ("AF".to_string(), Value::Array(vec![Value::Float(0.5)])),
+                ("DB".to_string(), Value::Flag(true)),
+                ("XX".to_string(), Value::String("1".to_string())),
+            ]
+        );
+        let sample = typed.samples.get("A").unwrap();
+        assert_eq!(
+            sample.get("AD"),
+            Some(&Value::Array(vec![Value::Integer(5), Value::Missing]))
+        );
+        assert_eq!(
+            typed.samples.get("B").unwrap().get("AD"),
+            Some(&Value::Missing)
+        );
+        assert_eq!(typed.to_record(), record);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_to_json() {
+        let (record, typed) = typed();
+        let json = serde_json::to_string(&typed).unwrap();
+        assert_eq!(
+            json,
+            r#"{"chrom":"1","pos":100,"id":["rs1"],"ref":"A","alt":["G"],"qual":50.0,"filter":["PASS"],"info":{"DP":"x","AF":[0.5],"DB":true,"XX":"1"},"samples":{"A":{"GT":"0/1","AD":[5,null]},"B":{"GT":"./.","AD":null}}}"#
+        );
+        let parsed: TypedRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, typed);
+        assert_eq!(parsed.to_record(), record);
+    }
+}
@@ -1,20 +1,207 @@
+use std::collections::HashSet;
+use std::fmt;
 use std::io;
 use std::io::BufRead;
-use crate::Header;
-use crate::HeaderValue::Flat;
+use crate::{Definitions, Header, InfoFormat, LazyRecord, Record};
+use crate::HeaderValue::{Flat, Nested};
 use crate::validate_fileformat::is_valid_file_format;
 use crate::parse;
+use crate::samples::SampleSelection;
 
+/// The file-level part of a VCF: everything up to and including the `#CHROM` line.
+///
+/// Meta-information lines other than `##fileformat` are kept verbatim in `meta`, so that they
+/// are written back out unchanged. Use [`VCF::headers`] to get them as parsed [`Header`]s.
+///
+/// With the `serde` feature, `meta` is serialized as parsed [`Header`]s, and lines that do not
+/// parse as strings.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VCF {
     pub file_format: String,
+    #[cfg_attr(feature = "serde", serde(with = "meta_lines"))]
+    pub meta: Vec<String>,
+    pub samples: Vec<String>,
+}
+
+impl VCF {
+    /// The meta-information lines, parsed.
+    pub fn headers(&self) -> impl Iterator<Item = Header<'_>> {
+        self.meta.iter().filter_map(|line| Header::parse(line).ok())
+    }
+
+    /// The IDs of the `##contig` lines, in header order.
+    pub fn contigs(&self) -> Vec<String> {
+        self.ids("contig")
+    }
+
+    /// The IDs of the symbolic alleles defined by `##ALT` lines.
+    pub fn alt_ids(&self) -> HashSet<String> {
+        self.ids("ALT").into_iter().collect()
+    }
+
+    fn ids(&self, key: &str) -> Vec<String> {
+        self.headers()
+            .filter(|header| header.key == key)
+            .filter_map(|header| match header.value {
+                Nested(fields) => fields.get("ID").map(|id| id.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The `##INFO` and `##FORMAT` definitions. Lines that are not valid definitions are skipped.
+    pub fn definitions(&self) -> Definitions {
+        let mut definitions = Definitions::default();
+        for header in self.headers() {
+            let map = match header.key {
+                "INFO" => &mut definitions.info,
+                "FORMAT" => &mut definitions.format,
+                _ => continue,
+            };
+            if let Nested(fields) = &header.value {
+                if let (Some(id), Ok(definition)) = (fields.get("ID"), InfoFormat::parse(fields)) {
+                    map.insert(id.to_string(), definition);
+                }
+            }
+        }
+        definitions
+    }
+
+    /// Add a meta-information line, replacing the line with the same key and `ID` if there is
+    /// one. New lines go after the last line with the same key, or at the end.
+    pub fn set_header(&mut self, header: &Header) {
+        let line = header.to_string();
+        let id = header.id();
+        let mut last_with_key = None;
+        for (index, existing) in self.meta.iter().enumerate() {
+            let Ok(existing) = Header::parse(existing) else {
+                continue;
+            };
+            if existing.key != header.key {
+                continue;
+            }
+            if id.is_some() && existing.id() == id || id.is_none() && existing == *header {
+                self.meta[index] = line;
+                return;
+            }
+            last_with_key = Some(index);
+        }
+        match last_with_key {
+            Some(index) => self.meta.insert(index + 1, line),
+            None => self.meta.push(line),
+        }
+    }
+
+    /// Remove the meta-information line with this key and `ID`, returning whether there was one.
+    pub fn remove_header(&mut self, key: &str, id: &str) -> bool {
+        let length = self.meta.len();
+        self.meta.retain(|line| {
+            !matches!(Header::parse(line), Ok(header) if header.key == key && header.id() == Some(id))
+        });
+        self.meta.len() < length
+    }
+
+    /// Change the `ID` of the meta-information line with this key and `ID`, in place and leaving
+    /// its other fields as they were written, returning whether there was one. A line that
+    /// already had the new `ID` is removed.
+    pub fn rename_header(&mut self, key: &str, id: &str, new_id: &str) -> bool {
+        let matching = |line: &String, id: &str| {
+            matches!(Header::parse(line), Ok(header) if header.key == key && header.id() == Some(id))
+        };
+        if !self.meta.iter().any(|line| matching(line, id)) {
+            return false;
+        }
+        if id != new_id {
+            self.remove_header(key, new_id);
+        }
+        for line in self.meta.iter_mut().filter(|line| matching(line, id)) {
+            if let Some(renamed) = crate::parse::replace_id(line, new_id) {
+                *line = renamed;
+            }
+        }
+        true
+    }
+}
+
+impl fmt::Display for VCF {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "##fileformat={}", self.file_format)?;
+        for line in &self.meta {
+            writeln!(f, "{}", line)?;
+        }
+        write!(f, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+        if !self.samples.is_empty() {
+            write!(f, "\tFORMAT\t{}", self.samples.join("\t"))?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod meta_lines {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{Header, HeaderLine};
+
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Line<'a> {
+        Parsed(Header<'a>),
+        Raw(&'a str),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OwnedLine {
+        Parsed(HeaderLine),
+        Raw(String),
+    }
+
+    pub fn serialize<S: Serializer>(meta: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(meta.iter().map(|line| match Header::parse(line) {
+            Ok(header) => Line::Parsed(header),
+            Err(_) => Line::Raw(line),
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+        let lines = Vec::<OwnedLine>::deserialize(deserializer)?;
+        Ok(lines
+            .into_iter()
+            .map(|line| match line {
+                OwnedLine::Parsed(header) => header.to_string(),
+                OwnedLine::Raw(line) => line,
+            })
+            .collect())
+    }
 }
 
 #[derive(Debug)]
 pub enum VCFError {
     ParseError,
     IoError(io::Error),
+    ReferenceError(String),
+    InvalidRecord(String),
+    InvalidHeader(String),
+    InvalidExpression(String),
 }
 
+impl fmt::Display for VCFError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VCFError::ParseError => write!(f, "invalid VCF"),
+            VCFError::IoError(error) => write!(f, "{}", error),
+            VCFError::ReferenceError(message) => write!(f, "reference sequence: {}", message),
+            VCFError::InvalidRecord(message) => write!(f, "invalid record: {}", message),
+            VCFError::InvalidHeader(message) => write!(f, "invalid header: {}", message),
+            VCFError::InvalidExpression(message) => write!(f, "invalid expression: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for VCFError {}
+
 impl From<io::Error> for VCFError {
     fn from(error: io::Error) -> Self {
         VCFError::IoError(error)
@@ -22,7 +209,7 @@ impl From<io::Error> for VCFError {
 }
 
 impl From<parse::ParseError> for VCFError {
-    fn from(error: parse::ParseError) -> Self {
+    fn from(_error: parse::ParseError) -> Self {
         VCFError::ParseError
     }
 }
@@ -102,14 +289,263 @@ impl From<parse::ParseError> for VCFError {
 /// };
 /// ```
 pub fn parse_vcf(source: impl BufRead) ->  Result<VCF, VCFError> {
-    let first_line = source.lines().next().ok_or(VCFError::ParseError)??;
-    let parsed = Header::parse(&first_line)?;
-    if is_valid_file_format(&parsed) {
-        match parsed.value {
-            Flat(s) => Ok(VCF {file_format: s.to_string()}),
-            _ => panic!(),
+    Ok(Reader::new(source)?.vcf)
+}
+
+/// Streams the records of a VCF file after reading its header.
+///
+/// ```
+/// use vcf::vcf::Reader;
+/// let source = b"##fileformat=VCFv4.4\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n1\t100\t.\tA\tG\t.\tPASS\t.\n";
+///# use vcf::vcf::VCFError;
+/// let mut reader = Reader::new(&source[..])?;
+/// let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(records[0].pos, 100);
+///# Ok::<(), VCFError>(())
+/// ```
+pub struct Reader<R> {
+    inner: R,
+    vcf: VCF,
+    line: String,
+    /// The sample columns to keep, when a selection has been made.
+    samples: Option<Vec<usize>>,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(mut inner: R) -> Result<Self, VCFError> {
+        let mut line = String::new();
+        let mut lines = HeaderLines::default();
+        let vcf = loop {
+            line.clear();
+            if inner.read_line(&mut line)? == 0 {
+                return Err(VCFError::ParseError);
+            }
+            if let Some(vcf) = lines.push(&line)? {
+                break vcf;
+            }
+        };
+        Ok(Self { inner, vcf, line, samples: None })
+    }
+
+    pub fn header(&self) -> &VCF {
+        &self.vcf
+    }
+
+    pub fn into_header(self) -> VCF {
+        self.vcf
+    }
+
+    /// The input, positioned at the start of the next record.
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// The header, the input positioned at the first data line, and the selected sample columns.
+    pub(crate) fn into_parts(self) -> (VCF, R, Option<Vec<usize>>) {
+        (self.vcf, self.inner, self.samples)
+    }
+
+    /// Keep only the selected samples, in the order of the selection, in the header and in the
+    /// records read from now on. Selections made one after the other apply in turn.
+    pub fn select_samples(&mut self, selection: &SampleSelection) -> Result<(), VCFError> {
+        let indices = selection.indices(&self.vcf.samples)?;
+        self.vcf.samples = indices.iter().map(|&i| self.vcf.samples[i].clone()).collect();
+        self.samples = Some(match &self.samples {
+            Some(previous) => indices.iter().map(|&i| previous[i]).collect(),
+            None => indices,
+        });
+        Ok(())
+    }
+
+    /// Read the next record, or `None` at the end of the input.
+    pub fn read_record(&mut self) -> Result<Option<Record>, VCFError> {
+        if self.read_line()?.is_none() {
+            return Ok(None);
+        }
+        let line = self.line.trim_end_matches(['\n', '\r']);
+        Ok(Some(Record::parse_samples(line, self.samples.as_deref())?))
+    }
+
+    /// Read the next record without decoding it, or `None` at the end of the input.
+    ///
+    /// The record borrows the reader's line buffer, which is reused from one record to the
+    /// next, so reading does not allocate once the buffer has grown to the longest line.
+    ///
+    /// ```
+    /// use vcf::vcf::{Reader, VCFError};
+    ///
+    /// let source = b"##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+    /// 1\t100\t.\tA\tG\t.\t.\tDP=3\n1\t200\t.\tC\tT\t.\t.\tDP=7\n";
+    /// let mut reader = Reader::new(&source[..])?;
+    /// let mut depths = Vec::new();
+    /// while let Some(record) = reader.read_lazy()? {
+    ///     depths.push(record.info("DP").flatten().unwrap().parse::<u32>().unwrap());
+    /// }
+    /// assert_eq!(depths, [3, 7]);
+    ///# Ok::<(), VCFError>(())
+    /// ```
+    pub fn read_lazy(&mut self) -> Result<Option<LazyRecord<'_>>, VCFError> {
+        if self.read_line()?.is_none() {
+            return Ok(None);
+        }
+        let line = self.line.trim_end_matches(['\n', '\r']);
+        Ok(Some(LazyRecord::parse(line, self.samples.as_deref())?))
+    }
+
+    /// Read the next data line without parsing it, or `None` at the end of the input.
+    ///
+    /// Blank lines are skipped and the line ending is removed. The line keeps all its sample
+    /// columns, whatever samples have been selected.
+    pub fn read_line(&mut self) -> Result<Option<&str>, VCFError> {
+        loop {
+            self.line.clear();
+            if self.inner.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            if data_line(&self.line).is_some() {
+                return Ok(data_line(&self.line));
+            }
+        }
+    }
+
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records { reader: self }
+    }
+}
+
+/// Builds the header of a VCF from its lines, for readers of any kind.
+#[derive(Default)]
+pub(crate) struct HeaderLines {
+    vcf: Option<VCF>,
+}
+
+impl HeaderLines {
+    /// Take the next header line, returning the header once its `#CHROM` line is reached.
+    pub(crate) fn push(&mut self, line: &str) -> Result<Option<VCF>, VCFError> {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        let Some(vcf) = &mut self.vcf else {
+            let parsed = Header::parse(trimmed)?;
+            let file_format = match parsed.value {
+                Flat(s) if is_valid_file_format(&parsed) => s.to_string(),
+                _ => return Err(VCFError::ParseError),
+            };
+            self.vcf = Some(VCF { file_format, ..VCF::default() });
+            return Ok(None);
+        };
+        if trimmed.starts_with("##") {
+            Header::parse(trimmed)?;
+            vcf.meta.push(trimmed.to_string());
+            Ok(None)
+        } else if trimmed.starts_with("#CHROM") {
+            vcf.samples = trimmed.split('\t').skip(9).map(str::to_string).collect();
+            Ok(self.vcf.take())
+        } else {
+            Err(VCFError::ParseError)
         }
-    } else {
-        Err(VCFError::ParseError)
+    }
+}
+
+/// A data line without its line ending, or `None` for a blank line, which readers skip.
+pub(crate) fn data_line(line: &str) -> Option<&str> {
+    match line.trim().is_empty() {
+        true => None,
+        false => Some(line.trim_end_matches(['\n', '\r'])),
+    }
+}
+
+pub struct Records<'r, R> {
+    reader: &'r mut Reader<R>,
+}
+
+impl<R: BufRead> Iterator for Records<'_, R> {
+    type Item = Result<Record, VCFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = b"##fileformat=VCFv4.3\n\
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total Depth\">\n\
+##ALT=<ID=NON_REF,Description=\"Any other allele\">\n\
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\n\
+1\t100\t.\tA\tG\t50\tPASS\tDP=3\tGT\t0/1\t1/1\n";
+
+    #[test]
+    fn reads_header_and_records() {
+        let mut reader = Reader::new(SOURCE).unwrap();
+        assert_eq!(reader.header().samples, vec!["S1", "S2"]);
+        assert!(reader.header().alt_ids().contains("NON_REF"));
+        assert_eq!(reader.header().headers().next().unwrap().key, "INFO");
+        let definitions = reader.header().definitions();
+        assert_eq!(
+            definitions.info["DP"].fieldtype,
+            crate::DataType::Integer(crate::NumberField::Number(1))
+        );
+        let records = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].samples[1], vec!["1/1"]);
+    }
+
+    #[test]
+    fn sets_header_lines() {
+        let mut header = Reader::new(SOURCE).unwrap().into_header();
+        let line = "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">";
+        header.set_header(&Header::parse(line).unwrap());
+        assert_eq!(header.meta[1], line);
+
+        let line = "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Read depth\">";
+        header.set_header(&Header::parse(line).unwrap());
+        assert_eq!(header.meta[0], line);
+        assert_eq!(header.meta.len(), 3);
+
+        let line = "##FILTER=<ID=q10,Description=\"Quality below 10\">";
+        header.set_header(&Header::parse(line).unwrap());
+        header.set_header(&Header::parse(line).unwrap());
+        assert_eq!(header.meta.len(), 4);
+        assert_eq!(header.meta[3], line);
+
+        assert!(header.rename_header("INFO", "DP", "AF"));
+        assert_eq!(header.meta.len(), 3);
+        assert_eq!(header.meta[0], "##INFO=<ID=AF,Number=1,Type=Integer,Description=\"Read depth\">");
+        assert!(!header.rename_header("INFO", "DP", "AF"));
+        assert!(header.remove_header("FILTER", "q10"));
+        assert!(!header.remove_header("FILTER", "q10"));
+        assert_eq!(header.meta.len(), 2);
+
+        let line = "##INFO=<Type=Float,ID=AF,Number=A,Source=gnomad,Description=\"Frequency, \\\"AF\\\"\">";
+        header.meta[0] = line.to_string();
+        assert!(header.rename_header("INFO", "AF", "gnomAD_AF"));
+        assert_eq!(header.meta[0], line.replace("ID=AF", "ID=gnomAD_AF"));
+    }
+
+    #[test]
+    fn header_round_trips_through_display() {
+        let reader = Reader::new(SOURCE).unwrap();
+        let text = reader.header().to_string();
+        assert!(SOURCE.starts_with(text.as_bytes()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_meta_lines_as_headers() {
+        let mut header = Reader::new(SOURCE).unwrap().into_header();
+        header.meta.push("##unstructured".to_string());
+        let json = serde_json::to_value(&header).unwrap();
+        assert_eq!(json["meta"][0]["key"], "INFO");
+        assert_eq!(json["meta"][0]["value"]["Description"], "Total Depth");
+        assert_eq!(json["meta"][2], "##unstructured");
+        let parsed: VCF = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, header);
+    }
+
+    #[test]
+    fn missing_column_header_is_an_error() {
+        let source = b"##fileformat=VCFv4.3\n1\t100\t.\tA\tG\t50\tPASS\t.\n";
+        assert!(Reader::new(&source[..]).is_err());
     }
 }